@@ -44,6 +44,7 @@ fn bench_token_bucket(c: &mut Criterion) {
     use std::sync::atomic::{AtomicU64, Ordering};
     use std::time::Instant;
 
+    #[allow(dead_code)]
     struct SimpleBucket {
         tokens: AtomicU64,
         last_refill: std::sync::Mutex<Instant>,
@@ -5,28 +5,34 @@ use axum::{
     Router,
     extract::Request,
     middleware::{from_fn, from_fn_with_state},
-    routing::{any, get},
+    routing::{any, get, post},
 };
-use axum_client_ip::ClientIpSource;
 use http::StatusCode;
 use tower_http::trace::TraceLayer;
 use uuid::Uuid;
 
 use crate::{
+    errors::AppError,
     middleware::{
         auth::auth::layer as auth_layer, cache::cache::layer as cache_layer,
         circuit_breaker::circuit_breaker::layer as circuit_breaker_layer,
+        client_ip::client_ip::layer as client_ip_layer,
+        hop_by_hop::hop_by_hop::layer as hop_by_hop_layer,
         rate_limiter::rate_limit::layer as ratelimiter_layer,
         request_id::request_id::layer as request_id_layer,
     },
     proxy::proxy_handler,
     state::AppState,
-    utils::metric_handler::metrics_handler,
+    utils::{
+        metric_handler::metrics_handler, revocation_handler::revocation_handler,
+        token_handler::token_handler,
+    },
 };
 
 pub const REQUEST_ID_HEADER: &str = "x-request-id";
 
-pub fn create_app(state: Arc<AppState>) -> Result<Router, Error> {
+// `serve_admin` is false when the admin endpoint has a listener of its own
+pub fn create_app(state: Arc<AppState>, serve_admin: bool) -> Result<Router, Error> {
     let proxy_router = Router::new()
         .route("/{*path}", any(proxy_handler))
         .route_layer(from_fn_with_state(state.clone(), circuit_breaker_layer))
@@ -36,12 +42,23 @@ pub fn create_app(state: Arc<AppState>) -> Result<Router, Error> {
 
     let prometheus_router = Router::new().route("/metrics", get(metrics_handler));
 
-    let router = Router::new()
+    let mut router = Router::new()
         .route("/health", get(|| async { (StatusCode::OK, "OK") }))
+        .route("/_gateway/token", post(token_handler));
+    if serve_admin {
+        router = router.merge(admin_router());
+    } else {
+        // Not proxied either, so the admin token never reaches an upstream
+        router = router.route(
+            "/_gateway/revocations",
+            any(|| async { AppError::RouteNotFound }),
+        );
+    }
+    let router = router
         .merge(proxy_router)
         .merge(prometheus_router)
-        .with_state(state)
-        .layer(ClientIpSource::ConnectInfo.into_extension());
+        .layer(from_fn_with_state(state.clone(), client_ip_layer))
+        .with_state(state);
 
     Ok(router
         .layer(
@@ -61,5 +78,17 @@ pub fn create_app(state: Arc<AppState>) -> Result<Router, Error> {
                 )
             }),
         )
-        .layer(from_fn(request_id_layer)))
+        .layer(from_fn(request_id_layer))
+        .layer(from_fn(hop_by_hop_layer)))
+}
+
+/// The admin endpoint on its own, for `server.admin_addr`.
+pub fn create_admin_app(state: Arc<AppState>) -> Router {
+    admin_router()
+        .with_state(state)
+        .layer(TraceLayer::new_for_http())
+}
+
+fn admin_router() -> Router<Arc<AppState>> {
+    Router::new().route("/_gateway/revocations", post(revocation_handler))
 }
@@ -1,7 +1,19 @@
-use std::{collections::HashMap, fs, path::Path, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    net::IpAddr,
+    path::Path,
+    sync::Arc,
+};
 
 use anyhow::{Error, Ok};
-use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use ipnet::IpNet;
+use jsonwebtoken::Algorithm;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::features::auth::{api_key, policy::PolicyCondition};
 
 #[derive(Debug, Deserialize)]
 pub struct GatewayConfig {
@@ -15,27 +27,803 @@ pub struct GatewayConfig {
 #[derive(Debug, Deserialize)]
 pub struct ServerConfig {
     pub addr: String,
+    // Allows several gateway processes to bind the same address (SO_REUSEPORT)
+    #[serde(default)]
+    pub reuse_port: bool,
+    // How long in-flight requests may take to finish once draining starts
+    #[serde(default = "default_drain_timeout")]
+    pub drain_timeout: String,
+    // Serves the admin endpoint here instead of on `addr`
+    pub admin_addr: Option<String>,
+    pub proxy_protocol: Option<ProxyProtocolConfig>,
+    #[serde(default)]
+    pub client_ip: ClientIpConfig,
+    #[serde(default)]
+    pub forwarded_headers: ForwardedHeadersConfig,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProxyProtocolConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // Only connections from these networks may carry a PROXY header
+    pub trusted_cidrs: Vec<IpNet>,
+}
+
+impl ProxyProtocolConfig {
+    pub fn is_trusted(&self, peer: &IpAddr) -> bool {
+        contains_ip(&self.trusted_cidrs, peer)
+    }
+}
+
+// Where the real client IP is read from when the peer is a trusted proxy
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum ClientIpSource {
+    #[default]
+    ConnectInfo,
+    XForwardedFor,
+    XRealIp,
+    CfConnectingIp,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ClientIpConfig {
+    #[serde(default)]
+    pub source: ClientIpSource,
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl ClientIpConfig {
+    pub fn is_trusted(&self, peer: &IpAddr) -> bool {
+        contains_ip(&self.trusted_proxies, peer)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ForwardedHeadersConfig {
+    // X-Forwarded-For / X-Forwarded-Proto / X-Forwarded-Host
+    #[serde(default = "default_true")]
+    pub x_forwarded: bool,
+    // RFC 7239 Forwarded
+    #[serde(default)]
+    pub forwarded: bool,
+}
+
+impl Default for ForwardedHeadersConfig {
+    fn default() -> Self {
+        Self {
+            x_forwarded: true,
+            forwarded: false,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+pub(crate) fn contains_ip(nets: &[IpNet], ip: &IpAddr) -> bool {
+    let ip = ip.to_canonical();
+    nets.iter().any(|net| net.contains(&ip))
+}
+
+fn default_drain_timeout() -> String {
+    "30s".to_string()
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct IdentityConfig {
     pub api_key_store_path: String,
+    // How `api_key_store_path` is stored
+    #[serde(default)]
+    pub api_key_store_backend: KeyStoreBackend,
+    // In-memory LRU in front of database backends
+    #[serde(default)]
+    pub api_key_cache: KeyCacheConfig,
+    // Defaults to HS256 tokens signed with JWT_SECRET
+    pub jwt: Option<JwtConfig>,
+    // Named issuers that routes can opt into with `auth.providers`
+    #[serde(default)]
+    pub jwt_providers: Vec<JwtProviderConfig>,
+    // htpasswd or YAML (.yaml/.yml) file of bcrypt/argon2 hashed users
+    pub basic_auth_path: Option<String>,
+    // Denylist of JWT `jti`s and subjects, hot reloaded
+    pub revocation_list_path: Option<String>,
+    // Revocations made through the admin endpoint are dropped this long after
+    // the tokens they cover were issued; at least the longest JWT lifetime
+    #[serde(default = "default_runtime_revocation_ttl")]
+    pub runtime_revocation_ttl: String,
+    // Serves `POST /_gateway/token`, exchanging API keys for JWTs
+    pub token_issuer: Option<TokenIssuerConfig>,
+    // Slows down and locks out clients that keep failing to authenticate
+    pub lockout: Option<LockoutConfig>,
+}
+
+fn default_runtime_revocation_ttl() -> String {
+    "24h".to_string()
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LockoutConfig {
+    // Failures tolerated per client IP, and per presented credential (API key
+    // ID or Basic username), before a lockout
+    #[serde(default = "default_lockout_ip_failures")]
+    pub ip_failures: u64,
+    #[serde(default = "default_lockout_credential_failures")]
+    pub credential_failures: u64,
+    // Failures are forgotten gradually over this window
+    #[serde(default = "default_lockout_window")]
+    pub window: String,
+    #[serde(default = "default_lockout_duration")]
+    pub duration: String,
+    // Added to failed responses for each recent failure, up to `max_delay`
+    #[serde(default = "default_lockout_delay")]
+    pub delay: String,
+    #[serde(default = "default_lockout_max_delay")]
+    pub max_delay: String,
+}
+
+fn default_lockout_ip_failures() -> u64 {
+    20
+}
+
+fn default_lockout_credential_failures() -> u64 {
+    5
+}
+
+fn default_lockout_window() -> String {
+    "15m".to_string()
+}
+
+fn default_lockout_duration() -> String {
+    "15m".to_string()
+}
+
+fn default_lockout_delay() -> String {
+    "1s".to_string()
+}
+
+fn default_lockout_max_delay() -> String {
+    "5s".to_string()
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum KeyStoreBackend {
+    // Read into memory and hot reloaded
+    #[default]
+    Yaml,
+    // Embedded database, for large key sets
+    Sqlite,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct KeyCacheConfig {
+    #[serde(default = "default_key_cache_capacity")]
+    pub capacity: u64,
+    // Bounds how long changes made by other processes take to apply
+    #[serde(default = "default_key_cache_ttl")]
+    pub ttl: String,
+}
+
+impl Default for KeyCacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: default_key_cache_capacity(),
+            ttl: default_key_cache_ttl(),
+        }
+    }
+}
+
+fn default_key_cache_capacity() -> u64 {
+    10_000
+}
+
+fn default_key_cache_ttl() -> String {
+    "30s".to_string()
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TokenIssuerConfig {
+    // HS* algorithms sign with JWT_SECRET, so the default provider accepts the
+    // tokens; others need `private_key_path` and a matching `identity.jwt` key
+    #[serde(default = "default_internal_jwt_algorithm")]
+    pub algorithm: Algorithm,
+    pub private_key_path: Option<String>,
+    // `kid` header, for verifiers that select keys from a JWKS
+    pub kid: Option<String>,
+    #[serde(default = "default_internal_jwt_issuer")]
+    pub issuer: String,
+    pub audience: Option<String>,
+    #[serde(default = "default_access_token_ttl")]
+    pub access_token_ttl: String,
+    #[serde(default = "default_refresh_token_ttl")]
+    pub refresh_token_ttl: String,
+    // Refresh tokens are single use and kept in memory
+    #[serde(default = "default_true")]
+    pub refresh_tokens: bool,
+}
+
+fn default_access_token_ttl() -> String {
+    "15m".to_string()
+}
+
+fn default_refresh_token_ttl() -> String {
+    "720h".to_string()
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct JwtProviderConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub keys: JwtConfig,
+    #[serde(flatten)]
+    pub validation: JwtValidationConfig,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct JwtConfig {
+    #[serde(default = "default_jwt_algorithms")]
+    pub algorithms: Vec<Algorithm>,
+    // PEM encoded RSA, EC or Ed25519 public key
+    pub public_key_path: Option<String>,
+    pub jwks: Option<JwksConfig>,
+}
+
+fn default_jwt_algorithms() -> Vec<Algorithm> {
+    vec![Algorithm::HS256]
+}
+
+// Exactly one of `url` or `path` must be set
+#[derive(Debug, Deserialize, Clone)]
+pub struct JwksConfig {
+    pub url: Option<String>,
+    pub path: Option<String>,
+    #[serde(default = "default_jwks_refresh_interval")]
+    pub refresh_interval: String,
+    // Lower bound between refetches triggered by an unknown `kid`
+    #[serde(default = "default_jwks_min_refetch_interval")]
+    pub min_refetch_interval: String,
+    // Upper bound for a single fetch from `url`
+    #[serde(default = "default_jwks_fetch_timeout")]
+    pub fetch_timeout: String,
+}
+
+fn default_jwks_refresh_interval() -> String {
+    "10m".to_string()
+}
+
+fn default_jwks_min_refetch_interval() -> String {
+    "30s".to_string()
+}
+
+fn default_jwks_fetch_timeout() -> String {
+    "5s".to_string()
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub enum AuthType {
     Jwt,
     ApiKey,
+    // Opaque tokens checked against an OAuth2 introspection endpoint (RFC 7662)
+    Introspection,
+    // Browser login through an OpenID Connect provider with gateway sessions
+    Oidc,
+    // Decision delegated to an HTTP authorization service (forward auth)
+    External,
+    // HTTP Basic against `identity.basic_auth_path`
+    Basic,
+    // HMAC request signatures (webhooks), with secrets from the key store
+    Signature,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct AuthConfig {
     #[serde(rename = "type")]
     pub auth_type: AuthType,
-    pub roles: Option<Vec<String>>,
+    // A plain list requires every role; see `RoleExpr` for any_of/none_of
+    pub roles: Option<RoleExpr>,
+    // Extra requirements per HTTP method, on top of `roles`
+    #[serde(default)]
+    pub method_roles: HashMap<String, RoleExpr>,
+    // Attribute-based rules checked after authentication and roles
+    #[serde(default)]
+    pub policies: Vec<PolicyRule>,
+    // Claim validation and mapping for `Jwt` routes, overriding the provider's
+    pub jwt: Option<JwtValidationOverrides>,
+    // Names from `identity.jwt_providers`; the default provider when unset
+    pub providers: Option<Vec<String>>,
+    // Required for `Introspection` routes
+    pub introspection: Option<IntrospectionConfig>,
+    // Required for `Oidc` routes
+    pub oidc: Option<OidcConfig>,
+    // Required for `External` routes
+    pub external: Option<ExternalAuthConfig>,
+    // Realm sent in the `WWW-Authenticate` challenge of `Basic` routes
+    pub realm: Option<String>,
+    // Required for `Signature` routes
+    pub signature: Option<SignatureConfig>,
+    // Where `Jwt`, `ApiKey` and `Introspection` routes read the credential
+    // from, tried in order
+    #[serde(default = "default_credential_sources")]
+    pub credentials: Vec<CredentialSource>,
+    // Remove the credential before proxying; defaults to true for `ApiKey`
+    // routes, whose keys upstreams never need
+    pub strip_credential: Option<bool>,
+    // Identity headers (and optionally a gateway-signed JWT) sent upstream
+    pub propagate: Option<IdentityPropagationConfig>,
 }
 
+// Client-supplied copies of every header named here are removed first, so
+// upstreams can trust them.
 #[derive(Debug, Deserialize, Clone)]
+pub struct IdentityPropagationConfig {
+    #[serde(default = "default_propagated_subject_header")]
+    pub subject_header: String,
+    // Comma-separated roles
+    #[serde(default = "default_propagated_roles_header")]
+    pub roles_header: String,
+    // Header name to JSON pointer into the claims, e.g. X-Tenant: "/tenant"
+    #[serde(default)]
+    pub claims: HashMap<String, String>,
+    pub internal_jwt: Option<InternalJwtConfig>,
+}
+
+fn default_propagated_subject_header() -> String {
+    "x-user-id".to_string()
+}
+
+fn default_propagated_roles_header() -> String {
+    "x-user-roles".to_string()
+}
+
+// Short-lived token minted per request for zero-trust backends
+#[derive(Debug, Deserialize, Clone)]
+pub struct InternalJwtConfig {
+    #[serde(default = "default_internal_jwt_header")]
+    pub header: String,
+    #[serde(default = "default_internal_jwt_algorithm")]
+    pub algorithm: Algorithm,
+    // HMAC secret for HS* algorithms
+    #[serde(default = "default_internal_jwt_secret_env")]
+    pub secret_env: String,
+    // PEM encoded RSA, EC or Ed25519 private key for asymmetric algorithms
+    pub private_key_path: Option<String>,
+    #[serde(default = "default_internal_jwt_issuer")]
+    pub issuer: String,
+    pub audience: Option<String>,
+    #[serde(default = "default_internal_jwt_ttl")]
+    pub ttl: String,
+}
+
+fn default_internal_jwt_header() -> String {
+    "x-gateway-identity".to_string()
+}
+
+fn default_internal_jwt_algorithm() -> Algorithm {
+    Algorithm::HS256
+}
+
+fn default_internal_jwt_secret_env() -> String {
+    "GATEWAY_IDENTITY_SECRET".to_string()
+}
+
+fn default_internal_jwt_issuer() -> String {
+    "rustygw".to_string()
+}
+
+fn default_internal_jwt_ttl() -> String {
+    "60s".to_string()
+}
+
+impl AuthConfig {
+    pub fn strips_credential(&self) -> bool {
+        self.strip_credential
+            .unwrap_or(self.auth_type == AuthType::ApiKey)
+    }
+}
+
+// Role requirement evaluated against `Claims.roles`. Blocks nest, and every
+// block present in a map must hold:
+//
+//   roles:
+//     any_of: ["admin", { all_of: ["support", "billing"] }]
+//     none_of: ["suspended"]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum RoleExpr {
+    Role(String),
+    // Every entry must hold, as with the original role lists
+    All(Vec<RoleExpr>),
+    Block(RoleBlock),
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RoleBlock {
+    pub any_of: Option<Vec<RoleExpr>>,
+    pub all_of: Option<Vec<RoleExpr>>,
+    pub none_of: Option<Vec<RoleExpr>>,
+}
+
+// Every rule whose `methods` and `path` match the request must have all of its
+// `require` conditions hold, e.g.
+//
+//   - name: "own-orders"
+//     methods: ["GET"]
+//     path: "/api/orders/user/{user_id}"
+//     require: ["path.user_id == claims.sub"]
+#[derive(Debug, Deserialize, Clone)]
+pub struct PolicyRule {
+    // Reported in the 403 when the rule denies a request
+    pub name: String,
+    // Any method when empty
+    #[serde(default)]
+    pub methods: Vec<String>,
+    // Full request path; `{name}` captures one segment as `path.name`
+    pub path: Option<String>,
+    pub require: Vec<PolicyCondition>,
+}
+
+// Written as `bearer`, `header:<name>`, `query:<name>` or `cookie:<name>`
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(try_from = "String")]
+pub enum CredentialSource {
+    // `Authorization: Bearer <credential>`
+    Bearer,
+    Header(String),
+    Query(String),
+    Cookie(String),
+}
+
+impl TryFrom<String> for CredentialSource {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value == "bearer" {
+            return std::result::Result::Ok(Self::Bearer);
+        }
+        let source = match value.split_once(':') {
+            Some(("header", name)) if !name.is_empty() => Self::Header(name.to_lowercase()),
+            Some(("query", name)) if !name.is_empty() => Self::Query(name.to_string()),
+            Some(("cookie", name)) if !name.is_empty() => Self::Cookie(name.to_string()),
+            _ => return Err(format!("Invalid credential source '{}'", value)),
+        };
+        std::result::Result::Ok(source)
+    }
+}
+
+fn default_credential_sources() -> Vec<CredentialSource> {
+    vec![CredentialSource::Bearer]
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct IntrospectionConfig {
+    pub endpoint: String,
+    pub client_id: String,
+    // Environment variable holding the client secret
+    #[serde(default = "default_introspection_secret_env")]
+    pub client_secret_env: String,
+    // Upper bound for caching active tokens; never beyond the token's `exp`
+    #[serde(default = "default_introspection_cache_ttl")]
+    pub cache_ttl: String,
+    // How long inactive tokens are remembered
+    #[serde(default = "default_introspection_negative_cache_ttl")]
+    pub negative_cache_ttl: String,
+    #[serde(default = "default_introspection_timeout")]
+    pub timeout: String,
+    // JSON pointers into the introspection response, as for JWT claims
+    #[serde(default = "default_subject_claim")]
+    pub subject_claim: String,
+    #[serde(default = "default_introspection_roles_claims")]
+    pub roles_claims: Vec<String>,
+}
+
+fn default_introspection_secret_env() -> String {
+    "INTROSPECTION_CLIENT_SECRET".to_string()
+}
+
+fn default_introspection_cache_ttl() -> String {
+    "5m".to_string()
+}
+
+fn default_introspection_negative_cache_ttl() -> String {
+    "30s".to_string()
+}
+
+fn default_introspection_timeout() -> String {
+    "5s".to_string()
+}
+
+fn default_introspection_roles_claims() -> Vec<String> {
+    vec!["/scope".to_string()]
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct OidcConfig {
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    // RP-initiated logout at the provider, if it supports it
+    pub end_session_endpoint: Option<String>,
+    pub client_id: String,
+    // Environment variable holding the client secret; public client when unset
+    #[serde(default = "default_oidc_secret_env")]
+    pub client_secret_env: String,
+    // Absolute callback URL registered with the provider. Its path must be
+    // under the route's path; the gateway answers it.
+    pub redirect_uri: String,
+    // Relative to the route's path
+    #[serde(default = "default_oidc_logout_path")]
+    pub logout_path: String,
+    pub post_logout_redirect_uri: Option<String>,
+    // Name from `identity.jwt_providers` that verifies ID tokens
+    pub jwt_provider: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    #[serde(default = "default_oidc_session_ttl")]
+    pub session_ttl: String,
+    #[serde(default = "default_oidc_cookie_name")]
+    pub cookie_name: String,
+    #[serde(default = "default_true")]
+    pub cookie_secure: bool,
+}
+
+fn default_oidc_secret_env() -> String {
+    "OIDC_CLIENT_SECRET".to_string()
+}
+
+fn default_oidc_logout_path() -> String {
+    "/oauth2/logout".to_string()
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec![
+        "openid".to_string(),
+        "profile".to_string(),
+        "email".to_string(),
+    ]
+}
+
+fn default_oidc_session_ttl() -> String {
+    "8h".to_string()
+}
+
+fn default_oidc_cookie_name() -> String {
+    "gw_session".to_string()
+}
+
+// GitHub style:  format: Plain, header: "X-Hub-Signature-256", prefix: "sha256="
+// Stripe style:  format: Stripe, header: "Stripe-Signature"
+#[derive(Debug, Deserialize, Clone)]
+pub struct SignatureConfig {
+    #[serde(default)]
+    pub format: SignatureFormat,
+    pub header: String,
+    // Stripped from `Plain` header values before decoding
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub algorithm: SignatureAlgorithm,
+    #[serde(default)]
+    pub encoding: SignatureEncoding,
+    // Key store entry holding the secret: fixed, or named by a request header
+    pub key_id: Option<String>,
+    pub key_id_header: Option<String>,
+    // Unix seconds; `Stripe` signatures carry their own `t=`
+    pub timestamp_header: Option<String>,
+    // Required and rejected when seen twice within the tolerance
+    pub nonce_header: Option<String>,
+    // What is signed, joined by `separator`; defaults to the body, or
+    // timestamp and body for `Stripe`
+    pub canonical: Option<Vec<CanonicalPart>>,
+    #[serde(default = "default_signature_separator")]
+    pub separator: String,
+    // Accepted clock difference for signed timestamps
+    #[serde(default = "default_signature_tolerance")]
+    pub tolerance: String,
+    #[serde(default = "default_signature_max_body_size")]
+    pub max_body_size: usize,
+}
+
+impl SignatureConfig {
+    /// What is signed, with the format's default filled in.
+    pub fn canonical_parts(&self) -> Vec<CanonicalPart> {
+        self.canonical.clone().unwrap_or_else(|| match self.format {
+            SignatureFormat::Plain => vec![CanonicalPart::Body],
+            SignatureFormat::Stripe => vec![CanonicalPart::Timestamp, CanonicalPart::Body],
+        })
+    }
+
+    /// The timestamp or nonce header when it is checked but not signed, which
+    /// would let a captured request be replayed with a fresh value.
+    pub fn unsigned_header(&self) -> Option<&str> {
+        let canonical = self.canonical_parts();
+        [
+            (&self.timestamp_header, CanonicalPart::Timestamp),
+            (&self.nonce_header, CanonicalPart::Nonce),
+        ]
+        .into_iter()
+        .find_map(|(header, part)| header.as_deref().filter(|_| !canonical.contains(&part)))
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum SignatureFormat {
+    // The header value is `prefix` followed by the encoded signature
+    #[default]
+    Plain,
+    // `t=<timestamp>,v1=<signature>[,v1=...]`
+    Stripe,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum SignatureAlgorithm {
+    #[default]
+    HmacSha256,
+    HmacSha512,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum SignatureEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum CanonicalPart {
+    Timestamp,
+    Nonce,
+    Method,
+    // Path and query as sent
+    Path,
+    Body,
+}
+
+fn default_signature_separator() -> String {
+    ".".to_string()
+}
+
+fn default_signature_tolerance() -> String {
+    "5m".to_string()
+}
+
+fn default_signature_max_body_size() -> usize {
+    1024 * 1024
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ExternalAuthConfig {
+    pub url: String,
+    // Client request headers sent along with the subrequest
+    #[serde(default = "default_external_forward_headers")]
+    pub forward_headers: Vec<String>,
+    // Authorization response headers copied onto the upstream request; client
+    // supplied copies are always removed
+    #[serde(default)]
+    pub upstream_headers: Vec<String>,
+    // Response headers that become the subject and comma-separated roles
+    // checked against `auth.roles`
+    #[serde(default = "default_external_subject_header")]
+    pub subject_header: String,
+    #[serde(default = "default_external_roles_header")]
+    pub roles_header: String,
+    #[serde(default = "default_external_timeout")]
+    pub timeout: String,
+    #[serde(default)]
+    pub on_error: ExternalAuthFailureMode,
+    // Decisions are not cached when unset
+    pub cache_ttl: Option<String>,
+}
+
+// What to do when the authorization service cannot be reached, times out or
+// answers with a 5xx
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum ExternalAuthFailureMode {
+    #[default]
+    Deny,
+    Allow,
+}
+
+fn default_external_forward_headers() -> Vec<String> {
+    vec!["authorization".to_string(), "cookie".to_string()]
+}
+
+fn default_external_subject_header() -> String {
+    "x-user-id".to_string()
+}
+
+fn default_external_roles_header() -> String {
+    "x-user-roles".to_string()
+}
+
+fn default_external_timeout() -> String {
+    "2s".to_string()
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct JwtValidationConfig {
+    // Accepted `iss` values; any issuer when empty
+    #[serde(default)]
+    pub issuers: Vec<String>,
+    // Accepted `aud` values; `aud` is not checked when empty
+    #[serde(default)]
+    pub audiences: Vec<String>,
+    // Clock skew tolerated for `exp` and `nbf`
+    #[serde(default = "default_jwt_leeway")]
+    pub leeway: String,
+    // JSON pointer to the subject, e.g. "/sub" or "/preferred_username"
+    #[serde(default = "default_subject_claim")]
+    pub subject_claim: String,
+    // JSON pointers to role claims, e.g. "/realm_access/roles" or "/scope".
+    // String values are split on whitespace.
+    #[serde(default = "default_roles_claims")]
+    pub roles_claims: Vec<String>,
+}
+
+impl Default for JwtValidationConfig {
+    fn default() -> Self {
+        Self {
+            issuers: Vec::new(),
+            audiences: Vec::new(),
+            leeway: default_jwt_leeway(),
+            subject_claim: default_subject_claim(),
+            roles_claims: default_roles_claims(),
+        }
+    }
+}
+
+// Route-level `jwt` block. Only the fields it sets replace the provider's, so
+// mapping claims differently keeps each provider's `iss`/`aud` checks.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct JwtValidationOverrides {
+    pub issuers: Option<Vec<String>>,
+    pub audiences: Option<Vec<String>>,
+    pub leeway: Option<String>,
+    pub subject_claim: Option<String>,
+    pub roles_claims: Option<Vec<String>>,
+}
+
+impl JwtValidationOverrides {
+    pub fn apply(&self, provider: &JwtValidationConfig) -> JwtValidationConfig {
+        JwtValidationConfig {
+            issuers: self
+                .issuers
+                .clone()
+                .unwrap_or_else(|| provider.issuers.clone()),
+            audiences: self
+                .audiences
+                .clone()
+                .unwrap_or_else(|| provider.audiences.clone()),
+            leeway: self
+                .leeway
+                .clone()
+                .unwrap_or_else(|| provider.leeway.clone()),
+            subject_claim: self
+                .subject_claim
+                .clone()
+                .unwrap_or_else(|| provider.subject_claim.clone()),
+            roles_claims: self
+                .roles_claims
+                .clone()
+                .unwrap_or_else(|| provider.roles_claims.clone()),
+        }
+    }
+}
+
+fn default_jwt_leeway() -> String {
+    "60s".to_string()
+}
+
+fn default_subject_claim() -> String {
+    "/sub".to_string()
+}
+
+fn default_roles_claims() -> Vec<String> {
+    vec!["/roles".to_string()]
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RateLimitConfig {
     pub requests: u64,
     pub period: String,
@@ -50,6 +838,32 @@ pub struct RouteConfig {
     pub rate_limit: Option<RateLimitConfig>,
     pub cache: Option<CacheConfig>,
     pub circuit_breaker: Option<CircuitBreakerConfig>,
+    // Forward the client's Host header instead of the upstream authority
+    #[serde(default)]
+    pub preserve_host: bool,
+    pub response_rewrite: Option<ResponseRewriteConfig>,
+}
+
+impl RouteConfig {
+    /// Where a request for `request_path` is forwarded: the destination plus
+    /// whatever follows the route's path.
+    pub fn upstream_url(&self, request_path: &str) -> String {
+        let rest = request_path.strip_prefix(&self.path).unwrap_or("");
+        format!("{}{}", self.destination, rest)
+    }
+}
+
+// Maps upstream URLs in redirect and cookie headers back to the public route
+#[derive(Debug, Deserialize, Clone)]
+pub struct ResponseRewriteConfig {
+    // Location and Content-Location
+    #[serde(default = "default_true")]
+    pub redirects: bool,
+    // Path attribute of Set-Cookie
+    #[serde(default = "default_true")]
+    pub cookie_path: bool,
+    // Replaces the Domain attribute of Set-Cookie; an empty string removes it
+    pub cookie_domain: Option<String>,
 }
 
 impl GatewayConfig {
@@ -70,40 +884,97 @@ impl GatewayConfig {
 
 //       API key store condig    //
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ApiKeyStore {
+    // Keyed by the key ID (`gw_<id>`) for hashed entries, or by the key
+    // itself for legacy plaintext entries
+    #[serde(serialize_with = "serialize_sorted")]
     pub keys: HashMap<String, ApiKeyDetails>,
+    // From `API_KEY_HMAC_SECRET`, for `hmac-sha256:` digests
+    #[serde(skip)]
+    pub hmac_secret: Option<Vec<u8>>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ApiKeyDetails {
+    // "sha256:<hex>" or "hmac-sha256:<hex>" digest of the full key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
     pub user_id: String,
     pub roles: Vec<String>,
-    #[serde(default = "default_status")]
-    pub status: String,
+    #[serde(default)]
+    pub status: KeyStatus,
+    // RFC 3339 timestamps bounding when the key is accepted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    // Route names, or request path patterns starting with '/' (a trailing
+    // `*` matches any suffix); empty allows every route
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_routes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_cidrs: Vec<IpNet>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_methods: Vec<String>,
+    // Replaces the route's per-IP limit with one shared by all users of the key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitConfig>,
+    // Shared HMAC secret for `Signature` routes that name this entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_secret: Option<String>,
 }
 
-fn default_status() -> String {
-    "active".to_string()
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyStatus {
+    #[default]
+    Active,
+    Revoked,
+    Suspended,
+}
+
+// Keeps the file stable across rewrites, so diffs only show changed entries
+fn serialize_sorted<S: serde::Serializer>(
+    keys: &HashMap<String, ApiKeyDetails>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    keys.iter()
+        .collect::<BTreeMap<_, _>>()
+        .serialize(serializer)
 }
 
 impl ApiKeyStore {
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, anyhow::Error> {
         let content = fs::read_to_string(path)?;
-        serde_yaml::from_str(&content).map_err(Into::into)
+        let mut store: Self = serde_yaml::from_str(&content)?;
+        store.hmac_secret = std::env::var(api_key::HMAC_SECRET_ENV)
+            .ok()
+            .map(String::into_bytes);
+
+        for details in store.keys.values().filter(|details| details.hash.is_none()) {
+            warn!(
+                user_id = %details.user_id,
+                "API key is stored in plaintext; replace it with `rustygw keys rotate`"
+            );
+        }
+        Ok(store)
     }
 }
 
 //      Secrets Config
 pub struct SecretsConfig {
-    pub jwt_secret: String,
+    // Only required when JWTs are verified with an HMAC algorithm
+    pub jwt_secret: Option<String>,
+    // Enables the revocation admin endpoint
+    pub revocation_admin_token: Option<String>,
 }
 
 impl SecretsConfig {
     pub fn from_env() -> Result<Self, Error> {
         Ok(Self {
-            jwt_secret: std::env::var("JWT_SECRET")
-                .map_err(|_| anyhow::anyhow!("JWT_SECRET must be set in .env file"))?,
+            jwt_secret: std::env::var("JWT_SECRET").ok(),
+            revocation_admin_token: std::env::var("REVOCATION_ADMIN_TOKEN").ok(),
         })
     }
 }
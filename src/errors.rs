@@ -1,5 +1,5 @@
 use axum::{
-    http::StatusCode,
+    http::{HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
 use reqwest::Error;
@@ -8,16 +8,31 @@ use reqwest::Error;
 pub enum AppError {
     RateLimited,
     ServiceUnavailable,
+    // 429 with `Retry-After` after repeated authentication failures
+    LockedOut { retry_after: u64 },
 
     // Auth errors
     AuthFailed(String),
     MissingAuthToken,
     InvalidAuthHeader,
-    InsufficientPermissions,
+    // Carries the reason for the denial
+    InsufficientPermissions(String),
     TokenExpired,
+    // 401 with a `WWW-Authenticate: Basic` challenge for the realm
+    BasicAuthFailed { realm: String, reason: String },
+    ApiKeyRevoked,
+    ApiKeySuspended,
+    ApiKeyExpired,
+    ApiKeyNotYetValid,
+    // The key is valid but restricted away from this request
+    ApiKeyNotAllowed(String),
+
+    // Malformed request to a gateway endpoint
+    InvalidRequest(String),
 
     // Proxy errors
     RouteNotFound,
+    PayloadTooLarge,
     ProxyError(Error),
     InvalidDestination(String),
     InternalServerError,
@@ -25,11 +40,27 @@ pub enum AppError {
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        let extra_header = match &self {
+            AppError::BasicAuthFailed { realm, .. } => {
+                HeaderValue::from_str(&format!("Basic realm=\"{}\", charset=\"UTF-8\"", realm))
+                    .ok()
+                    .map(|value| (header::WWW_AUTHENTICATE, value))
+            }
+            AppError::LockedOut { retry_after } => {
+                Some((header::RETRY_AFTER, HeaderValue::from(*retry_after)))
+            }
+            _ => None,
+        };
+
         let (status, error_message) = match self {
             AppError::RateLimited => (
                 StatusCode::TOO_MANY_REQUESTS,
                 "Too many requests".to_string(),
             ),
+            AppError::LockedOut { .. } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many failed authentication attempts".to_string(),
+            ),
             AppError::AuthFailed(reason) => (
                 StatusCode::UNAUTHORIZED,
                 format!("Authentication failed: {}", reason),
@@ -42,12 +73,45 @@ impl IntoResponse for AppError {
                 StatusCode::UNAUTHORIZED,
                 "Invalid 'Authorization' header format. Expected 'Bearer <token>'.".to_string(),
             ),
-            AppError::InsufficientPermissions => (
+            AppError::InsufficientPermissions(reason) => (
                 StatusCode::FORBIDDEN,
-                "You do not have permission to access this resource.".to_string(),
+                format!(
+                    "You do not have permission to access this resource: {}",
+                    reason
+                ),
             ),
             AppError::TokenExpired => (StatusCode::UNAUTHORIZED, "Token has expired".to_string()),
+            AppError::BasicAuthFailed { reason, .. } => (
+                StatusCode::UNAUTHORIZED,
+                format!("Authentication failed: {}", reason),
+            ),
+            AppError::ApiKeyRevoked => (
+                StatusCode::UNAUTHORIZED,
+                "API key has been revoked".to_string(),
+            ),
+            AppError::ApiKeySuspended => {
+                (StatusCode::FORBIDDEN, "API key is suspended".to_string())
+            }
+            AppError::ApiKeyExpired => {
+                (StatusCode::UNAUTHORIZED, "API key has expired".to_string())
+            }
+            AppError::ApiKeyNotYetValid => (
+                StatusCode::UNAUTHORIZED,
+                "API key is not valid yet".to_string(),
+            ),
+            AppError::ApiKeyNotAllowed(reason) => (
+                StatusCode::FORBIDDEN,
+                format!("API key is not allowed to {}", reason),
+            ),
+            AppError::InvalidRequest(reason) => (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid request: {}", reason),
+            ),
             AppError::RouteNotFound => (StatusCode::NOT_FOUND, "Route not found".to_string()),
+            AppError::PayloadTooLarge => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "Request body too large".to_string(),
+            ),
             AppError::ProxyError(e) => {
                 tracing::error!("Proxy error: {}", e);
                 (
@@ -72,7 +136,11 @@ impl IntoResponse for AppError {
             ),
         };
 
-        (status, error_message).into_response()
+        let mut response = (status, error_message).into_response();
+        if let Some((name, value)) = extra_header {
+            response.headers_mut().insert(name, value);
+        }
+        response
     }
 }
 
@@ -0,0 +1,251 @@
+// API keys of the form `gw_<id>.<secret>`. The store only keeps a SHA-256
+// (or HMAC-SHA-256) digest of the whole key under its `gw_<id>` prefix, so the
+// key file is useless to whoever reads it.
+
+use std::net::IpAddr;
+
+use anyhow::{anyhow, bail};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use http::Method;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use tracing::error;
+
+use crate::{
+    config::{ApiKeyDetails, KeyStatus, RateLimitConfig, RouteConfig, contains_ip},
+    errors::AppError,
+    features::auth::{key_store::KeyStore, policy::canonical_segments},
+};
+
+pub const KEY_PREFIX: &str = "gw_";
+// Pepper for `hmac-sha256` digests, kept out of the key file
+pub const HMAC_SECRET_ENV: &str = "API_KEY_HMAC_SECRET";
+
+const SHA256_SCHEME: &str = "sha256:";
+const HMAC_SHA256_SCHEME: &str = "hmac-sha256:";
+
+pub struct GeneratedKey {
+    pub id: String,
+    // Handed to the client once; never stored
+    pub key: String,
+    pub hash: String,
+}
+
+/// Generates a new `gw_<id>.<secret>` key and the digest to store for it.
+pub fn generate_key(hmac_secret: Option<&[u8]>) -> GeneratedKey {
+    let mut id = [0u8; 8];
+    let mut secret = [0u8; 32];
+    rand::rng().fill_bytes(&mut id);
+    rand::rng().fill_bytes(&mut secret);
+
+    let id = format!("{}{}", KEY_PREFIX, hex(&id));
+    let key = format!("{}.{}", id, URL_SAFE_NO_PAD.encode(secret));
+    let hash = hash_key(&key, hmac_secret);
+    GeneratedKey { id, key, hash }
+}
+
+/// Digest stored in `api_keys.yaml`, prefixed with its scheme.
+pub fn hash_key(key: &str, hmac_secret: Option<&[u8]>) -> String {
+    match hmac_secret {
+        Some(secret) => format!("{}{}", HMAC_SHA256_SCHEME, hex(&hmac_sha256(secret, key))),
+        None => format!("{}{}", SHA256_SCHEME, hex(&Sha256::digest(key))),
+    }
+}
+
+/// Generates a key and adds an entry for it with `details`. The key itself is
+/// only returned here; the store keeps its digest.
+pub async fn create_key(
+    store: &dyn KeyStore,
+    details: ApiKeyDetails,
+) -> Result<GeneratedKey, anyhow::Error> {
+    let generated = generate_key(store.hmac_secret());
+    let details = ApiKeyDetails {
+        hash: Some(generated.hash.clone()),
+        ..details
+    };
+    store.create(&generated.id, details).await?;
+    Ok(generated)
+}
+
+/// Replaces the key of an entry with a new one carrying the same metadata,
+/// then revokes the old entry.
+pub async fn rotate_key(store: &dyn KeyStore, id: &str) -> Result<GeneratedKey, anyhow::Error> {
+    let details = store
+        .get(id)
+        .await?
+        .ok_or_else(|| anyhow!("API key '{}' not found", id))?;
+    if details.status == KeyStatus::Revoked {
+        bail!("API key '{}' is revoked", id);
+    }
+    let generated = create_key(store, details).await?;
+    store.revoke(id).await?;
+    Ok(generated)
+}
+
+/// Finds the entry for a presented key. Hashed entries are looked up by the
+/// key's ID prefix and compared in constant time. Legacy keys have no ID:
+/// hashed ones are stored under their digest, plaintext ones are still
+/// matched by the literal key.
+pub async fn find_key(store: &dyn KeyStore, key: &str) -> Result<Option<ApiKeyDetails>, AppError> {
+    let lookup = |id: String| async move {
+        store.get(&id).await.map_err(|e| {
+            error!("API key store lookup failed: {}", e);
+            AppError::InternalServerError
+        })
+    };
+
+    if let Some((id, _)) = key.split_once('.')
+        && let Some(details) = lookup(id.to_string()).await?
+        && let Some(stored) = &details.hash
+    {
+        let matches = verify_hash(key, stored, store.hmac_secret());
+        return Ok(matches.then_some(details));
+    }
+
+    let digest = hash_key(key, store.hmac_secret());
+    if let Some(details) = lookup(digest.clone()).await?
+        && details.hash.as_deref() == Some(digest.as_str())
+    {
+        return Ok(Some(details));
+    }
+
+    Ok(lookup(key.to_string())
+        .await?
+        .filter(|details| details.hash.is_none()))
+}
+
+/// Looks up a presented key and checks its state.
+pub async fn verify_key(store: &dyn KeyStore, key: &str) -> Result<ApiKeyDetails, AppError> {
+    let details = find_key(store, key)
+        .await?
+        .ok_or_else(|| AppError::AuthFailed("Invalid API Key.".to_string()))?;
+    check_key_state(&details)?;
+    Ok(details)
+}
+
+/// Checks an entry's status and validity window.
+pub fn check_key_state(details: &ApiKeyDetails) -> Result<(), AppError> {
+    match details.status {
+        KeyStatus::Active => {}
+        KeyStatus::Revoked => return Err(AppError::ApiKeyRevoked),
+        KeyStatus::Suspended => return Err(AppError::ApiKeySuspended),
+    }
+
+    let now = Utc::now();
+    if details
+        .not_before
+        .is_some_and(|not_before| now < not_before)
+    {
+        return Err(AppError::ApiKeyNotYetValid);
+    }
+    if details
+        .expires_at
+        .is_some_and(|expires_at| now >= expires_at)
+    {
+        return Err(AppError::ApiKeyExpired);
+    }
+    Ok(())
+}
+
+/// Enforces the key's route, method and source address restrictions.
+pub fn check_key_access(
+    details: &ApiKeyDetails,
+    route: &RouteConfig,
+    path: &str,
+    method: &Method,
+    client_ip: Option<IpAddr>,
+) -> Result<(), AppError> {
+    // Upstream URLs resolve dot segments, so path patterns see the path the
+    // upstream will
+    let canonical = canonical_segments(path).map(|segments| format!("/{}", segments.join("/")));
+    if !details.allowed_routes.is_empty()
+        && !details
+            .allowed_routes
+            .iter()
+            .any(|allowed| route_matches(allowed, route, canonical.as_deref()))
+    {
+        return Err(AppError::ApiKeyNotAllowed(format!(
+            "access route '{}'",
+            route.name
+        )));
+    }
+
+    if !details.allowed_methods.is_empty()
+        && !details
+            .allowed_methods
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(method.as_str()))
+    {
+        return Err(AppError::ApiKeyNotAllowed(format!("use method {}", method)));
+    }
+
+    if !details.allowed_cidrs.is_empty()
+        && !client_ip.is_some_and(|ip| contains_ip(&details.allowed_cidrs, &ip))
+    {
+        return Err(AppError::ApiKeyNotAllowed(
+            "be used from this address".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// Inserted into request extensions so the rate limiter buckets by key
+#[derive(Clone, Debug)]
+pub struct KeyRateLimit {
+    // Digest of the presented key, never the key itself
+    pub bucket: String,
+    pub config: RateLimitConfig,
+}
+
+impl KeyRateLimit {
+    pub fn new(key: &str, config: RateLimitConfig) -> Self {
+        Self {
+            bucket: format!("api_key:{}", hash_key(key, None)),
+            config,
+        }
+    }
+}
+
+// `path` is `None` when it can't be canonicalized, which no path pattern
+// matches
+fn route_matches(allowed: &str, route: &RouteConfig, path: Option<&str>) -> bool {
+    if !allowed.starts_with('/') {
+        return allowed == route.name;
+    }
+    let Some(path) = path else {
+        return false;
+    };
+    match allowed.strip_suffix('*') {
+        Some(prefix) => path.starts_with(prefix),
+        None => path == allowed,
+    }
+}
+
+fn verify_hash(key: &str, stored: &str, hmac_secret: Option<&[u8]>) -> bool {
+    let expected = if stored.starts_with(SHA256_SCHEME) {
+        hash_key(key, None)
+    } else if stored.starts_with(HMAC_SHA256_SCHEME) {
+        match hmac_secret {
+            Some(secret) => hash_key(key, Some(secret)),
+            None => return false,
+        }
+    } else {
+        return false;
+    };
+
+    expected.as_bytes().ct_eq(stored.as_bytes()).into()
+}
+
+fn hmac_sha256(secret: &[u8], key: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(key.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
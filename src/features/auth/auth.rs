@@ -1,13 +1,30 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, sync::Arc, time::Duration};
 
-use http::HeaderMap;
-use jsonwebtoken::{DecodingKey, Validation, decode, errors::ErrorKind};
+use http::{HeaderMap, Method, Uri};
+use jsonwebtoken::{Algorithm, Validation, decode, decode_header, errors::ErrorKind};
+use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use serde_json::{Map, Value};
+use tokio::sync::RwLock;
 
 use crate::{
-    config::{ApiKeyStore, AuthType, SecretsConfig},
+    config::{ApiKeyDetails, AuthConfig, AuthType, JwtValidationConfig, RoleExpr},
     errors::AppError,
+    features::auth::{
+        api_key::verify_key,
+        basic::{BasicAuthenticator, BasicCredentials},
+        credentials::extract_credential,
+        external::ExternalAuthorizer,
+        introspection::Introspector,
+        jwt::JwtProviders,
+        key_store::KeyStore,
+        oidc::OidcSessions,
+        propagation::IdentityPropagator,
+        revocation::{RevocationList, Revocations},
+        signature::SignatureVerifier,
+        token_issuer::TokenIssuer,
+    },
+    middleware::rate_limiter::rate_limit::parse_duration,
 };
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -15,70 +32,269 @@ pub struct Claims {
     pub sub: String, // Subject (Uer Id)
     pub roles: Vec<String>,
     pub exp: usize, // Required for JWT validation
+    // JWT provider that authenticated the request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    // Remaining token claims, preserved for downstream use
+    #[serde(flatten, default)]
+    pub extra: Map<String, Value>,
 }
 
-pub fn verify_token(
+// Verification backends shared by all routes
+pub struct AuthBackends {
+    pub jwt_providers: JwtProviders,
+    pub introspector: Introspector,
+    pub oidc: OidcSessions,
+    pub external: ExternalAuthorizer,
+    pub basic: BasicAuthenticator,
+    pub propagator: IdentityPropagator,
+    pub signatures: SignatureVerifier,
+    pub revocations: Revocations,
+    pub token_issuer: TokenIssuer,
+}
+
+impl AuthBackends {
+    pub fn new(
+        jwt_providers: JwtProviders,
+        http_client: Client,
+        basic_credentials: BasicCredentials,
+        revocation_list: RevocationList,
+        runtime_revocation_ttl: Duration,
+    ) -> Self {
+        Self {
+            jwt_providers,
+            introspector: Introspector::new(http_client.clone()),
+            oidc: OidcSessions::new(http_client),
+            external: ExternalAuthorizer::new(),
+            basic: BasicAuthenticator::new(Arc::new(RwLock::new(basic_credentials))),
+            propagator: IdentityPropagator::new(),
+            signatures: SignatureVerifier::new(),
+            revocations: Revocations::new(
+                Arc::new(RwLock::new(revocation_list)),
+                runtime_revocation_ttl,
+            ),
+            token_issuer: TokenIssuer::new(),
+        }
+    }
+}
+
+pub async fn verify_token(
     headers: &HeaderMap,
-    auth_config: &crate::config::AuthConfig,
-    secrets: &SecretsConfig,
-    key_store: &ApiKeyStore,
+    uri: &Uri,
+    auth_config: &AuthConfig,
+    backends: &AuthBackends,
+    key_store: &dyn KeyStore,
 ) -> Result<Claims, AppError> {
-    let token = extract_bearer_token(headers)?;
-
     match auth_config.auth_type {
-        AuthType::Jwt => verify_jwt(token, secrets),
-        AuthType::ApiKey => verify_api_key(token, key_store),
+        AuthType::Jwt => {
+            let token = extract_credential(headers, uri, &auth_config.credentials)?;
+            let claims = verify_jwt(&token, auth_config, &backends.jwt_providers).await?;
+            backends.revocations.check(&claims).await?;
+            Ok(claims)
+        }
+        AuthType::ApiKey => {
+            let key = extract_credential(headers, uri, &auth_config.credentials)?;
+            verify_api_key(&key, key_store).await
+        }
+        AuthType::Introspection => {
+            let token = extract_credential(headers, uri, &auth_config.credentials)?;
+            let config = auth_config.introspection.as_ref().ok_or_else(|| {
+                tracing::error!("Introspection route is missing 'auth.introspection'");
+                AppError::InternalServerError
+            })?;
+            backends.introspector.verify(&token, config).await
+        }
+        // Browser session cookie set by the OIDC callback
+        AuthType::Oidc => {
+            backends
+                .oidc
+                .verify_session(headers, auth_config, &backends.jwt_providers)
+                .await
+        }
+        AuthType::Basic => {
+            backends
+                .basic
+                .verify(headers, auth_config.realm.as_deref())
+                .await
+        }
+        // Both need more than the headers; the middleware handles them
+        AuthType::External | AuthType::Signature => {
+            tracing::error!(
+                "{:?} auth cannot be verified from headers alone",
+                auth_config.auth_type
+            );
+            Err(AppError::InternalServerError)
+        }
     }
 }
 
-fn extract_bearer_token(headers: &HeaderMap) -> Result<&str, AppError> {
-    let auth_header = headers
-        .get("Authorization")
-        .and_then(|value| value.to_str().ok())
-        .ok_or(AppError::MissingAuthToken)?;
+/// Checks the route's `roles` and the requirement for this method, if any.
+pub fn check_route_roles(
+    user_roles: &[String],
+    auth_config: &AuthConfig,
+    method: &Method,
+) -> Result<(), AppError> {
+    let method_roles = auth_config
+        .method_roles
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(method.as_str()))
+        .map(|(_, expr)| expr);
+
+    for required in auth_config.roles.iter().chain(method_roles) {
+        check_roles(user_roles, required)?;
+    }
+    Ok(())
+}
 
-    auth_header
-        .strip_prefix("Bearer ")
-        .ok_or(AppError::InvalidAuthHeader)
+pub fn check_roles(user_roles: &[String], required: &RoleExpr) -> Result<(), AppError> {
+    let user_roles_set: HashSet<&str> = user_roles.iter().map(String::as_str).collect();
+    if satisfies(required, &user_roles_set) {
+        Ok(())
+    } else {
+        Err(AppError::InsufficientPermissions(
+            "missing required roles".to_string(),
+        ))
+    }
 }
 
-pub fn check_roles(user_roles: &[String], required_roles: &[String]) -> Result<(), AppError> {
-    let user_roles_set: HashSet<_> = user_roles.iter().collect();
-    for required_role in required_roles {
-        if !user_roles_set.contains(required_role) {
-            return Err(AppError::InsufficientPermissions);
+fn satisfies(expr: &RoleExpr, user_roles: &HashSet<&str>) -> bool {
+    match expr {
+        RoleExpr::Role(role) => user_roles.contains(role.as_str()),
+        RoleExpr::All(exprs) => exprs.iter().all(|expr| satisfies(expr, user_roles)),
+        RoleExpr::Block(block) => {
+            block
+                .any_of
+                .as_ref()
+                .is_none_or(|exprs| exprs.iter().any(|expr| satisfies(expr, user_roles)))
+                && block
+                    .all_of
+                    .as_ref()
+                    .is_none_or(|exprs| exprs.iter().all(|expr| satisfies(expr, user_roles)))
+                && block
+                    .none_of
+                    .as_ref()
+                    .is_none_or(|exprs| !exprs.iter().any(|expr| satisfies(expr, user_roles)))
         }
     }
-    Ok(())
 }
 
 // ------- Private Helper Functions  -----
 
-fn verify_jwt(token: &str, secrets: &SecretsConfig) -> Result<Claims, AppError> {
-    info!(token);
-    let key = DecodingKey::from_secret(secrets.jwt_secret.as_ref());
-    let validation = Validation::new(jsonwebtoken::Algorithm::HS256);
-    decode::<Claims>(token, &key, &validation)
-        .map_err(|error| match error.kind() {
-            ErrorKind::ExpiredSignature => AppError::TokenExpired,
-            _ => AppError::AuthFailed("Invalid JWT.".to_string()),
-        })
-        .map(|token_data| token_data.claims)
+async fn verify_jwt(
+    token: &str,
+    auth_config: &AuthConfig,
+    jwt_providers: &JwtProviders,
+) -> Result<Claims, AppError> {
+    let header =
+        decode_header(token).map_err(|_| AppError::AuthFailed("Invalid JWT.".to_string()))?;
+    let provider = jwt_providers.select(token, &header, auth_config.providers.as_deref())?;
+    let jwt_config = provider.validation_for(auth_config.jwt.as_ref());
+
+    let key = provider.decoding_key(&header).await?;
+    let validation = build_validation(header.alg, &jwt_config);
+
+    let token_data = decode::<Map<String, Value>>(token, &key, &validation).map_err(jwt_error)?;
+
+    let mut claims = map_claims(
+        token_data.claims,
+        &jwt_config.subject_claim,
+        &jwt_config.roles_claims,
+    )?;
+    claims.provider = Some(provider.name.clone());
+    Ok(claims)
 }
 
-fn verify_api_key(token: &str, key_store: &ApiKeyStore) -> Result<Claims, AppError> {
-    let details = key_store
-        .keys
-        .get(token)
-        .ok_or_else(|| AppError::AuthFailed("Invalid API Key.".to_string()))?;
+pub(crate) fn jwt_error(error: jsonwebtoken::errors::Error) -> AppError {
+    match error.kind() {
+        ErrorKind::ExpiredSignature => AppError::TokenExpired,
+        ErrorKind::ImmatureSignature => AppError::AuthFailed("JWT is not valid yet.".to_string()),
+        ErrorKind::InvalidIssuer => AppError::AuthFailed("Invalid JWT issuer.".to_string()),
+        ErrorKind::InvalidAudience => AppError::AuthFailed("Invalid JWT audience.".to_string()),
+        _ => AppError::AuthFailed("Invalid JWT.".to_string()),
+    }
+}
+
+pub(crate) fn build_validation(
+    algorithm: Algorithm,
+    jwt_config: &JwtValidationConfig,
+) -> Validation {
+    let mut validation = Validation::new(algorithm);
+    validation.validate_nbf = true;
+    validation.leeway = parse_duration(&jwt_config.leeway)
+        .map(|leeway| leeway.as_secs())
+        .unwrap_or(60);
+
+    if !jwt_config.issuers.is_empty() {
+        validation.set_issuer(&jwt_config.issuers);
+    }
+    if jwt_config.audiences.is_empty() {
+        validation.validate_aud = false;
+    } else {
+        validation.set_audience(&jwt_config.audiences);
+    }
+    validation
+}
+
+// Builds `Claims` from validated token claims using the configured JSON pointers.
+pub(crate) fn map_claims(
+    mut token_claims: Map<String, Value>,
+    subject_claim: &str,
+    roles_claims: &[String],
+) -> Result<Claims, AppError> {
+    let token_value = Value::Object(token_claims.clone());
+
+    let sub = match token_value.pointer(subject_claim) {
+        Some(Value::String(sub)) => sub.clone(),
+        Some(Value::Number(sub)) => sub.to_string(),
+        _ => {
+            return Err(AppError::AuthFailed(
+                "Token is missing the subject claim.".to_string(),
+            ));
+        }
+    };
 
-    if details.status != "active" {
-        return Err(AppError::AuthFailed("API Key is revoked.".to_string()));
+    let mut roles: Vec<String> = Vec::new();
+    for pointer in roles_claims {
+        let values: Vec<&str> = match token_value.pointer(pointer) {
+            Some(Value::String(scopes)) => scopes.split_whitespace().collect(),
+            Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+            _ => continue,
+        };
+        for role in values {
+            if !roles.iter().any(|r| r == role) {
+                roles.push(role.to_string());
+            }
+        }
+    }
+
+    let exp = token_claims
+        .get("exp")
+        .and_then(Value::as_u64)
+        .unwrap_or_default() as usize;
+
+    for claim in ["sub", "roles", "exp"] {
+        token_claims.remove(claim);
     }
 
     Ok(Claims {
+        sub,
+        roles,
+        exp,
+        provider: None,
+        extra: token_claims,
+    })
+}
+
+async fn verify_api_key(token: &str, key_store: &dyn KeyStore) -> Result<Claims, AppError> {
+    Ok(api_key_claims(&verify_key(key_store, token).await?))
+}
+
+pub(crate) fn api_key_claims(details: &ApiKeyDetails) -> Claims {
+    Claims {
         sub: details.user_id.clone(),
         roles: details.roles.clone(),
         exp: 0, // Not applicable for API keys
-    })
+        provider: None,
+        extra: Map::new(),
+    }
 }
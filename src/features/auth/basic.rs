@@ -0,0 +1,199 @@
+// HTTP Basic authentication against a credential file of bcrypt or argon2
+// password hashes. Both formats are accepted:
+//
+//   htpasswd:  alice:$2y$10$...            (optionally `:role1,role2`)
+//   YAML:      users: { alice: { password_hash: "$argon2id$...", roles: [...] } }
+
+use std::{collections::HashMap, fs, path::Path, sync::Arc, time::Duration};
+
+use anyhow::{Error, anyhow};
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use http::HeaderMap;
+use moka::future::Cache;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::Map;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use tracing::{error, warn};
+
+use crate::{errors::AppError, features::auth::auth::Claims};
+
+const DEFAULT_REALM: &str = "gateway";
+// Reason given when no Basic credentials were sent at all
+pub(crate) const MISSING_CREDENTIALS: &str = "Missing Basic credentials.";
+
+// Verified against when the user does not exist, so unknown and known users
+// take the same time to reject.
+static DUMMY_HASH: Lazy<String> =
+    Lazy::new(|| bcrypt::hash("dummy-password", bcrypt::DEFAULT_COST).unwrap_or_default());
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct BasicUser {
+    pub password_hash: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct BasicCredentials {
+    pub users: HashMap<String, BasicUser>,
+}
+
+impl BasicCredentials {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        let credentials = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&content)?,
+            _ => Self::parse_htpasswd(&content)?,
+        };
+
+        for (username, user) in &credentials.users {
+            if !is_supported_hash(&user.password_hash) {
+                warn!(user = %username, "Unsupported password hash; use bcrypt or argon2");
+            }
+        }
+        Ok(credentials)
+    }
+
+    fn parse_htpasswd(content: &str) -> Result<Self, Error> {
+        let mut users = HashMap::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.splitn(3, ':');
+            let (Some(username), Some(password_hash)) = (fields.next(), fields.next()) else {
+                return Err(anyhow!("Invalid htpasswd entry on line {}", number + 1));
+            };
+            let roles = fields
+                .next()
+                .map(|roles| {
+                    roles
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|role| !role.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            users.insert(
+                username.to_string(),
+                BasicUser {
+                    password_hash: password_hash.to_string(),
+                    roles,
+                },
+            );
+        }
+        Ok(Self { users })
+    }
+}
+
+pub struct BasicAuthenticator {
+    credentials: Arc<RwLock<BasicCredentials>>,
+    // Digests of recently verified `user:password:hash` triples; password
+    // hashing is deliberately slow and Basic clients resend on every request.
+    verified: Cache<[u8; 32], ()>,
+}
+
+impl BasicAuthenticator {
+    pub fn new(credentials: Arc<RwLock<BasicCredentials>>) -> Self {
+        Self {
+            credentials,
+            verified: Cache::builder()
+                .max_capacity(10_000)
+                .time_to_live(Duration::from_secs(60))
+                .build(),
+        }
+    }
+
+    // Shared with the hot reloader
+    pub fn credentials(&self) -> Arc<RwLock<BasicCredentials>> {
+        self.credentials.clone()
+    }
+
+    pub async fn verify(
+        &self,
+        headers: &HeaderMap,
+        realm: Option<&str>,
+    ) -> Result<Claims, AppError> {
+        let failed = |reason: &str| AppError::BasicAuthFailed {
+            realm: realm.unwrap_or(DEFAULT_REALM).to_string(),
+            reason: reason.to_string(),
+        };
+
+        let (username, password) =
+            basic_credentials(headers).ok_or_else(|| failed(MISSING_CREDENTIALS))?;
+        let user = self.credentials.read().await.users.get(&username).cloned();
+        let password_hash = user
+            .as_ref()
+            .map_or_else(|| DUMMY_HASH.clone(), |user| user.password_hash.clone());
+
+        let digest: [u8; 32] = Sha256::new()
+            .chain_update(&username)
+            .chain_update([0])
+            .chain_update(&password)
+            .chain_update([0])
+            .chain_update(&password_hash)
+            .finalize()
+            .into();
+
+        // Unknown users are checked against the dummy hash and still rejected.
+        let valid = self.verified.contains_key(&digest)
+            || tokio::task::spawn_blocking(move || verify_password(&password, &password_hash))
+                .await
+                .unwrap_or_else(|e| {
+                    error!("Password verification task failed: {}", e);
+                    false
+                });
+
+        match user {
+            Some(user) if valid => {
+                self.verified.insert(digest, ()).await;
+                Ok(Claims {
+                    sub: username,
+                    roles: user.roles,
+                    exp: 0, // Not applicable for Basic auth
+                    provider: None,
+                    extra: Map::new(),
+                })
+            }
+            _ => Err(failed("Invalid username or password.")),
+        }
+    }
+}
+
+/// Username and password from an `Authorization: Basic` header.
+pub(crate) fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let encoded = headers
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())?
+        .strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+fn is_supported_hash(hash: &str) -> bool {
+    hash.starts_with("$2") || hash.starts_with("$argon2")
+}
+
+// Both libraries compare the derived hash in constant time.
+fn verify_password(password: &str, hash: &str) -> bool {
+    if hash.starts_with("$argon2") {
+        PasswordHash::new(hash).is_ok_and(|parsed| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok()
+        })
+    } else if hash.starts_with("$2") {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    } else {
+        false
+    }
+}
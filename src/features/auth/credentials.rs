@@ -0,0 +1,116 @@
+// Reads a route's credential from the first configured source that carries
+// one (Authorization bearer, custom header, query parameter or cookie) and
+// removes it again before the request is proxied.
+
+use std::collections::HashMap;
+
+use axum::extract::Query;
+use http::{HeaderMap, HeaderValue, Uri, header};
+
+use crate::{config::CredentialSource, errors::AppError};
+
+pub fn extract_credential(
+    headers: &HeaderMap,
+    uri: &Uri,
+    sources: &[CredentialSource],
+) -> Result<String, AppError> {
+    if let Some(credential) = sources
+        .iter()
+        .find_map(|source| read_source(headers, uri, source))
+    {
+        return Ok(credential);
+    }
+
+    // A non-Bearer Authorization header is most likely a client mistake.
+    if sources.contains(&CredentialSource::Bearer) && headers.contains_key(header::AUTHORIZATION) {
+        return Err(AppError::InvalidAuthHeader);
+    }
+    Err(AppError::MissingAuthToken)
+}
+
+/// Removes the credential that `extract_credential` would have used.
+pub fn strip_credential(headers: &mut HeaderMap, uri: &mut Uri, sources: &[CredentialSource]) {
+    let Some(source) = sources
+        .iter()
+        .find(|source| read_source(headers, uri, source).is_some())
+    else {
+        return;
+    };
+
+    match source {
+        CredentialSource::Bearer => {
+            headers.remove(header::AUTHORIZATION);
+        }
+        CredentialSource::Header(name) => {
+            headers.remove(name.as_str());
+        }
+        CredentialSource::Query(name) => {
+            if let Some(stripped) = strip_query_param(uri, name) {
+                *uri = stripped;
+            }
+        }
+        CredentialSource::Cookie(name) => strip_cookie(headers, name),
+    }
+}
+
+fn read_source(headers: &HeaderMap, uri: &Uri, source: &CredentialSource) -> Option<String> {
+    let value = match source {
+        CredentialSource::Bearer => headers
+            .get(header::AUTHORIZATION)?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")?
+            .to_string(),
+        CredentialSource::Header(name) => headers.get(name.as_str())?.to_str().ok()?.to_string(),
+        CredentialSource::Query(name) => {
+            let Query(mut params) = Query::<HashMap<String, String>>::try_from_uri(uri).ok()?;
+            params.remove(name)?
+        }
+        CredentialSource::Cookie(name) => cookies(headers)
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())?,
+    };
+    Some(value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+fn cookies(headers: &HeaderMap) -> impl Iterator<Item = (&str, &str)> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+}
+
+fn strip_cookie(headers: &mut HeaderMap, name: &str) {
+    let remaining: Vec<String> = cookies(headers)
+        .filter(|(key, _)| *key != name)
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+
+    headers.remove(header::COOKIE);
+    if let Ok(value) = HeaderValue::from_str(&remaining.join("; "))
+        && !remaining.is_empty()
+    {
+        headers.insert(header::COOKIE, value);
+    }
+}
+
+// Drops every `name=...` pair and leaves the others exactly as sent.
+fn strip_query_param(uri: &Uri, name: &str) -> Option<Uri> {
+    let query = uri.query()?;
+    let remaining: Vec<&str> = query
+        .split('&')
+        .filter(|pair| pair.split('=').next() != Some(name))
+        .collect();
+
+    let path_and_query = if remaining.is_empty() {
+        uri.path().to_string()
+    } else {
+        format!("{}?{}", uri.path(), remaining.join("&"))
+    };
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse().ok()?);
+    Uri::from_parts(parts).ok()
+}
@@ -0,0 +1,53 @@
+// Per-entry time to live for the moka caches whose entries each know how long
+// they stay valid.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use moka::Expiry;
+
+pub(crate) trait EntryTtl {
+    fn ttl(&self) -> Duration;
+}
+
+/// Expires each cache entry after its own `EntryTtl::ttl`, also when it is
+/// replaced.
+pub(crate) struct EntryExpiry;
+
+impl<K, V: EntryTtl> Expiry<K, V> for EntryExpiry {
+    fn expire_after_create(&self, _key: &K, value: &V, _created_at: Instant) -> Option<Duration> {
+        Some(value.ttl())
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &K,
+        value: &V,
+        _updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(value.ttl())
+    }
+}
+
+// A bare duration is the entry's time to live
+impl EntryTtl for Duration {
+    fn ttl(&self) -> Duration {
+        *self
+    }
+}
+
+// A bare instant is the entry's deadline
+impl EntryTtl for Instant {
+    fn ttl(&self) -> Duration {
+        self.saturating_duration_since(Instant::now())
+    }
+}
+
+impl<T: EntryTtl> EntryTtl for Arc<T> {
+    fn ttl(&self) -> Duration {
+        self.as_ref().ttl()
+    }
+}
@@ -0,0 +1,236 @@
+// Forward auth: asks an HTTP authorization service whether a request may pass.
+// A 2xx answer allows it; 5xx answers and transport errors follow `on_error`;
+// anything else (e.g. a 401 or a redirect to a login page) is relayed to the
+// client as-is.
+
+use std::{net::IpAddr, time::Duration};
+
+use bytes::Bytes;
+use http::{HeaderMap, HeaderName, Method, StatusCode, Uri, header};
+use moka::future::Cache;
+use reqwest::{Client, redirect};
+use serde_json::Map;
+use tracing::{debug, error, warn};
+
+use crate::{
+    config::{ExternalAuthConfig, ExternalAuthFailureMode},
+    errors::AppError,
+    features::{
+        auth::{
+            auth::Claims,
+            expiry::{EntryExpiry, EntryTtl},
+        },
+        client_ip::client_ip::X_FORWARDED_FOR,
+        proxy::headers::strip_hop_by_hop,
+    },
+    middleware::rate_limiter::rate_limit::parse_duration,
+};
+
+// Original request line, as sent by Traefik and nginx `auth_request` setups
+const X_FORWARDED_METHOD: HeaderName = HeaderName::from_static("x-forwarded-method");
+const X_FORWARDED_URI: HeaderName = HeaderName::from_static("x-forwarded-uri");
+
+#[derive(Clone)]
+pub enum Decision {
+    Allow {
+        claims: Claims,
+        // Added to the upstream request
+        headers: HeaderMap,
+    },
+    Deny {
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Bytes,
+    },
+}
+
+#[derive(Clone)]
+struct CachedDecision {
+    decision: Decision,
+    ttl: Duration,
+}
+
+impl EntryTtl for CachedDecision {
+    fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+pub struct ExternalAuthorizer {
+    http_client: Client,
+    cache: Cache<String, CachedDecision>,
+}
+
+impl Default for ExternalAuthorizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExternalAuthorizer {
+    pub fn new() -> Self {
+        Self {
+            // A redirect (to a login page) is the service's answer, and must
+            // not be followed to that page's 200
+            http_client: Client::builder()
+                .redirect(redirect::Policy::none())
+                .build()
+                .expect("HTTP client configuration is valid"),
+            cache: Cache::builder()
+                .max_capacity(100_000)
+                .expire_after(EntryExpiry)
+                .build(),
+        }
+    }
+
+    pub async fn authorize(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+        client_ip: Option<IpAddr>,
+        config: &ExternalAuthConfig,
+    ) -> Result<Decision, AppError> {
+        let path = uri.path_and_query().map_or("/", |p| p.as_str());
+        let forwarded: Vec<(HeaderName, &[u8])> = config
+            .forward_headers
+            .iter()
+            .filter_map(|name| HeaderName::from_bytes(name.as_bytes()).ok())
+            .flat_map(|name| {
+                headers
+                    .get_all(&name)
+                    .iter()
+                    .map(move |value| (name.clone(), value.as_bytes()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        let cache_ttl = config
+            .cache_ttl
+            .as_deref()
+            .and_then(|ttl| parse_duration(ttl).ok());
+        // Everything the service sees goes into the key.
+        let cache_key = cache_ttl.map(|_| {
+            let mut key = format!("{}\n{}\n{}\n{:?}", config.url, method, path, client_ip);
+            for (name, value) in &forwarded {
+                key.push('\n');
+                key.push_str(name.as_str());
+                key.push(':');
+                key.push_str(&String::from_utf8_lossy(value));
+            }
+            key
+        });
+        if let Some(key) = &cache_key
+            && let Some(cached) = self.cache.get(key).await
+        {
+            return Ok(cached.decision);
+        }
+
+        let mut request = self
+            .http_client
+            .get(&config.url)
+            .header(X_FORWARDED_METHOD, method.as_str())
+            .header(X_FORWARDED_URI, path)
+            .timeout(parse_duration(&config.timeout).unwrap_or(Duration::from_secs(2)));
+        if let Some(client_ip) = client_ip {
+            request = request.header(X_FORWARDED_FOR, client_ip.to_string());
+        }
+        for (name, value) in forwarded {
+            request = request.header(name, value);
+        }
+
+        let response = match request.send().await {
+            Ok(response) if response.status().is_server_error() => {
+                Err(format!("answered {}", response.status()))
+            }
+            Ok(response) => Ok(response),
+            Err(e) => Err(e.to_string()),
+        };
+        // Failures are never cached
+        let response = match response {
+            Ok(response) => response,
+            Err(e) if config.on_error == ExternalAuthFailureMode::Allow => {
+                warn!(url = %config.url, "Authorization service failed, allowing request: {}", e);
+                return Ok(Decision::Allow {
+                    claims: claims_from(&HeaderMap::new(), config),
+                    headers: HeaderMap::new(),
+                });
+            }
+            Err(e) => {
+                error!(url = %config.url, "Authorization service failed: {}", e);
+                return Err(AppError::ServiceUnavailable);
+            }
+        };
+
+        let status = response.status();
+        let mut response_headers = response.headers().clone();
+        let decision = if status.is_success() {
+            let mut upstream_headers = HeaderMap::new();
+            for name in &config.upstream_headers {
+                let Ok(name) = HeaderName::from_bytes(name.as_bytes()) else {
+                    continue;
+                };
+                for value in response_headers.get_all(&name) {
+                    upstream_headers.append(name.clone(), value.clone());
+                }
+            }
+            Decision::Allow {
+                claims: claims_from(&response_headers, config),
+                headers: upstream_headers,
+            }
+        } else {
+            let body = response.bytes().await.unwrap_or_default();
+            strip_hop_by_hop(&mut response_headers);
+            response_headers.remove(header::CONTENT_LENGTH);
+            Decision::Deny {
+                status,
+                headers: response_headers,
+                body,
+            }
+        };
+        debug!(url = %config.url, status = %status, "External authorization decision");
+
+        if let (Some(key), Some(ttl)) = (cache_key, cache_ttl) {
+            self.cache
+                .insert(
+                    key,
+                    CachedDecision {
+                        decision: decision.clone(),
+                        ttl,
+                    },
+                )
+                .await;
+        }
+        Ok(decision)
+    }
+}
+
+/// Removes client supplied copies of the headers the authorization service
+/// sets, so they cannot be spoofed towards the upstream.
+pub fn strip_upstream_headers(headers: &mut HeaderMap, config: &ExternalAuthConfig) {
+    for name in &config.upstream_headers {
+        headers.remove(name.as_str());
+    }
+}
+
+fn claims_from(headers: &HeaderMap, config: &ExternalAuthConfig) -> Claims {
+    let header_value = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+    };
+
+    Claims {
+        sub: header_value(&config.subject_header).to_string(),
+        roles: header_value(&config.roles_header)
+            .split(',')
+            .map(str::trim)
+            .filter(|role| !role.is_empty())
+            .map(str::to_string)
+            .collect(),
+        exp: 0,
+        provider: None,
+        extra: Map::new(),
+    }
+}
@@ -0,0 +1,132 @@
+// OAuth2 token introspection (RFC 7662) for opaque access tokens. Results are
+// cached, active tokens no longer than their `exp`, so most requests skip the
+// round-trip to the authorization server.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use moka::future::Cache;
+use reqwest::Client;
+use serde_json::{Map, Value};
+use tracing::{debug, error};
+
+use crate::{
+    config::IntrospectionConfig,
+    errors::AppError,
+    features::auth::{
+        auth::{Claims, map_claims},
+        expiry::{EntryExpiry, EntryTtl},
+    },
+    middleware::rate_limiter::rate_limit::parse_duration,
+};
+
+// Endpoint and token, so routes using different servers never share results
+type CacheKey = (String, String);
+
+#[derive(Clone)]
+struct Introspected {
+    // `None` for an inactive token
+    claims: Option<Claims>,
+    ttl: Duration,
+}
+
+impl EntryTtl for Introspected {
+    fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+pub struct Introspector {
+    http_client: Client,
+    cache: Cache<CacheKey, Introspected>,
+}
+
+impl Introspector {
+    pub fn new(http_client: Client) -> Self {
+        Self {
+            http_client,
+            cache: Cache::builder()
+                .max_capacity(100_000)
+                .expire_after(EntryExpiry)
+                .build(),
+        }
+    }
+
+    pub async fn verify(
+        &self,
+        token: &str,
+        config: &IntrospectionConfig,
+    ) -> Result<Claims, AppError> {
+        let key = (config.endpoint.clone(), token.to_string());
+        let introspected = match self.cache.get(&key).await {
+            Some(introspected) => introspected,
+            None => {
+                let introspected = self.introspect(token, config).await?;
+                if !introspected.ttl.is_zero() {
+                    self.cache.insert(key, introspected.clone()).await;
+                }
+                introspected
+            }
+        };
+
+        introspected
+            .claims
+            .ok_or_else(|| AppError::AuthFailed("Token is not active.".to_string()))
+    }
+
+    async fn introspect(
+        &self,
+        token: &str,
+        config: &IntrospectionConfig,
+    ) -> Result<Introspected, AppError> {
+        let client_secret = std::env::var(&config.client_secret_env).map_err(|_| {
+            error!(env = %config.client_secret_env, "Introspection client secret is not set");
+            AppError::InternalServerError
+        })?;
+        let timeout = parse_duration(&config.timeout).unwrap_or(Duration::from_secs(5));
+
+        let mut response: Map<String, Value> = self
+            .http_client
+            .post(&config.endpoint)
+            .basic_auth(&config.client_id, Some(client_secret))
+            .form(&[("token", token), ("token_type_hint", "access_token")])
+            .timeout(timeout)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| {
+                error!(endpoint = %config.endpoint, "Token introspection failed: {}", e);
+                AppError::ServiceUnavailable
+            })?
+            .json()
+            .await
+            .map_err(|e| {
+                error!(endpoint = %config.endpoint, "Invalid introspection response: {}", e);
+                AppError::ServiceUnavailable
+            })?;
+
+        if response.remove("active") != Some(Value::Bool(true)) {
+            debug!(endpoint = %config.endpoint, "Introspected token is inactive");
+            return Ok(Introspected {
+                claims: None,
+                ttl: parse_duration(&config.negative_cache_ttl).unwrap_or(Duration::from_secs(30)),
+            });
+        }
+
+        let claims = map_claims(response, &config.subject_claim, &config.roles_claims)?;
+
+        let mut ttl = parse_duration(&config.cache_ttl).unwrap_or(Duration::from_secs(300));
+        if claims.exp > 0 {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let remaining = (claims.exp as u64).saturating_sub(now);
+            ttl = ttl.min(Duration::from_secs(remaining));
+        }
+
+        Ok(Introspected {
+            claims: Some(claims),
+            ttl,
+        })
+    }
+}
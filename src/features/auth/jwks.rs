@@ -0,0 +1,172 @@
+// JSON Web Key Set loaded from a file or URL, refreshed periodically and
+// refetched (rate-limited) when a token names a `kid` we have not seen yet.
+
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Error, anyhow};
+use jsonwebtoken::{DecodingKey, jwk::JwkSet};
+use reqwest::Client;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::{config::JwksConfig, middleware::rate_limiter::rate_limit::parse_duration};
+
+enum JwksSource {
+    Url(String),
+    Path(String),
+}
+
+struct JwksKey {
+    kid: Option<String>,
+    key: DecodingKey,
+}
+
+pub struct JwksStore {
+    source: JwksSource,
+    http_client: Client,
+    keys: RwLock<Vec<JwksKey>>,
+    // When the last fetch started; guards refetch-on-unknown-kid. Held while
+    // fetching, so requests with the same unknown kid wait for one fetch,
+    // which `fetch_timeout` keeps short.
+    last_fetch: Mutex<Option<Instant>>,
+    min_refetch_interval: Duration,
+    fetch_timeout: Duration,
+}
+
+impl JwksStore {
+    pub async fn load(config: &JwksConfig, http_client: Client) -> Result<Arc<Self>, Error> {
+        let source = match (&config.url, &config.path) {
+            (Some(url), None) => JwksSource::Url(url.clone()),
+            (None, Some(path)) => JwksSource::Path(path.clone()),
+            _ => return Err(anyhow!("JWKS config needs exactly one of 'url' or 'path'")),
+        };
+
+        let store = Arc::new(Self {
+            source,
+            http_client,
+            keys: RwLock::new(Vec::new()),
+            last_fetch: Mutex::new(None),
+            min_refetch_interval: parse_duration(&config.min_refetch_interval)
+                .map_err(|e| anyhow!("Invalid JWKS min_refetch_interval: {}", e))?,
+            fetch_timeout: parse_duration(&config.fetch_timeout)
+                .map_err(|e| anyhow!("Invalid JWKS fetch_timeout: {}", e))?,
+        });
+        store.refresh().await?;
+
+        let refresh_interval = parse_duration(&config.refresh_interval)
+            .map_err(|e| anyhow!("Invalid JWKS refresh_interval: {}", e))?;
+        tokio::spawn(Self::refresh_periodically(
+            Arc::downgrade(&store),
+            refresh_interval,
+        ));
+
+        Ok(store)
+    }
+
+    /// Finds the key for a token header. Tokens without a `kid` are only
+    /// accepted when the set holds a single key.
+    pub async fn key_for(&self, kid: Option<&str>) -> Option<DecodingKey> {
+        if let Some(key) = self.lookup(kid) {
+            return Some(key);
+        }
+
+        // Unknown kid: the issuer may have rotated keys since our last fetch.
+        // A concurrent request may have refetched already, so always look again.
+        if kid.is_some() {
+            self.try_refetch().await;
+            return self.lookup(kid);
+        }
+        None
+    }
+
+    pub fn has_key(&self, kid: &str) -> bool {
+        self.lookup(Some(kid)).is_some()
+    }
+
+    fn lookup(&self, kid: Option<&str>) -> Option<DecodingKey> {
+        let keys = self.keys.read().unwrap();
+        match kid {
+            Some(kid) => keys
+                .iter()
+                .find(|k| k.kid.as_deref() == Some(kid))
+                .map(|k| k.key.clone()),
+            None if keys.len() == 1 => Some(keys[0].key.clone()),
+            None => None,
+        }
+    }
+
+    async fn try_refetch(&self) {
+        let mut last_fetch = self.last_fetch.lock().await;
+        if last_fetch.is_some_and(|at| at.elapsed() < self.min_refetch_interval) {
+            return;
+        }
+
+        *last_fetch = Some(Instant::now());
+        if let Err(e) = self.fetch().await {
+            warn!("Failed to refetch JWKS for unknown kid: {:#}", e);
+        }
+    }
+
+    pub async fn refresh(&self) -> Result<(), Error> {
+        let mut last_fetch = self.last_fetch.lock().await;
+        *last_fetch = Some(Instant::now());
+        self.fetch().await
+    }
+
+    async fn fetch(&self) -> Result<(), Error> {
+        let set: JwkSet = match &self.source {
+            JwksSource::Url(url) => self
+                .http_client
+                .get(url)
+                .timeout(self.fetch_timeout)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .with_context(|| format!("Failed to fetch JWKS from {}", url))?
+                .json()
+                .await
+                .with_context(|| format!("Invalid JWKS document at {}", url))?,
+            JwksSource::Path(path) => {
+                let content = tokio::fs::read_to_string(path)
+                    .await
+                    .with_context(|| format!("Failed to read JWKS file {}", path))?;
+                serde_json::from_str(&content)
+                    .with_context(|| format!("Invalid JWKS document in {}", path))?
+            }
+        };
+
+        let keys: Vec<JwksKey> = set
+            .keys
+            .into_iter()
+            .filter_map(|jwk| match DecodingKey::from_jwk(&jwk) {
+                Ok(key) => Some(JwksKey {
+                    kid: jwk.common.key_id,
+                    key,
+                }),
+                Err(e) => {
+                    warn!(kid = ?jwk.common.key_id, "Skipping unusable JWK: {}", e);
+                    None
+                }
+            })
+            .collect();
+
+        info!(keys = keys.len(), "Loaded JWKS");
+        *self.keys.write().unwrap() = keys;
+        Ok(())
+    }
+
+    async fn refresh_periodically(store: std::sync::Weak<Self>, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            let Some(store) = store.upgrade() else {
+                return;
+            };
+            if let Err(e) = store.refresh().await {
+                error!("Failed to refresh JWKS, keeping previous keys: {:#}", e);
+            }
+        }
+    }
+}
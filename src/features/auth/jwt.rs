@@ -0,0 +1,289 @@
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+use anyhow::{Error, anyhow};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{Algorithm, DecodingKey, Header};
+use reqwest::Client;
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::{
+    config::{
+        IdentityConfig, JwtConfig, JwtValidationConfig, JwtValidationOverrides, SecretsConfig,
+    },
+    errors::AppError,
+    features::auth::jwks::JwksStore,
+};
+
+// Name reported in `Claims.provider` for the `identity.jwt` provider
+pub const DEFAULT_PROVIDER: &str = "default";
+
+// All JWT providers known to the gateway: the default one from `identity.jwt`
+// plus the named ones from `identity.jwt_providers`.
+pub struct JwtProviders {
+    default: Option<Arc<JwtProvider>>,
+    named: HashMap<String, Arc<JwtProvider>>,
+}
+
+impl JwtProviders {
+    pub async fn from_config(
+        identity: &IdentityConfig,
+        secrets: &SecretsConfig,
+        http_client: Client,
+    ) -> Result<Self, Error> {
+        let mut named = HashMap::new();
+        for provider in &identity.jwt_providers {
+            if provider.name == DEFAULT_PROVIDER || named.contains_key(&provider.name) {
+                return Err(anyhow!("Duplicate JWT provider name '{}'", provider.name));
+            }
+            let jwt_provider = JwtProvider::from_config(
+                &provider.name,
+                Some(&provider.keys),
+                provider.validation.clone(),
+                secrets,
+                http_client.clone(),
+            )
+            .await?;
+            info!(provider = %provider.name, "Loaded JWT provider");
+            named.insert(provider.name.clone(), Arc::new(jwt_provider));
+        }
+
+        let default = JwtProvider::from_config(
+            DEFAULT_PROVIDER,
+            identity.jwt.as_ref(),
+            JwtValidationConfig::default(),
+            secrets,
+            http_client,
+        )
+        .await;
+        let default = match default {
+            Ok(provider) => Some(Arc::new(provider)),
+            // Only the implicit HS256 provider may be skipped, and only when
+            // named providers exist; it needs JWT_SECRET.
+            Err(e) if identity.jwt.is_none() && !named.is_empty() => {
+                warn!("Default JWT provider disabled: {}", e);
+                None
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self { default, named })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<JwtProvider>> {
+        self.named.get(name)
+    }
+
+    /// Picks the provider that should verify `token` among the route's allowed
+    /// providers, preferring an `iss` match, then a JWKS that knows the `kid`.
+    pub fn select(
+        &self,
+        token: &str,
+        header: &Header,
+        allowed: Option<&[String]>,
+    ) -> Result<&Arc<JwtProvider>, AppError> {
+        let Some(allowed) = allowed else {
+            return self.default.as_ref().ok_or_else(|| {
+                AppError::AuthFailed("No JWT provider is configured for this route.".to_string())
+            });
+        };
+
+        let candidates: Vec<&Arc<JwtProvider>> = allowed
+            .iter()
+            .filter_map(|name| {
+                let provider = self.named.get(name);
+                if provider.is_none() {
+                    warn!(provider = %name, "Route references an unknown JWT provider");
+                }
+                provider
+            })
+            .collect();
+
+        if let Some(issuer) = unverified_issuer(token)
+            && let Some(provider) = candidates
+                .iter()
+                .find(|p| p.validation.issuers.contains(&issuer))
+        {
+            return Ok(provider);
+        }
+
+        if let Some(kid) = &header.kid
+            && let Some(provider) = candidates.iter().find(|p| p.knows_kid(kid))
+        {
+            return Ok(provider);
+        }
+
+        match candidates[..] {
+            [provider] => Ok(provider),
+            _ => Err(AppError::AuthFailed(
+                "JWT issuer is not accepted for this route.".to_string(),
+            )),
+        }
+    }
+}
+
+// Reads `iss` without verifying the signature; only used to pick a provider,
+// whose key then verifies the token.
+fn unverified_issuer(token: &str) -> Option<String> {
+    let payload = token.split('.').nth(1)?;
+    let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    let claims: Value = serde_json::from_slice(&payload).ok()?;
+    claims.get("iss")?.as_str().map(str::to_string)
+}
+
+enum KeySource {
+    // Shared secret or a single PEM public key
+    Static(DecodingKey),
+    Jwks(Arc<JwksStore>),
+}
+
+// Knows which algorithms a token may use, where its verification key comes
+// from and how its claims are validated and mapped.
+pub struct JwtProvider {
+    pub name: String,
+    pub algorithms: Vec<Algorithm>,
+    pub validation: JwtValidationConfig,
+    keys: KeySource,
+}
+
+impl JwtProvider {
+    pub async fn from_config(
+        name: &str,
+        config: Option<&JwtConfig>,
+        validation: JwtValidationConfig,
+        secrets: &SecretsConfig,
+        http_client: Client,
+    ) -> Result<Self, Error> {
+        let Some(config) = config else {
+            // No key configuration: HS256 with JWT_SECRET, as before.
+            return Ok(Self {
+                name: name.to_string(),
+                algorithms: vec![Algorithm::HS256],
+                validation,
+                keys: KeySource::Static(hmac_key(secrets)?),
+            });
+        };
+
+        let families = key_families(&config.algorithms)?;
+        let hmac = families == [KeyFamily::Hmac];
+
+        let keys = match (&config.public_key_path, &config.jwks) {
+            (None, None) if hmac => KeySource::Static(hmac_key(secrets)?),
+            _ if hmac => {
+                return Err(anyhow!(
+                    "HMAC algorithms use JWT_SECRET, not a public key or JWKS"
+                ));
+            }
+            (Some(path), None) => {
+                let [family] = families[..] else {
+                    return Err(anyhow!(
+                        "A single public key cannot verify {:?}; use a JWKS instead",
+                        config.algorithms
+                    ));
+                };
+                let pem = std::fs::read(path)
+                    .map_err(|e| anyhow!("Failed to read JWT public key {}: {}", path, e))?;
+                let key = match family {
+                    KeyFamily::Rsa => DecodingKey::from_rsa_pem(&pem),
+                    KeyFamily::Ec => DecodingKey::from_ec_pem(&pem),
+                    KeyFamily::Ed | KeyFamily::Hmac => DecodingKey::from_ed_pem(&pem),
+                }
+                .map_err(|e| anyhow!("Invalid JWT public key {}: {}", path, e))?;
+                KeySource::Static(key)
+            }
+            (None, Some(jwks)) => KeySource::Jwks(JwksStore::load(jwks, http_client).await?),
+            _ => {
+                return Err(anyhow!(
+                    "Public key JWT algorithms need exactly one of 'public_key_path' or 'jwks'"
+                ));
+            }
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            algorithms: config.algorithms.clone(),
+            validation,
+            keys,
+        })
+    }
+
+    fn knows_kid(&self, kid: &str) -> bool {
+        match &self.keys {
+            KeySource::Static(_) => false,
+            KeySource::Jwks(store) => store.has_key(kid),
+        }
+    }
+
+    /// The provider's validation with a route's overrides applied.
+    pub fn validation_for(
+        &self,
+        overrides: Option<&JwtValidationOverrides>,
+    ) -> Cow<'_, JwtValidationConfig> {
+        match overrides {
+            Some(overrides) => Cow::Owned(overrides.apply(&self.validation)),
+            None => Cow::Borrowed(&self.validation),
+        }
+    }
+
+    pub async fn decoding_key(&self, header: &Header) -> Result<DecodingKey, AppError> {
+        if !self.algorithms.contains(&header.alg) {
+            return Err(AppError::AuthFailed(
+                "JWT algorithm not allowed.".to_string(),
+            ));
+        }
+
+        match &self.keys {
+            KeySource::Static(key) => Ok(key.clone()),
+            KeySource::Jwks(store) => store
+                .key_for(header.kid.as_deref())
+                .await
+                .ok_or_else(|| AppError::AuthFailed("Unknown JWT signing key.".to_string())),
+        }
+    }
+}
+
+fn hmac_key(secrets: &SecretsConfig) -> Result<DecodingKey, Error> {
+    let secret = secrets
+        .jwt_secret
+        .as_ref()
+        .ok_or_else(|| anyhow!("JWT_SECRET must be set in .env file"))?;
+    Ok(DecodingKey::from_secret(secret.as_ref()))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum KeyFamily {
+    Hmac,
+    Rsa,
+    Ec,
+    Ed,
+}
+
+fn family(algorithm: Algorithm) -> KeyFamily {
+    match algorithm {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => KeyFamily::Hmac,
+        Algorithm::ES256 | Algorithm::ES384 => KeyFamily::Ec,
+        Algorithm::EdDSA => KeyFamily::Ed,
+        _ => KeyFamily::Rsa,
+    }
+}
+
+// HMAC secrets and public keys cannot be mixed within one provider.
+fn key_families(algorithms: &[Algorithm]) -> Result<Vec<KeyFamily>, Error> {
+    let mut families: Vec<KeyFamily> = Vec::new();
+    for algorithm in algorithms {
+        let family = family(*algorithm);
+        if !families.contains(&family) {
+            families.push(family);
+        }
+    }
+
+    match families[..] {
+        [] => Err(anyhow!("At least one JWT algorithm must be configured")),
+        [_] => Ok(families),
+        _ if families.contains(&KeyFamily::Hmac) => Err(anyhow!(
+            "JWT algorithms {:?} mix HMAC and public key algorithms",
+            algorithms
+        )),
+        _ => Ok(families),
+    }
+}
@@ -0,0 +1,333 @@
+// Where API key entries live. The YAML file is read into memory and hot
+// reloaded; SQLite suits large key sets and keeps recently used entries in a
+// small in-memory LRU. Entries are keyed by key ID (`gw_<id>`), or by the key
+// itself for legacy plaintext entries.
+
+use std::{
+    fs,
+    io::{self, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Error, anyhow};
+use async_trait::async_trait;
+use moka::future::Cache;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params};
+use tokio::sync::RwLock;
+use tracing::info;
+
+use crate::{
+    config::{
+        ApiKeyDetails, ApiKeyStore, IdentityConfig, KeyCacheConfig, KeyStatus, KeyStoreBackend,
+    },
+    features::auth::api_key,
+    middleware::rate_limiter::rate_limit::parse_duration,
+};
+
+#[async_trait]
+pub trait KeyStore: Send + Sync {
+    async fn get(&self, id: &str) -> Result<Option<ApiKeyDetails>, Error>;
+    async fn list(&self) -> Result<Vec<(String, ApiKeyDetails)>, Error>;
+    // Fails if an entry with the ID already exists
+    async fn create(&self, id: &str, details: ApiKeyDetails) -> Result<(), Error>;
+    // Returns false if there is no such entry
+    async fn revoke(&self, id: &str) -> Result<bool, Error>;
+    // Pepper for `hmac-sha256:` digests, from `API_KEY_HMAC_SECRET`
+    fn hmac_secret(&self) -> Option<&[u8]>;
+}
+
+// The YAML store is also returned on its own, for the hot reloader; database
+// stores are read on demand
+pub type OpenedKeyStore = (Arc<dyn KeyStore>, Option<Arc<YamlKeyStore>>);
+
+/// Opens the store `identity` configures.
+pub fn open_key_store(identity: &IdentityConfig) -> Result<OpenedKeyStore, Error> {
+    let path = &identity.api_key_store_path;
+    match identity.api_key_store_backend {
+        KeyStoreBackend::Yaml => {
+            let store = Arc::new(YamlKeyStore::load(path)?);
+            Ok((store.clone(), Some(store)))
+        }
+        KeyStoreBackend::Sqlite => Ok((
+            Arc::new(SqliteKeyStore::open(path, &identity.api_key_cache)?),
+            None,
+        )),
+    }
+}
+
+pub struct YamlKeyStore {
+    // Unset for stores that only live in memory
+    path: Option<PathBuf>,
+    store: RwLock<ApiKeyStore>,
+    hmac_secret: Option<Vec<u8>>,
+}
+
+impl From<ApiKeyStore> for YamlKeyStore {
+    fn from(store: ApiKeyStore) -> Self {
+        Self {
+            path: None,
+            hmac_secret: store.hmac_secret.clone(),
+            store: RwLock::new(store),
+        }
+    }
+}
+
+impl YamlKeyStore {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let store = ApiKeyStore::load(&path)?;
+        Ok(Self {
+            path: Some(path.as_ref().to_path_buf()),
+            ..Self::from(store)
+        })
+    }
+
+    /// Re-reads the file after it changed on disk.
+    pub async fn reload(&self) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        *self.store.write().await = ApiKeyStore::load(path)?;
+        Ok(())
+    }
+
+    // Writes a uniquely named sibling with the original file's permissions,
+    // syncs it and renames it over the store, so neither a concurrent writer
+    // nor a crash leaves a partial or world-readable file behind.
+    fn save(&self, store: &ApiKeyStore) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mode = match fs::metadata(path) {
+            Ok(metadata) => metadata.permissions().mode() & 0o7777,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0o600,
+            Err(e) => return Err(e.into()),
+        };
+
+        let (tmp, mut file) = loop {
+            let mut tmp = path.clone().into_os_string();
+            tmp.push(format!(".{:016x}.tmp", rand::random::<u64>()));
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(mode)
+                .open(&tmp)
+            {
+                Ok(file) => break (PathBuf::from(tmp), file),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        let written = (|| -> Result<(), Error> {
+            // The umask may have narrowed the mode requested at creation
+            file.set_permissions(fs::Permissions::from_mode(mode))?;
+            file.write_all(serde_yaml::to_string(store)?.as_bytes())?;
+            file.sync_all()?;
+            fs::rename(&tmp, path)?;
+            Ok(())
+        })();
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        written
+    }
+}
+
+#[async_trait]
+impl KeyStore for YamlKeyStore {
+    async fn get(&self, id: &str) -> Result<Option<ApiKeyDetails>, Error> {
+        Ok(self.store.read().await.keys.get(id).cloned())
+    }
+
+    async fn list(&self) -> Result<Vec<(String, ApiKeyDetails)>, Error> {
+        let store = self.store.read().await;
+        let mut entries: Vec<_> = store
+            .keys
+            .iter()
+            .map(|(id, details)| (id.clone(), details.clone()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(entries)
+    }
+
+    async fn create(&self, id: &str, details: ApiKeyDetails) -> Result<(), Error> {
+        let mut store = self.store.write().await;
+        if store.keys.contains_key(id) {
+            return Err(anyhow!("API key '{}' already exists", id));
+        }
+        store.keys.insert(id.to_string(), details);
+        self.save(&store)
+    }
+
+    async fn revoke(&self, id: &str) -> Result<bool, Error> {
+        let mut store = self.store.write().await;
+        let Some(details) = store.keys.get_mut(id) else {
+            return Ok(false);
+        };
+        details.status = KeyStatus::Revoked;
+        self.save(&store)?;
+        Ok(true)
+    }
+
+    fn hmac_secret(&self) -> Option<&[u8]> {
+        self.hmac_secret.as_deref()
+    }
+}
+
+// One row per entry; `details` holds the entry as JSON, so columns don't have
+// to follow every new key attribute.
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS api_keys (
+    id TEXT PRIMARY KEY,
+    details TEXT NOT NULL
+)";
+
+pub struct SqliteKeyStore {
+    connection: Arc<Mutex<Connection>>,
+    // Only entries that exist are cached; misses are keyed by whatever a
+    // client sent and would let it fill the cache
+    cache: Cache<String, ApiKeyDetails>,
+    hmac_secret: Option<Vec<u8>>,
+}
+
+impl SqliteKeyStore {
+    pub fn open<P: AsRef<Path>>(path: P, cache: &KeyCacheConfig) -> Result<Self, Error> {
+        let ttl = parse_duration(&cache.ttl)
+            .map_err(|e| anyhow!("Invalid api_key_cache.ttl '{}': {}", cache.ttl, e))?;
+        let connection = Connection::open(&path)?;
+        connection.execute(SCHEMA, [])?;
+        info!(path = ?path.as_ref(), "Opened SQLite API key store");
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            cache: Cache::builder()
+                .max_capacity(cache.capacity)
+                .time_to_live(ttl)
+                .build(),
+            hmac_secret: std::env::var(api_key::HMAC_SECRET_ENV)
+                .ok()
+                .map(String::into_bytes),
+        })
+    }
+
+    /// Checks that the database at `path` holds a key table, without creating
+    /// or changing anything.
+    pub fn check<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+        let connection = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        connection.query_row("SELECT COUNT(*) FROM api_keys", [], |row| {
+            row.get::<_, i64>(0)
+        })?;
+        Ok(())
+    }
+
+    // SQLite calls block, so they run off the async workers
+    async fn with_connection<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, Error> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let connection = connection
+                .lock()
+                .map_err(|_| anyhow!("SQLite connection lock poisoned"))?;
+            f(&connection)
+        })
+        .await?
+    }
+}
+
+#[async_trait]
+impl KeyStore for SqliteKeyStore {
+    async fn get(&self, id: &str) -> Result<Option<ApiKeyDetails>, Error> {
+        if let Some(details) = self.cache.get(id).await {
+            return Ok(Some(details));
+        }
+
+        let owned_id = id.to_string();
+        let details: Option<ApiKeyDetails> = self
+            .with_connection(move |connection| {
+                let details: Option<String> = connection
+                    .query_row(
+                        "SELECT details FROM api_keys WHERE id = ?1",
+                        params![owned_id],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                Ok(details
+                    .map(|details| serde_json::from_str(&details))
+                    .transpose()?)
+            })
+            .await?;
+        if let Some(details) = &details {
+            self.cache.insert(id.to_string(), details.clone()).await;
+        }
+        Ok(details)
+    }
+
+    async fn list(&self) -> Result<Vec<(String, ApiKeyDetails)>, Error> {
+        self.with_connection(|connection| {
+            let mut statement =
+                connection.prepare("SELECT id, details FROM api_keys ORDER BY id")?;
+            let rows = statement.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?;
+            let mut entries = Vec::new();
+            for row in rows {
+                let (id, details) = row?;
+                entries.push((id, serde_json::from_str(&details)?));
+            }
+            Ok(entries)
+        })
+        .await
+    }
+
+    async fn create(&self, id: &str, details: ApiKeyDetails) -> Result<(), Error> {
+        let owned_id = id.to_string();
+        let details = serde_json::to_string(&details)?;
+        self.with_connection(move |connection| {
+            connection
+                .execute(
+                    "INSERT INTO api_keys (id, details) VALUES (?1, ?2)",
+                    params![owned_id, details],
+                )
+                .map_err(|e| anyhow!("Failed to create API key '{}': {}", owned_id, e))?;
+            Ok(())
+        })
+        .await?;
+        self.cache.invalidate(id).await;
+        Ok(())
+    }
+
+    async fn revoke(&self, id: &str) -> Result<bool, Error> {
+        let owned_id = id.to_string();
+        let revoked = self
+            .with_connection(move |connection| {
+                let details: Option<String> = connection
+                    .query_row(
+                        "SELECT details FROM api_keys WHERE id = ?1",
+                        params![owned_id],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                let Some(details) = details else {
+                    return Ok(false);
+                };
+                let mut details: ApiKeyDetails = serde_json::from_str(&details)?;
+                details.status = KeyStatus::Revoked;
+                connection.execute(
+                    "UPDATE api_keys SET details = ?2 WHERE id = ?1",
+                    params![owned_id, serde_json::to_string(&details)?],
+                )?;
+                Ok(true)
+            })
+            .await?;
+        self.cache.invalidate(id).await;
+        Ok(revoked)
+    }
+
+    fn hmac_secret(&self) -> Option<&[u8]> {
+        self.hmac_secret.as_deref()
+    }
+}
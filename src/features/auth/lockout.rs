@@ -0,0 +1,226 @@
+// Brute-force protection. Authentication failures drain a bucket per client IP
+// and per presented credential; each failure delays the response a little
+// more, and a drained bucket is locked out. A locked out client IP is turned
+// away before verification. Key IDs and usernames are not secret, so a locked
+// out credential only turns away failed attempts: the holder of the real
+// credential still gets in.
+//
+// Credential buckets are keyed by a digest, since clients choose what they
+// present, and are forgotten once they have refilled.
+//
+// Only ApiKey, Jwt and Basic routes count failures. `Signature` routes check
+// an HMAC no client can guess, and `External` routes leave the decision, and
+// any throttling, to the authorization service.
+
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use axum_prometheus::metrics::counter;
+use http::{HeaderMap, Uri};
+use moka::future::Cache;
+use tracing::warn;
+
+use crate::{
+    config::{AuthConfig, AuthType, LockoutConfig},
+    errors::AppError,
+    features::auth::{
+        api_key::{KEY_PREFIX, hash_key},
+        basic::{MISSING_CREDENTIALS, basic_credentials},
+        credentials::extract_credential,
+        expiry::{EntryExpiry, EntryTtl},
+    },
+    middleware::rate_limiter::rate_limit::parse_duration,
+};
+
+struct FailureBucket {
+    key: String,
+    // "ip" or "credential", for metrics
+    scope: &'static str,
+    threshold: u64,
+}
+
+/// The buckets one authentication attempt counts against.
+pub struct AuthAttempt {
+    buckets: Vec<FailureBucket>,
+    config: LockoutConfig,
+}
+
+impl AuthAttempt {
+    pub fn new(
+        client_ip: Option<IpAddr>,
+        credential: Option<String>,
+        config: &LockoutConfig,
+    ) -> Self {
+        let ip = client_ip.map(|ip| FailureBucket {
+            key: format!("auth_failures:ip:{}", ip),
+            scope: "ip",
+            threshold: config.ip_failures,
+        });
+        let credential = credential.map(|credential| FailureBucket {
+            key: format!("auth_failures:credential:{}", hash_key(&credential, None)),
+            scope: "credential",
+            threshold: config.credential_failures,
+        });
+        Self {
+            buckets: ip.into_iter().chain(credential).collect(),
+            config: config.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Failures {
+    // Failures still allowed, refilling over the window
+    left: f64,
+    last_failure: Instant,
+    window: Duration,
+}
+
+// A bucket has refilled a window after its last failure
+impl EntryTtl for Failures {
+    fn ttl(&self) -> Duration {
+        self.window.saturating_sub(self.last_failure.elapsed())
+    }
+}
+
+pub struct Lockouts {
+    failures: Cache<String, Failures>,
+    // Locked out buckets, until their lockout ends
+    locked: Cache<String, Instant>,
+}
+
+impl Default for Lockouts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lockouts {
+    pub fn new() -> Self {
+        Self {
+            failures: Cache::builder()
+                .max_capacity(1_000_000)
+                .expire_after(EntryExpiry)
+                .build(),
+            locked: Cache::builder()
+                .max_capacity(1_000_000)
+                .expire_after(EntryExpiry)
+                .build(),
+        }
+    }
+
+    /// Turns attempts away while their client IP is locked out.
+    pub async fn check(&self, attempt: &AuthAttempt) -> Result<(), AppError> {
+        for bucket in attempt.buckets.iter().filter(|b| b.scope == "ip") {
+            if let Some(until) = self.locked.get(&bucket.key).await {
+                let retry_after = until.saturating_duration_since(Instant::now());
+                return Err(AppError::LockedOut {
+                    retry_after: retry_after.as_secs().max(1),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts a failed attempt, then either locks out the buckets it drained,
+    /// including ones already locked out, or waits out the delay for the
+    /// failures seen so far.
+    pub async fn record_failure(&self, attempt: &AuthAttempt) -> Result<(), AppError> {
+        counter!("gateway_auth_failures_total").increment(1);
+        let config = &attempt.config;
+        let window = parse_duration(&config.window).unwrap_or(Duration::from_secs(900));
+        let duration = parse_duration(&config.duration).unwrap_or(Duration::from_secs(900));
+
+        let mut recent_failures = 0.0f64;
+        let mut locked_out = false;
+        for bucket in &attempt.buckets {
+            let left = self.count_failure(bucket, window).await;
+            recent_failures = recent_failures.max(bucket.threshold as f64 - left);
+
+            if left < 1.0 {
+                warn!(bucket = %bucket.key, duration = ?duration, "Locked out after repeated authentication failures");
+                counter!("gateway_auth_lockouts_total", "scope" => bucket.scope).increment(1);
+                self.locked
+                    .insert(bucket.key.clone(), Instant::now() + duration)
+                    .await;
+                locked_out = true;
+            }
+        }
+        if locked_out {
+            return Err(AppError::LockedOut {
+                retry_after: duration.as_secs().max(1),
+            });
+        }
+
+        // The first failure is free; each further one adds a step
+        let step = parse_duration(&config.delay).unwrap_or(Duration::from_secs(1));
+        let max_delay = parse_duration(&config.max_delay).unwrap_or(Duration::from_secs(5));
+        let delay = step
+            .mul_f64((recent_failures - 1.0).round().max(0.0))
+            .min(max_delay);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        Ok(())
+    }
+
+    // Takes one of the bucket's allowed failures and returns how many are left
+    async fn count_failure(&self, bucket: &FailureBucket, window: Duration) -> f64 {
+        let threshold = bucket.threshold as f64;
+        let refill_rate = threshold / window.as_secs_f64();
+        self.failures
+            .entry(bucket.key.clone())
+            .and_upsert_with(|existing| async move {
+                let left = existing.map_or(threshold, |entry| {
+                    let failures = entry.into_value();
+                    let refilled = failures.last_failure.elapsed().as_secs_f64() * refill_rate;
+                    (failures.left + refilled).min(threshold)
+                });
+                Failures {
+                    left: (left - 1.0).max(0.0),
+                    last_failure: Instant::now(),
+                    window,
+                }
+            })
+            .await
+            .into_value()
+            .left
+    }
+}
+
+/// Identifies the presented credential across guesses: the ID of a `gw_` API
+/// key or the Basic username. Other credentials are only counted per IP.
+pub fn presented_credential(
+    headers: &HeaderMap,
+    uri: &Uri,
+    auth_config: &AuthConfig,
+) -> Option<String> {
+    match auth_config.auth_type {
+        AuthType::ApiKey => {
+            let key = extract_credential(headers, uri, &auth_config.credentials).ok()?;
+            key_id(&key)
+        }
+        AuthType::Basic => basic_credentials(headers).map(|(username, _)| username),
+        _ => None,
+    }
+}
+
+/// The `gw_<id>` part of a presented API key.
+pub fn key_id(key: &str) -> Option<String> {
+    key.split_once('.')
+        .map(|(id, _)| id)
+        .filter(|id| id.starts_with(KEY_PREFIX))
+        .map(str::to_string)
+}
+
+/// Failures that suggest a guessed credential, as opposed to a missing or
+/// merely expired one.
+pub fn is_guess(error: &AppError) -> bool {
+    match error {
+        AppError::AuthFailed(_) => true,
+        AppError::BasicAuthFailed { reason, .. } => reason != MISSING_CREDENTIALS,
+        _ => false,
+    }
+}
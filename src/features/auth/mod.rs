@@ -1,2 +1,18 @@
+pub mod api_key;
 #[allow(clippy::module_inception)]
 pub mod auth;
+pub mod basic;
+pub mod credentials;
+pub mod expiry;
+pub mod external;
+pub mod introspection;
+pub mod jwks;
+pub mod jwt;
+pub mod key_store;
+pub mod lockout;
+pub mod oidc;
+pub mod policy;
+pub mod propagation;
+pub mod revocation;
+pub mod signature;
+pub mod token_issuer;
@@ -0,0 +1,583 @@
+// OpenID Connect authorization code flow with PKCE for browser routes. Tokens
+// never reach the browser: sessions are kept server-side and the browser only
+// holds an opaque, HttpOnly session cookie.
+//
+// Sessions and pending logins live in this process's memory only. They do not
+// survive a restart or a SIGUSR2 handoff, and with `reuse_port` a provider
+// callback may reach a process that never saw the login; users then simply
+// log in again.
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::Query,
+    response::{IntoResponse, Redirect, Response},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use http::{HeaderMap, HeaderValue, Method, Uri, header};
+use jsonwebtoken::{decode, decode_header};
+use moka::future::Cache;
+use rand::RngCore;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use tracing::{error, info, warn};
+
+use crate::{
+    config::{AuthConfig, OidcConfig, RouteConfig},
+    errors::AppError,
+    features::auth::{
+        auth::{Claims, build_validation, jwt_error, map_claims},
+        expiry::{EntryExpiry, EntryTtl},
+        jwt::JwtProviders,
+    },
+    middleware::rate_limiter::rate_limit::parse_duration,
+};
+
+// How long a user has to complete the login at the provider
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(600);
+// Upper bound for a single call to the token endpoint
+const TOKEN_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// How long a refresh result is handed to requests that still present the
+// session as it was before the refresh
+const REFRESH_GRACE: Duration = Duration::from_secs(30);
+
+struct PendingLogin {
+    client_id: String,
+    code_verifier: String,
+    nonce: String,
+    // Path and query the user originally asked for
+    return_to: String,
+}
+
+struct Session {
+    client_id: String,
+    claims: Claims,
+    id_token: String,
+    refresh_token: Option<String>,
+    ttl: Duration,
+}
+
+impl EntryTtl for Session {
+    fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+}
+
+pub struct OidcSessions {
+    http_client: Client,
+    // Keyed by the `state` parameter
+    pending: Cache<String, Arc<PendingLogin>>,
+    // Keyed by the session cookie value
+    sessions: Cache<String, Arc<Session>>,
+    // Keyed by the refresh token being redeemed, so concurrent requests of a
+    // session share one refresh instead of spending a single-use token twice
+    refreshes: Cache<String, Arc<Session>>,
+}
+
+impl OidcSessions {
+    pub fn new(http_client: Client) -> Self {
+        Self {
+            http_client,
+            pending: Cache::builder()
+                .max_capacity(100_000)
+                .time_to_live(LOGIN_TIMEOUT)
+                .build(),
+            sessions: Cache::builder()
+                .max_capacity(100_000)
+                .expire_after(EntryExpiry)
+                .build(),
+            refreshes: Cache::builder()
+                .max_capacity(100_000)
+                .time_to_live(REFRESH_GRACE)
+                .build(),
+        }
+    }
+
+    /// Returns the claims of the session named by the request's cookie,
+    /// refreshing its tokens first when the ID token has expired.
+    pub async fn verify_session(
+        &self,
+        headers: &HeaderMap,
+        auth_config: &AuthConfig,
+        jwt_providers: &JwtProviders,
+    ) -> Result<Claims, AppError> {
+        let config = oidc_config(auth_config)?;
+        let session_id = cookie(headers, &config.cookie_name).ok_or(AppError::MissingAuthToken)?;
+        let session = self
+            .sessions
+            .get(session_id)
+            .await
+            .filter(|session| session.client_id == config.client_id)
+            .ok_or_else(|| AppError::AuthFailed("Session is not valid.".to_string()))?;
+
+        let expired = session.claims.exp != 0 && session.claims.exp as u64 <= now();
+        match &session.refresh_token {
+            Some(refresh_token) if expired => {
+                let refreshed = self
+                    .refreshes
+                    .try_get_with(
+                        refresh_token.clone(),
+                        self.refresh(&session, refresh_token, auth_config, jwt_providers),
+                    )
+                    .await;
+                match refreshed {
+                    Ok(refreshed) => {
+                        let claims = refreshed.claims.clone();
+                        self.sessions
+                            .insert(session_id.to_string(), refreshed)
+                            .await;
+                        Ok(claims)
+                    }
+                    Err(_) => {
+                        self.sessions.invalidate(session_id).await;
+                        Err(AppError::AuthFailed("Session has expired.".to_string()))
+                    }
+                }
+            }
+            // Without a refresh token the session lives for `session_ttl`
+            _ => Ok(session.claims.clone()),
+        }
+    }
+
+    /// Answers the callback and logout paths of an `Oidc` route; `None` for
+    /// any other request.
+    pub async fn handle_endpoint(
+        &self,
+        uri: &Uri,
+        headers: &HeaderMap,
+        route: &RouteConfig,
+        auth_config: &AuthConfig,
+        jwt_providers: &JwtProviders,
+    ) -> Result<Option<Response>, AppError> {
+        let config = oidc_config(auth_config)?;
+        let path = uri.path();
+
+        let callback_path = Url::parse(&config.redirect_uri)
+            .map(|url| url.path().to_string())
+            .map_err(|_| {
+                error!(redirect_uri = %config.redirect_uri, "Invalid OIDC redirect_uri");
+                AppError::InternalServerError
+            })?;
+        if path == callback_path {
+            return self
+                .callback(uri, headers, route, auth_config, config, jwt_providers)
+                .await
+                .map(Some);
+        }
+
+        if path == format!("{}{}", route.path.trim_end_matches('/'), config.logout_path) {
+            return self.logout(headers, route, config).await.map(Some);
+        }
+
+        Ok(None)
+    }
+
+    /// Starts a login by redirecting the browser to the authorization endpoint.
+    pub async fn login(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        route: &RouteConfig,
+        auth_config: &AuthConfig,
+    ) -> Result<Response, AppError> {
+        let config = oidc_config(auth_config)?;
+        // Only navigations can follow a redirect to the login page.
+        if method != Method::GET && method != Method::HEAD {
+            return Err(AppError::AuthFailed("Login required.".to_string()));
+        }
+
+        let state = random_token();
+        let nonce = random_token();
+        let code_verifier = random_token();
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let mut authorize_url = Url::parse(&config.authorization_endpoint).map_err(|_| {
+            error!(endpoint = %config.authorization_endpoint, "Invalid OIDC authorization_endpoint");
+            AppError::InternalServerError
+        })?;
+        authorize_url
+            .query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &config.client_id)
+            .append_pair("redirect_uri", &config.redirect_uri)
+            .append_pair("scope", &config.scopes.join(" "))
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce)
+            .append_pair("code_challenge", &code_challenge)
+            .append_pair("code_challenge_method", "S256");
+
+        let return_to = uri
+            .path_and_query()
+            .map(|p| p.to_string())
+            .unwrap_or_else(|| route.path.clone());
+        self.pending
+            .insert(
+                state.clone(),
+                Arc::new(PendingLogin {
+                    client_id: config.client_id.clone(),
+                    code_verifier,
+                    nonce,
+                    return_to,
+                }),
+            )
+            .await;
+
+        // Binds the login to this browser so a callback cannot be replayed
+        // into someone else's (login CSRF).
+        let state_cookie = set_cookie(
+            &state_cookie_name(config),
+            &state,
+            route,
+            config,
+            LOGIN_TIMEOUT,
+        );
+        Ok(with_cookies(
+            Redirect::to(authorize_url.as_str()),
+            [state_cookie],
+        ))
+    }
+
+    async fn callback(
+        &self,
+        uri: &Uri,
+        headers: &HeaderMap,
+        route: &RouteConfig,
+        auth_config: &AuthConfig,
+        config: &OidcConfig,
+        jwt_providers: &JwtProviders,
+    ) -> Result<Response, AppError> {
+        let Query(params) = Query::<HashMap<String, String>>::try_from_uri(uri)
+            .map_err(|_| AppError::AuthFailed("Invalid OIDC callback.".to_string()))?;
+        if let Some(error) = params.get("error") {
+            warn!(error = %error, "OIDC provider returned an error");
+            return Err(AppError::AuthFailed("Login was not completed.".to_string()));
+        }
+
+        let (Some(code), Some(state)) = (params.get("code"), params.get("state")) else {
+            return Err(AppError::AuthFailed("Invalid OIDC callback.".to_string()));
+        };
+        if cookie(headers, &state_cookie_name(config)) != Some(state.as_str()) {
+            return Err(AppError::AuthFailed("OIDC state mismatch.".to_string()));
+        }
+        let pending = self
+            .pending
+            .remove(state)
+            .await
+            .filter(|pending| pending.client_id == config.client_id)
+            .ok_or_else(|| AppError::AuthFailed("Login has expired.".to_string()))?;
+
+        let tokens = self
+            .token_request(
+                config,
+                &[
+                    ("grant_type", "authorization_code"),
+                    ("code", code),
+                    ("redirect_uri", &config.redirect_uri),
+                    ("code_verifier", &pending.code_verifier),
+                ],
+            )
+            .await?;
+        let id_token = tokens
+            .id_token
+            .ok_or_else(|| AppError::AuthFailed("Provider returned no ID token.".to_string()))?;
+        let claims = verify_id_token(
+            &id_token,
+            auth_config,
+            config,
+            jwt_providers,
+            Some(&pending.nonce),
+        )
+        .await?;
+
+        let ttl = parse_duration(&config.session_ttl).unwrap_or(Duration::from_secs(8 * 3600));
+        let session_id = random_token();
+        info!(sub = %claims.sub, route = %route.name, "OIDC login completed");
+        self.sessions
+            .insert(
+                session_id.clone(),
+                Arc::new(Session {
+                    client_id: config.client_id.clone(),
+                    claims,
+                    id_token,
+                    refresh_token: tokens.refresh_token,
+                    ttl,
+                }),
+            )
+            .await;
+
+        Ok(with_cookies(
+            Redirect::to(&pending.return_to),
+            [
+                set_cookie(&config.cookie_name, &session_id, route, config, ttl),
+                set_cookie(
+                    &state_cookie_name(config),
+                    "",
+                    route,
+                    config,
+                    Duration::ZERO,
+                ),
+            ],
+        ))
+    }
+
+    async fn logout(
+        &self,
+        headers: &HeaderMap,
+        route: &RouteConfig,
+        config: &OidcConfig,
+    ) -> Result<Response, AppError> {
+        let session = match cookie(headers, &config.cookie_name) {
+            Some(session_id) => self.sessions.remove(session_id).await,
+            None => None,
+        };
+
+        let target = match (&config.end_session_endpoint, &session) {
+            (Some(endpoint), Some(session)) => {
+                let mut url = Url::parse(endpoint).map_err(|_| {
+                    error!(endpoint = %endpoint, "Invalid OIDC end_session_endpoint");
+                    AppError::InternalServerError
+                })?;
+                url.query_pairs_mut()
+                    .append_pair("id_token_hint", &session.id_token)
+                    .append_pair("client_id", &config.client_id);
+                if let Some(uri) = &config.post_logout_redirect_uri {
+                    url.query_pairs_mut()
+                        .append_pair("post_logout_redirect_uri", uri);
+                }
+                url.to_string()
+            }
+            _ => config
+                .post_logout_redirect_uri
+                .clone()
+                .unwrap_or_else(|| "/".to_string()),
+        };
+
+        Ok(with_cookies(
+            Redirect::to(&target),
+            [set_cookie(
+                &config.cookie_name,
+                "",
+                route,
+                config,
+                Duration::ZERO,
+            )],
+        ))
+    }
+
+    async fn refresh(
+        &self,
+        session: &Session,
+        refresh_token: &str,
+        auth_config: &AuthConfig,
+        jwt_providers: &JwtProviders,
+    ) -> Result<Arc<Session>, AppError> {
+        let config = oidc_config(auth_config)?;
+        let tokens = self
+            .token_request(
+                config,
+                &[
+                    ("grant_type", "refresh_token"),
+                    ("refresh_token", refresh_token),
+                ],
+            )
+            .await
+            .map_err(|_| AppError::AuthFailed("Session has expired.".to_string()))?;
+
+        let (claims, id_token) = match tokens.id_token {
+            // Refreshed ID tokens carry no nonce.
+            Some(id_token) => (
+                verify_id_token(&id_token, auth_config, config, jwt_providers, None).await?,
+                id_token,
+            ),
+            None => {
+                let mut claims = session.claims.clone();
+                claims.exp = tokens.expires_in.map_or(0, |expires_in| now() + expires_in) as usize;
+                (claims, session.id_token.clone())
+            }
+        };
+
+        Ok(Arc::new(Session {
+            client_id: session.client_id.clone(),
+            claims,
+            id_token,
+            // Providers that rotate refresh tokens return a new one.
+            refresh_token: tokens
+                .refresh_token
+                .or_else(|| Some(refresh_token.to_string())),
+            ttl: session.ttl,
+        }))
+    }
+
+    async fn token_request(
+        &self,
+        config: &OidcConfig,
+        params: &[(&str, &str)],
+    ) -> Result<TokenResponse, AppError> {
+        let mut request = self.http_client.post(&config.token_endpoint);
+        let mut form = params.to_vec();
+        match std::env::var(&config.client_secret_env) {
+            Ok(secret) => request = request.basic_auth(&config.client_id, Some(secret)),
+            Err(_) => form.push(("client_id", &config.client_id)),
+        }
+
+        request
+            .form(&form)
+            .timeout(TOKEN_REQUEST_TIMEOUT)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| {
+                error!(endpoint = %config.token_endpoint, "OIDC token request failed: {}", e);
+                AppError::AuthFailed("Login could not be completed.".to_string())
+            })?
+            .json()
+            .await
+            .map_err(|e| {
+                error!(endpoint = %config.token_endpoint, "Invalid OIDC token response: {}", e);
+                AppError::AuthFailed("Login could not be completed.".to_string())
+            })
+    }
+}
+
+async fn verify_id_token(
+    id_token: &str,
+    auth_config: &AuthConfig,
+    config: &OidcConfig,
+    jwt_providers: &JwtProviders,
+    nonce: Option<&str>,
+) -> Result<Claims, AppError> {
+    let provider = jwt_providers.get(&config.jwt_provider).ok_or_else(|| {
+        error!(provider = %config.jwt_provider, "OIDC route references an unknown JWT provider");
+        AppError::InternalServerError
+    })?;
+    let jwt_config = provider.validation_for(auth_config.jwt.as_ref());
+
+    let header = decode_header(id_token)
+        .map_err(|_| AppError::AuthFailed("Invalid ID token.".to_string()))?;
+    let key = provider.decoding_key(&header).await?;
+    let mut validation = build_validation(header.alg, &jwt_config);
+    // ID tokens are always issued to us.
+    if jwt_config.audiences.is_empty() {
+        validation.set_audience(&[&config.client_id]);
+        validation.validate_aud = true;
+    }
+
+    let token_data =
+        decode::<Map<String, Value>>(id_token, &key, &validation).map_err(jwt_error)?;
+    if let Some(nonce) = nonce
+        && token_data.claims.get("nonce").and_then(Value::as_str) != Some(nonce)
+    {
+        return Err(AppError::AuthFailed("ID token nonce mismatch.".to_string()));
+    }
+
+    let mut claims = map_claims(
+        token_data.claims,
+        &jwt_config.subject_claim,
+        &jwt_config.roles_claims,
+    )?;
+    claims.provider = Some(provider.name.clone());
+    Ok(claims)
+}
+
+fn oidc_config(auth_config: &AuthConfig) -> Result<&OidcConfig, AppError> {
+    auth_config.oidc.as_ref().ok_or_else(|| {
+        error!("Oidc route is missing 'auth.oidc'");
+        AppError::InternalServerError
+    })
+}
+
+fn state_cookie_name(config: &OidcConfig) -> String {
+    format!("{}_state", config.cookie_name)
+}
+
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Removes the gateway's own cookies from a request before it goes upstream;
+/// the session cookie is a bearer credential for the gateway only.
+pub fn strip_session_cookies(headers: &mut HeaderMap, auth_config: &AuthConfig) {
+    let Some(config) = auth_config.oidc.as_ref() else {
+        return;
+    };
+    let state_cookie = state_cookie_name(config);
+    let kept: Vec<String> = headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .map(str::trim)
+        .filter(|pair| {
+            let name = pair.split_once('=').map_or(*pair, |(name, _)| name);
+            !pair.is_empty() && name != config.cookie_name && name != state_cookie
+        })
+        .map(str::to_string)
+        .collect();
+
+    headers.remove(header::COOKIE);
+    if let Ok(value) = HeaderValue::from_str(&kept.join("; "))
+        && !kept.is_empty()
+    {
+        headers.insert(header::COOKIE, value);
+    }
+}
+
+fn set_cookie(
+    name: &str,
+    value: &str,
+    route: &RouteConfig,
+    config: &OidcConfig,
+    max_age: Duration,
+) -> HeaderValue {
+    let secure = if config.cookie_secure { "; Secure" } else { "" };
+    let cookie = format!(
+        "{}={}; Path={}; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        name,
+        value,
+        route.path,
+        max_age.as_secs(),
+        secure
+    );
+    HeaderValue::from_str(&cookie).unwrap_or_else(|_| HeaderValue::from_static(""))
+}
+
+fn with_cookies(redirect: Redirect, cookies: impl IntoIterator<Item = HeaderValue>) -> Response {
+    let mut response = redirect.into_response();
+    for cookie in cookies {
+        response.headers_mut().append(header::SET_COOKIE, cookie);
+    }
+    response
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
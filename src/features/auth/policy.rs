@@ -0,0 +1,337 @@
+// Attribute-based access rules. Each condition compares two operands:
+//
+//   <operand> == <operand>      equal (scalars compare as text)
+//   <operand> != <operand>      both present and different
+//   <operand> in <operand>      member of a list or claim array, or an IP
+//                               inside a CIDR literal
+//
+// Operands are `method`, `client_ip`, `path.<capture>`, `header.<name>`,
+// `query.<name>`, `claims.<field>[.<field>...]`, quoted literals ('admin')
+// and lists of literals (['eu', 'us']). A missing operand fails the condition.
+//
+// Rule paths are matched against the percent-decoded path with empty and dot
+// segments resolved, so `/api//users/%34%32` and `/api/./users/42` are held to
+// the same rules as `/api/users/42`. Paths that cannot be put in that form
+// (an encoded `/`, invalid UTF-8) are denied by every rule with a path.
+
+use std::{collections::HashMap, net::IpAddr};
+
+use axum::extract::Query;
+use http::{HeaderMap, Method, Uri};
+use ipnet::IpNet;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::debug;
+
+use crate::{config::PolicyRule, errors::AppError, features::auth::auth::Claims};
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(try_from = "String")]
+pub struct PolicyCondition {
+    left: Operand,
+    op: Operator,
+    right: Operand,
+    // As written in the config, for denial messages
+    source: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Method,
+    ClientIp,
+    Path(String),
+    Header(String),
+    Query(String),
+    Claim(Vec<String>),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Eq,
+    Ne,
+    In,
+}
+
+/// Request attributes the conditions are evaluated against.
+pub struct PolicyContext<'a> {
+    pub method: &'a Method,
+    pub uri: &'a Uri,
+    pub headers: &'a HeaderMap,
+    pub client_ip: Option<IpAddr>,
+    pub claims: &'a Claims,
+}
+
+/// Denies with `InsufficientPermissions` naming the first failing rule.
+pub fn evaluate(rules: &[PolicyRule], ctx: &PolicyContext) -> Result<(), AppError> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    let claims = serde_json::to_value(ctx.claims).unwrap_or(Value::Null);
+    let query = Query::<HashMap<String, String>>::try_from_uri(ctx.uri)
+        .map(|Query(query)| query)
+        .unwrap_or_default();
+
+    let segments = canonical_segments(ctx.uri.path());
+
+    for rule in rules {
+        if !matches_method(rule, ctx.method) {
+            continue;
+        }
+        let captures = match (&rule.path, &segments) {
+            (Some(pattern), Some(segments)) => match match_path(pattern, segments) {
+                Some(captures) => captures,
+                None => continue,
+            },
+            (Some(_), None) => {
+                debug!(rule = %rule.name, path = %ctx.uri.path(), "Policy denied non-canonical path");
+                return Err(AppError::InsufficientPermissions(format!(
+                    "policy '{}' cannot be checked against this path",
+                    rule.name
+                )));
+            }
+            (None, _) => HashMap::new(),
+        };
+
+        let resolver = Resolver {
+            ctx,
+            claims: &claims,
+            query: &query,
+            captures: &captures,
+        };
+        if let Some(failed) = rule.require.iter().find(|c| !resolver.holds(c)) {
+            debug!(rule = %rule.name, condition = %failed.source, "Policy denied request");
+            return Err(AppError::InsufficientPermissions(format!(
+                "policy '{}' requires {}",
+                rule.name, failed.source
+            )));
+        }
+    }
+    Ok(())
+}
+
+struct Resolver<'a> {
+    ctx: &'a PolicyContext<'a>,
+    claims: &'a Value,
+    query: &'a HashMap<String, String>,
+    captures: &'a HashMap<String, String>,
+}
+
+impl Resolver<'_> {
+    fn holds(&self, condition: &PolicyCondition) -> bool {
+        let (Some(left), Some(right)) = (
+            self.resolve(&condition.left),
+            self.resolve(&condition.right),
+        ) else {
+            return false;
+        };
+
+        match condition.op {
+            Operator::Eq => values_equal(&left, &right),
+            Operator::Ne => !values_equal(&left, &right),
+            Operator::In => match &right {
+                Value::Array(items) => items.iter().any(|item| values_equal(&left, item)),
+                Value::String(net) => match (
+                    net.parse::<IpNet>(),
+                    as_text(&left).and_then(|ip| ip.parse::<IpAddr>().ok()),
+                ) {
+                    (Ok(net), Some(ip)) => net.contains(&ip.to_canonical()),
+                    _ => false,
+                },
+                _ => false,
+            },
+        }
+    }
+
+    fn resolve(&self, operand: &Operand) -> Option<Value> {
+        let text = |value: &str| Some(Value::String(value.to_string()));
+        match operand {
+            Operand::Method => text(self.ctx.method.as_str()),
+            Operand::ClientIp => self.ctx.client_ip.and_then(|ip| text(&ip.to_string())),
+            Operand::Path(name) => self.captures.get(name).and_then(|v| text(v)),
+            Operand::Header(name) => self
+                .ctx
+                .headers
+                .get(name.as_str())
+                .and_then(|value| value.to_str().ok())
+                .and_then(text),
+            Operand::Query(name) => self.query.get(name).and_then(|v| text(v)),
+            Operand::Claim(fields) => fields
+                .iter()
+                .try_fold(self.claims, |value, field| value.get(field))
+                .filter(|value| !value.is_null())
+                .cloned(),
+            Operand::Literal(value) => Some(value.clone()),
+        }
+    }
+}
+
+/// Whether `rule` covers a request, regardless of its conditions.
+pub fn applies(rule: &PolicyRule, method: &Method, path: &str) -> bool {
+    matches_method(rule, method)
+        && rule.path.as_ref().is_none_or(|pattern| {
+            canonical_segments(path).is_none_or(|segments| match_path(pattern, &segments).is_some())
+        })
+}
+
+// HEAD reads what GET reads, so rules written for GET cover it too
+fn matches_method(rule: &PolicyRule, method: &Method) -> bool {
+    rule.methods.is_empty()
+        || rule.methods.iter().any(|name| {
+            name.eq_ignore_ascii_case(method.as_str())
+                || (method == Method::HEAD && name.eq_ignore_ascii_case(Method::GET.as_str()))
+        })
+}
+
+fn values_equal(left: &Value, right: &Value) -> bool {
+    match (as_text(left), as_text(right)) {
+        (Some(left), Some(right)) => left == right,
+        _ => left == right,
+    }
+}
+
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn match_path(pattern: &str, path: &[String]) -> Option<HashMap<String, String>> {
+    let pattern: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+    if pattern.len() != path.len() {
+        return None;
+    }
+
+    let mut captures = HashMap::new();
+    for (expected, actual) in pattern.iter().zip(path) {
+        match expected
+            .strip_prefix('{')
+            .and_then(|name| name.strip_suffix('}'))
+        {
+            Some(name) => {
+                captures.insert(name.to_string(), actual.clone());
+            }
+            None if expected == actual => {}
+            None => return None,
+        }
+    }
+    Some(captures)
+}
+
+// Percent-decodes each segment and resolves empty, `.` and `..` segments.
+// `None` when a segment decodes to something that is not plain text or that
+// holds a `/`, since upstreams disagree on what those mean.
+pub(crate) fn canonical_segments(path: &str) -> Option<Vec<String>> {
+    let mut segments = Vec::new();
+    for raw in path.split('/') {
+        let segment = percent_decode(raw)?;
+        match segment.as_str() {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ if segment.contains('/') => return None,
+            _ => segments.push(segment),
+        }
+    }
+    Some(segments)
+}
+
+fn percent_decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = segment.get(index + 1..index + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+impl TryFrom<String> for PolicyCondition {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        let (left, op, right) = split_condition(&source)
+            .ok_or_else(|| format!("Policy condition '{}' needs ==, != or in", source))?;
+        Ok(Self {
+            left: parse_operand(left)?,
+            op,
+            right: parse_operand(right)?,
+            source,
+        })
+    }
+}
+
+// Finds the first operator outside of quotes.
+fn split_condition(source: &str) -> Option<(&str, Operator, &str)> {
+    let mut quote = None;
+    for (index, c) in source.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, _) => {
+                let rest = &source[index..];
+                for (token, op) in [
+                    ("==", Operator::Eq),
+                    ("!=", Operator::Ne),
+                    (" in ", Operator::In),
+                ] {
+                    if let Some(right) = rest.strip_prefix(token) {
+                        return Some((source[..index].trim(), op, right.trim()));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_operand(token: &str) -> Result<Operand, String> {
+    if let Some(list) = token
+        .strip_prefix('[')
+        .and_then(|list| list.strip_suffix(']'))
+    {
+        let items = list
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| unquote(item).map(|item| Value::String(item.to_string())))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| format!("List '{}' may only hold quoted literals", token))?;
+        return Ok(Operand::Literal(Value::Array(items)));
+    }
+    if let Some(literal) = unquote(token) {
+        return Ok(Operand::Literal(Value::String(literal.to_string())));
+    }
+
+    let operand = match token.split_once('.') {
+        None if token == "method" => Operand::Method,
+        None if token == "client_ip" => Operand::ClientIp,
+        Some(("path", name)) => Operand::Path(name.to_string()),
+        Some(("header", name)) => Operand::Header(name.to_lowercase()),
+        Some(("query", name)) => Operand::Query(name.to_string()),
+        Some(("claims", fields)) => Operand::Claim(fields.split('.').map(str::to_string).collect()),
+        _ => return Err(format!("Unknown policy operand '{}'", token)),
+    };
+    Ok(operand)
+}
+
+fn unquote(token: &str) -> Option<&str> {
+    ['\'', '"'].iter().find_map(|quote| {
+        token
+            .strip_prefix(*quote)
+            .and_then(|rest| rest.strip_suffix(*quote))
+    })
+}
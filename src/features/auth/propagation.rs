@@ -0,0 +1,218 @@
+// Passes the authenticated identity to upstreams as trusted headers, and
+// optionally as a short-lived JWT signed by the gateway, so backends don't
+// have to re-verify the caller's credential.
+
+use std::{
+    fs,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Error, anyhow};
+use http::{HeaderMap, HeaderName, HeaderValue};
+use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+use moka::future::Cache;
+use serde_json::Value;
+use tracing::{error, warn};
+
+use crate::{
+    config::{IdentityPropagationConfig, InternalJwtConfig},
+    errors::AppError,
+    features::auth::auth::Claims,
+    middleware::rate_limiter::rate_limit::parse_duration,
+};
+
+pub struct IdentityPropagator {
+    keys: SigningKeys,
+}
+
+impl Default for IdentityPropagator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IdentityPropagator {
+    pub fn new() -> Self {
+        Self {
+            keys: SigningKeys::new(),
+        }
+    }
+
+    /// Adds the identity headers for `claims`, replacing any existing values.
+    pub async fn apply(
+        &self,
+        headers: &mut HeaderMap,
+        claims: &Claims,
+        config: &IdentityPropagationConfig,
+    ) -> Result<(), AppError> {
+        let claims_value = serde_json::to_value(claims).unwrap_or(Value::Null);
+
+        insert(headers, &config.subject_header, &claims.sub);
+        insert(headers, &config.roles_header, &claims.roles.join(","));
+        for (name, pointer) in &config.claims {
+            if let Some(value) = claims_value.pointer(pointer) {
+                let value = match value {
+                    Value::String(value) => value.clone(),
+                    other => other.to_string(),
+                };
+                insert(headers, name, &value);
+            }
+        }
+
+        if let Some(internal_jwt) = &config.internal_jwt {
+            let token = self.mint(claims_value, internal_jwt).await?;
+            insert(headers, &internal_jwt.header, &token);
+        }
+        Ok(())
+    }
+
+    async fn mint(&self, claims: Value, config: &InternalJwtConfig) -> Result<String, AppError> {
+        let Value::Object(mut claims) = claims else {
+            return Err(AppError::InternalServerError);
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let ttl = parse_duration(&config.ttl).unwrap_or(Duration::from_secs(60));
+
+        claims.insert("iat".to_string(), now.into());
+        claims.insert("exp".to_string(), (now + ttl.as_secs()).into());
+        claims.insert("iss".to_string(), config.issuer.clone().into());
+        if let Some(audience) = &config.audience {
+            claims.insert("aud".to_string(), audience.clone().into());
+        }
+
+        let key = self
+            .keys
+            .get(
+                config.algorithm,
+                config.private_key_path.as_deref(),
+                &config.secret_env,
+                || std::env::var(&config.secret_env).ok(),
+            )
+            .await
+            .map_err(|e| {
+                error!("Failed to load internal JWT signing key: {}", e);
+                AppError::InternalServerError
+            })?;
+
+        encode(&Header::new(config.algorithm), &claims, &key).map_err(|e| {
+            error!("Failed to sign internal JWT: {}", e);
+            AppError::InternalServerError
+        })
+    }
+}
+
+/// Keys for signing gateway-issued JWTs by their source, so PEM files aren't
+/// parsed per request.
+pub(crate) struct SigningKeys {
+    cache: Cache<String, Arc<EncodingKey>>,
+}
+
+impl SigningKeys {
+    pub(crate) fn new() -> Self {
+        Self {
+            cache: Cache::builder()
+                .max_capacity(100)
+                .time_to_live(Duration::from_secs(300))
+                .build(),
+        }
+    }
+
+    /// The key for `algorithm`, loaded on first use: the secret named
+    /// `secret_name` for HMAC algorithms, otherwise the PEM private key.
+    pub(crate) async fn get(
+        &self,
+        algorithm: Algorithm,
+        private_key_path: Option<&str>,
+        secret_name: &str,
+        secret: impl FnOnce() -> Option<String>,
+    ) -> Result<Arc<EncodingKey>, Arc<Error>> {
+        let source = match private_key_path {
+            Some(path) if !is_hmac(algorithm) => format!("file:{}", path),
+            _ => format!("secret:{}", secret_name),
+        };
+        self.cache
+            .try_get_with(source, async {
+                let secret = if is_hmac(algorithm) {
+                    Some(secret().ok_or_else(|| anyhow!("{} is not set", secret_name))?)
+                } else {
+                    None
+                };
+                signing_key(algorithm, secret.as_deref(), private_key_path).map(Arc::new)
+            })
+            .await
+    }
+}
+
+/// Removes client-supplied copies of every propagated header.
+pub fn strip_identity_headers(headers: &mut HeaderMap, config: &IdentityPropagationConfig) {
+    let internal_jwt = config.internal_jwt.as_ref().map(|jwt| &jwt.header);
+    for name in [&config.subject_header, &config.roles_header]
+        .into_iter()
+        .chain(config.claims.keys())
+        .chain(internal_jwt)
+    {
+        headers.remove(name.as_str());
+    }
+}
+
+fn insert(headers: &mut HeaderMap, name: &str, value: &str) {
+    match (
+        HeaderName::from_bytes(name.as_bytes()),
+        HeaderValue::from_str(value),
+    ) {
+        (Ok(name), Ok(value)) => {
+            headers.insert(name, value);
+        }
+        _ => warn!(header = %name, "Identity value is not a valid header; not propagated"),
+    }
+}
+
+pub(crate) fn load_key(config: &InternalJwtConfig) -> Result<EncodingKey, Error> {
+    let secret = if is_hmac(config.algorithm) {
+        Some(
+            std::env::var(&config.secret_env)
+                .map_err(|_| anyhow!("{} is not set", config.secret_env))?,
+        )
+    } else {
+        None
+    };
+    signing_key(
+        config.algorithm,
+        secret.as_deref(),
+        config.private_key_path.as_deref(),
+    )
+}
+
+/// Key for signing gateway-issued JWTs: the shared secret for HMAC
+/// algorithms, otherwise the PEM private key at `private_key_path`.
+pub(crate) fn signing_key(
+    algorithm: Algorithm,
+    secret: Option<&str>,
+    private_key_path: Option<&str>,
+) -> Result<EncodingKey, Error> {
+    if is_hmac(algorithm) {
+        let secret = secret.ok_or_else(|| anyhow!("No secret for {:?}", algorithm))?;
+        return Ok(EncodingKey::from_secret(secret.as_bytes()));
+    }
+
+    let path = private_key_path
+        .ok_or_else(|| anyhow!("'private_key_path' is required for {:?}", algorithm))?;
+    let pem = fs::read(path)?;
+    let key = match algorithm {
+        Algorithm::ES256 | Algorithm::ES384 => EncodingKey::from_ec_pem(&pem)?,
+        Algorithm::EdDSA => EncodingKey::from_ed_pem(&pem)?,
+        _ => EncodingKey::from_rsa_pem(&pem)?,
+    };
+    Ok(key)
+}
+
+pub(crate) fn is_hmac(algorithm: Algorithm) -> bool {
+    matches!(
+        algorithm,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    )
+}
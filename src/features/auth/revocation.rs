@@ -0,0 +1,272 @@
+// Denylist for JWTs that are still within their `exp`: individual tokens by
+// `jti`, and every token of a subject issued before a cutoff. Entries come from
+// the hot-reloaded `identity.revocation_list_path` file and from the admin
+// endpoint; the latter are kept in memory only, and only for
+// `identity.runtime_revocation_ttl` after the tokens they cover were issued.
+// On a binary upgrade they are handed to the successor process in the file
+// format below.
+//
+//   jti: ["3f1c...", "b7a0..."]
+//   subjects:
+//     alice@example.com: "2025-06-01T00:00:00Z"
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+use chrono::{DateTime, TimeDelta, Utc};
+use moka::future::Cache;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::info;
+
+use crate::{
+    errors::AppError,
+    features::auth::{
+        auth::Claims,
+        expiry::{EntryExpiry, EntryTtl},
+    },
+};
+
+/// Names the file a parent process handed its runtime revocations over in.
+pub const HANDED_OVER_REVOCATIONS_ENV: &str = "RUSTYGW_HANDED_OVER_REVOCATIONS";
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct RevocationList {
+    #[serde(default)]
+    pub jti: HashSet<String>,
+    // Tokens of the subject with an `iat` at or before the time are revoked
+    #[serde(default)]
+    pub subjects: HashMap<String, DateTime<Utc>>,
+}
+
+impl RevocationList {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)?;
+        let list: RevocationList = serde_yaml::from_str(&content)?;
+        Ok(list)
+    }
+
+    /// Writes the list to a new file only the owner can read, for a successor
+    /// process to pick up and remove.
+    pub fn save_for_successor(&self) -> Result<PathBuf, Error> {
+        let (path, mut file) = loop {
+            let path = std::env::temp_dir().join(format!(
+                "rustygw-revocations.{:016x}.yaml",
+                rand::random::<u64>()
+            ));
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+            {
+                Ok(file) => break (path, file),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        let written = (|| -> Result<(), Error> {
+            file.write_all(serde_yaml::to_string(self)?.as_bytes())?;
+            file.sync_all()?;
+            Ok(())
+        })();
+        if let Err(e) = written {
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+        Ok(path)
+    }
+
+    fn revokes(&self, claims: &Claims) -> bool {
+        if let Some(jti) = claims.extra.get("jti").and_then(|jti| jti.as_str())
+            && self.jti.contains(jti)
+        {
+            return true;
+        }
+
+        self.subjects
+            .get(&claims.sub)
+            .is_some_and(|cutoff| issued_by(claims, cutoff))
+    }
+}
+
+// `iat` has second precision, so a token issued in the second of the cutoff
+// is covered. Tokens without `iat` can't prove they postdate the cutoff.
+fn issued_by(claims: &Claims, cutoff: &DateTime<Utc>) -> bool {
+    claims
+        .extra
+        .get("iat")
+        .and_then(|iat| iat.as_i64())
+        .is_none_or(|iat| iat <= cutoff.timestamp())
+}
+
+#[derive(Clone)]
+struct SubjectCutoff {
+    issued_before: DateTime<Utc>,
+    // When the last token issued by the cutoff has expired
+    until: Instant,
+}
+
+impl EntryTtl for SubjectCutoff {
+    fn ttl(&self) -> Duration {
+        self.until.ttl()
+    }
+}
+
+pub struct Revocations {
+    file: Arc<RwLock<RevocationList>>,
+    // Entries from the admin endpoint, dropped once the tokens they cover
+    // have expired
+    runtime_jti: Cache<String, Instant>,
+    runtime_subjects: Cache<String, SubjectCutoff>,
+    runtime_ttl: Duration,
+    // Set once the runtime entries have been handed to a successor process
+    handed_over: RwLock<bool>,
+}
+
+impl Revocations {
+    pub fn new(file: Arc<RwLock<RevocationList>>, runtime_ttl: Duration) -> Self {
+        Self {
+            file,
+            runtime_jti: Cache::builder()
+                .max_capacity(1_000_000)
+                .expire_after(EntryExpiry)
+                .build(),
+            runtime_subjects: Cache::builder()
+                .max_capacity(1_000_000)
+                .expire_after(EntryExpiry)
+                .build(),
+            runtime_ttl,
+            handed_over: RwLock::new(false),
+        }
+    }
+
+    // Shared with the hot reloader
+    pub fn list(&self) -> Arc<RwLock<RevocationList>> {
+        self.file.clone()
+    }
+
+    /// Rejects revoked tokens; called once the signature has been verified.
+    pub async fn check(&self, claims: &Claims) -> Result<(), AppError> {
+        if self.file.read().await.revokes(claims) || self.revoked_at_runtime(claims).await {
+            return Err(AppError::AuthFailed("Token has been revoked.".to_string()));
+        }
+        Ok(())
+    }
+
+    async fn revoked_at_runtime(&self, claims: &Claims) -> bool {
+        if let Some(jti) = claims.extra.get("jti").and_then(|jti| jti.as_str())
+            && self.runtime_jti.contains_key(jti)
+        {
+            return true;
+        }
+        self.runtime_subjects
+            .get(&claims.sub)
+            .await
+            .is_some_and(|cutoff| issued_by(claims, &cutoff.issued_before))
+    }
+
+    /// Revokes a token by its `jti`, unless the entries were handed over.
+    pub async fn revoke_token(&self, jti: String) -> Result<(), AppError> {
+        let handed_over = self.handed_over.read().await;
+        if *handed_over {
+            return Err(AppError::ServiceUnavailable);
+        }
+        info!(jti = %jti, "Revoked token");
+        self.insert_token(jti).await;
+        Ok(())
+    }
+
+    /// Revokes the subject's tokens issued by `issued_before`, unless the
+    /// entries were handed over.
+    pub async fn revoke_subject(
+        &self,
+        sub: String,
+        issued_before: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        let handed_over = self.handed_over.read().await;
+        if *handed_over {
+            return Err(AppError::ServiceUnavailable);
+        }
+        info!(sub = %sub, issued_before = %issued_before, "Revoked tokens of subject");
+        self.insert_subject(sub, issued_before).await;
+        Ok(())
+    }
+
+    /// Returns the runtime entries for a successor process. Until `resume`,
+    /// new revocations are refused so none can be missed.
+    pub async fn hand_over(&self) -> RevocationList {
+        *self.handed_over.write().await = true;
+        RevocationList {
+            jti: self
+                .runtime_jti
+                .iter()
+                .map(|(jti, _)| (*jti).clone())
+                .collect(),
+            subjects: self
+                .runtime_subjects
+                .iter()
+                .map(|(sub, cutoff)| ((*sub).clone(), cutoff.issued_before))
+                .collect(),
+        }
+    }
+
+    /// Takes revocations again after a failed hand over.
+    pub async fn resume(&self) {
+        *self.handed_over.write().await = false;
+    }
+
+    /// Adds the entries a parent process handed over.
+    pub async fn restore(&self, list: RevocationList) {
+        info!(
+            jti = list.jti.len(),
+            subjects = list.subjects.len(),
+            "Restored runtime revocations from parent process"
+        );
+        for jti in list.jti {
+            self.insert_token(jti).await;
+        }
+        for (sub, issued_before) in list.subjects {
+            self.insert_subject(sub, issued_before).await;
+        }
+    }
+
+    // The token may have been issued just now, so it is remembered for a
+    // whole `runtime_ttl`.
+    async fn insert_token(&self, jti: String) {
+        self.runtime_jti
+            .insert(jti, Instant::now() + self.runtime_ttl)
+            .await;
+    }
+
+    async fn insert_subject(&self, sub: String, issued_before: DateTime<Utc>) {
+        let runtime_ttl = self.runtime_ttl;
+        self.runtime_subjects
+            .entry(sub)
+            .and_upsert_with(|existing| async move {
+                let issued_before = existing
+                    .map(|entry| entry.into_value().issued_before.max(issued_before))
+                    .unwrap_or(issued_before);
+                // Nothing is left to cover once a cutoff is `runtime_ttl` past
+                let expires = TimeDelta::from_std(runtime_ttl)
+                    .ok()
+                    .and_then(|ttl| issued_before.checked_add_signed(ttl))
+                    .unwrap_or(DateTime::<Utc>::MAX_UTC);
+                let left = (expires - Utc::now()).to_std().unwrap_or_default();
+                SubjectCutoff {
+                    issued_before,
+                    until: Instant::now() + left,
+                }
+            })
+            .await;
+    }
+}
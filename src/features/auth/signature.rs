@@ -0,0 +1,233 @@
+// HMAC request signatures, as sent by webhook providers. The shared secret is
+// the `signing_secret` of a key store entry; timestamped or nonced requests
+// are remembered for the tolerance window so they can't be replayed.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use hmac::{Hmac, Mac};
+use http::{HeaderMap, Method, Uri};
+use moka::future::Cache;
+use sha2::{Sha256, Sha512};
+use subtle::ConstantTimeEq;
+use tracing::debug;
+
+use crate::{
+    config::{
+        CanonicalPart, SignatureAlgorithm, SignatureConfig, SignatureEncoding, SignatureFormat,
+    },
+    errors::AppError,
+    features::auth::{
+        api_key::check_key_state,
+        auth::{Claims, api_key_claims},
+        expiry::EntryExpiry,
+        key_store::KeyStore,
+    },
+    middleware::rate_limiter::rate_limit::parse_duration,
+};
+
+/// What a request presented, before it is checked.
+struct Presented {
+    signatures: Vec<String>,
+    timestamp: Option<String>,
+}
+
+pub struct SignatureVerifier {
+    // Nonces (or signatures) seen within their tolerance window, per key
+    seen: Cache<String, Duration>,
+}
+
+impl Default for SignatureVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SignatureVerifier {
+    pub fn new() -> Self {
+        Self {
+            seen: Cache::builder()
+                .max_capacity(1_000_000)
+                .expire_after(EntryExpiry)
+                .build(),
+        }
+    }
+
+    pub async fn verify(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+        body: &[u8],
+        config: &SignatureConfig,
+        key_store: &dyn KeyStore,
+    ) -> Result<Claims, AppError> {
+        let failed = |reason: &str| AppError::AuthFailed(reason.to_string());
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        let value = header(&config.header).ok_or_else(|| failed("Missing request signature."))?;
+        let presented = match config.format {
+            SignatureFormat::Plain => Presented {
+                signatures: vec![
+                    value
+                        .strip_prefix(&config.prefix)
+                        .unwrap_or(&value)
+                        .to_string(),
+                ],
+                timestamp: config.timestamp_header.as_deref().and_then(header),
+            },
+            SignatureFormat::Stripe => parse_stripe(&value),
+        };
+        let nonce = match &config.nonce_header {
+            Some(name) => Some(header(name).ok_or_else(|| failed("Missing request nonce."))?),
+            None => None,
+        };
+
+        let key_id = match (&config.key_id, &config.key_id_header) {
+            (Some(key_id), _) => key_id.clone(),
+            (None, Some(name)) => header(name).ok_or_else(|| failed("Missing signing key ID."))?,
+            (None, None) => {
+                tracing::error!("Signature route needs 'key_id' or 'key_id_header'");
+                return Err(AppError::InternalServerError);
+            }
+        };
+        if let Some(unsigned) = config.unsigned_header() {
+            tracing::error!(header = %unsigned, "Signature route checks a header its canonical string leaves out");
+            return Err(AppError::InternalServerError);
+        }
+        let details = key_store
+            .get(&key_id)
+            .await
+            .map_err(|e| {
+                tracing::error!("API key store lookup failed: {}", e);
+                AppError::InternalServerError
+            })?
+            .ok_or_else(|| failed("Unknown signing key."))?;
+        check_key_state(&details)?;
+        let secret = details
+            .signing_secret
+            .as_ref()
+            .ok_or_else(|| failed("Unknown signing key."))?;
+
+        let canonical = config.canonical_parts();
+        if (canonical.contains(&CanonicalPart::Timestamp)
+            || config.format == SignatureFormat::Stripe)
+            && presented.timestamp.is_none()
+        {
+            return Err(failed("Missing signature timestamp."));
+        }
+
+        let tolerance = parse_duration(&config.tolerance).unwrap_or(Duration::from_secs(300));
+        if let Some(timestamp) = &presented.timestamp {
+            let timestamp: u64 = timestamp
+                .parse()
+                .map_err(|_| failed("Invalid signature timestamp."))?;
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            if now.abs_diff(timestamp) > tolerance.as_secs() {
+                return Err(failed("Signature timestamp is outside the tolerance."));
+            }
+        }
+
+        let mut message = Vec::new();
+        for (index, part) in canonical.iter().enumerate() {
+            if index > 0 {
+                message.extend_from_slice(config.separator.as_bytes());
+            }
+            match part {
+                CanonicalPart::Timestamp => message
+                    .extend_from_slice(presented.timestamp.as_deref().unwrap_or("").as_bytes()),
+                CanonicalPart::Nonce => {
+                    message.extend_from_slice(nonce.as_deref().unwrap_or("").as_bytes())
+                }
+                CanonicalPart::Method => message.extend_from_slice(method.as_str().as_bytes()),
+                CanonicalPart::Path => message.extend_from_slice(
+                    uri.path_and_query()
+                        .map_or(uri.path(), |path| path.as_str())
+                        .as_bytes(),
+                ),
+                CanonicalPart::Body => message.extend_from_slice(body),
+            }
+        }
+
+        let expected = sign(config.algorithm, secret.as_bytes(), &message);
+        let matched = presented.signatures.iter().find(|signature| {
+            decode(config.encoding, signature)
+                .is_some_and(|signature| bool::from(signature.ct_eq(&expected)))
+        });
+        let Some(matched) = matched else {
+            debug!(key_id = %key_id, "Request signature mismatch");
+            return Err(failed("Invalid request signature."));
+        };
+
+        // Only bounded-lifetime requests can be remembered long enough
+        if presented.timestamp.is_some() || nonce.is_some() {
+            let seen_key = format!("{}:{}", key_id, nonce.as_deref().unwrap_or(matched));
+            let entry = self.seen.entry(seen_key).or_insert(tolerance * 2).await;
+            if !entry.is_fresh() {
+                return Err(failed("Replayed request."));
+            }
+        }
+
+        Ok(api_key_claims(&details))
+    }
+}
+
+fn parse_stripe(value: &str) -> Presented {
+    let mut presented = Presented {
+        signatures: Vec::new(),
+        timestamp: None,
+    };
+    for (name, value) in value
+        .split(',')
+        .filter_map(|item| item.trim().split_once('='))
+    {
+        match name {
+            "t" => presented.timestamp = Some(value.to_string()),
+            "v1" => presented.signatures.push(value.to_string()),
+            _ => {}
+        }
+    }
+    presented
+}
+
+/// Signature for `message`, before encoding.
+pub fn sign(algorithm: SignatureAlgorithm, secret: &[u8], message: &[u8]) -> Vec<u8> {
+    match algorithm {
+        SignatureAlgorithm::HmacSha256 => {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+            mac.update(message);
+            mac.finalize().into_bytes().to_vec()
+        }
+        SignatureAlgorithm::HmacSha512 => {
+            let mut mac =
+                Hmac::<Sha512>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+            mac.update(message);
+            mac.finalize().into_bytes().to_vec()
+        }
+    }
+}
+
+fn decode(encoding: SignatureEncoding, signature: &str) -> Option<Vec<u8>> {
+    match encoding {
+        SignatureEncoding::Base64 => STANDARD.decode(signature.trim()).ok(),
+        SignatureEncoding::Hex => {
+            let signature = signature.trim();
+            if !signature.len().is_multiple_of(2) {
+                return None;
+            }
+            (0..signature.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(signature.get(i..i + 2)?, 16).ok())
+                .collect()
+        }
+    }
+}
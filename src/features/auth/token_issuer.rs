@@ -0,0 +1,289 @@
+// Exchanges API keys for short-lived JWTs that `Jwt` routes accept, so
+// long-lived keys stay off the wire. Refresh tokens are opaque, single use and
+// kept in memory by their digest; revoking or rotating the key ends them.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::Utc;
+use http::HeaderMap;
+use jsonwebtoken::{Header, encode};
+use moka::future::Cache;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    config::{ApiKeyDetails, SecretsConfig, TokenIssuerConfig},
+    errors::AppError,
+    features::auth::{
+        api_key::{check_key_state, hash_key, verify_key},
+        basic::basic_credentials,
+        expiry::{EntryExpiry, EntryTtl},
+        key_store::KeyStore,
+        lockout::key_id,
+        propagation::SigningKeys,
+    },
+    middleware::rate_limiter::rate_limit::parse_duration,
+};
+
+// OAuth 2.0 style token request, sent as a form or JSON body
+#[derive(Debug, Deserialize)]
+pub struct TokenRequest {
+    // "api_key", "client_credentials" or "refresh_token"
+    pub grant_type: String,
+    pub api_key: Option<String>,
+    // The key ID (`gw_<id>`) and secret; also accepted as HTTP Basic
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub refresh_token: Option<String>,
+}
+
+impl TokenRequest {
+    /// The key the request presents, for brute-force accounting.
+    pub fn credential(&self, headers: &HeaderMap) -> Option<String> {
+        match self.grant_type.as_str() {
+            "api_key" => self.api_key.as_deref().and_then(key_id),
+            "client_credentials" => self
+                .client_id
+                .clone()
+                .or_else(|| basic_credentials(headers).map(|(id, _)| id)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Clone)]
+struct RefreshGrant {
+    // Key store entry the tokens are issued for
+    key_id: String,
+    // Digest of the key at issuance, so rotating the key ends the grant
+    key_hash: Option<String>,
+    ttl: Duration,
+}
+
+impl EntryTtl for RefreshGrant {
+    fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+pub struct TokenIssuer {
+    keys: SigningKeys,
+    // Outstanding refresh tokens by their digest
+    refresh_tokens: Cache<String, RefreshGrant>,
+}
+
+impl Default for TokenIssuer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TokenIssuer {
+    pub fn new() -> Self {
+        Self {
+            keys: SigningKeys::new(),
+            refresh_tokens: Cache::builder()
+                .max_capacity(1_000_000)
+                .expire_after(EntryExpiry)
+                .build(),
+        }
+    }
+
+    pub async fn issue(
+        &self,
+        request: TokenRequest,
+        headers: &HeaderMap,
+        key_store: &dyn KeyStore,
+        config: &TokenIssuerConfig,
+        secrets: &SecretsConfig,
+    ) -> Result<TokenResponse, AppError> {
+        let missing = |field: &str| AppError::InvalidRequest(format!("'{}' is required", field));
+
+        let (key_id, details) = match request.grant_type.as_str() {
+            "api_key" => {
+                let key = request.api_key.ok_or_else(|| missing("api_key"))?;
+                authenticate(key_store, &key).await?
+            }
+            "client_credentials" => {
+                let (id, secret) = match (request.client_id, request.client_secret) {
+                    (Some(id), Some(secret)) => (id, secret),
+                    _ => basic_credentials(headers).ok_or_else(|| missing("client_secret"))?,
+                };
+                authenticate(key_store, &format!("{}.{}", id, secret)).await?
+            }
+            "refresh_token" if config.refresh_tokens => {
+                let token = request
+                    .refresh_token
+                    .ok_or_else(|| missing("refresh_token"))?;
+                self.redeem(key_store, &token).await?
+            }
+            other => {
+                return Err(AppError::InvalidRequest(format!(
+                    "unsupported grant_type '{}'",
+                    other
+                )));
+            }
+        };
+        check_exchangeable(&details)?;
+
+        let mut ttl = parse_duration(&config.access_token_ttl).unwrap_or(Duration::from_secs(900));
+        // A token never outlives the key it was exchanged for
+        if let Some(expires_at) = details.expires_at {
+            ttl = ttl.min((expires_at - Utc::now()).to_std().unwrap_or_default());
+        }
+        let access_token = self.sign(&key_id, &details, ttl, config, secrets).await?;
+
+        let refresh_token = if config.refresh_tokens {
+            let ttl = parse_duration(&config.refresh_token_ttl)
+                .unwrap_or(Duration::from_secs(30 * 86400));
+            Some(self.grant_refresh(key_id, &details, ttl).await)
+        } else {
+            None
+        };
+
+        info!(user = %details.user_id, grant_type = %request.grant_type, "Issued access token");
+        Ok(TokenResponse {
+            access_token,
+            token_type: "Bearer",
+            expires_in: ttl.as_secs(),
+            refresh_token,
+        })
+    }
+
+    // Consumes a refresh token; a replayed one finds nothing
+    async fn redeem(
+        &self,
+        key_store: &dyn KeyStore,
+        token: &str,
+    ) -> Result<(String, ApiKeyDetails), AppError> {
+        let invalid = || AppError::AuthFailed("Invalid refresh token.".to_string());
+        let grant = self
+            .refresh_tokens
+            .remove(&hash_key(token, None))
+            .await
+            .ok_or_else(invalid)?;
+        let details = key_store
+            .get(&grant.key_id)
+            .await
+            .map_err(|e| {
+                error!("API key store lookup failed: {}", e);
+                AppError::InternalServerError
+            })?
+            .filter(|details| details.hash == grant.key_hash)
+            .ok_or_else(invalid)?;
+        check_key_state(&details)?;
+        Ok((grant.key_id, details))
+    }
+
+    async fn grant_refresh(
+        &self,
+        key_id: String,
+        details: &ApiKeyDetails,
+        ttl: Duration,
+    ) -> String {
+        let mut secret = [0u8; 32];
+        rand::rng().fill_bytes(&mut secret);
+        let token = URL_SAFE_NO_PAD.encode(secret);
+
+        let grant = RefreshGrant {
+            key_id,
+            key_hash: details.hash.clone(),
+            ttl,
+        };
+        self.refresh_tokens
+            .insert(hash_key(&token, None), grant)
+            .await;
+        token
+    }
+
+    async fn sign(
+        &self,
+        key_id: &str,
+        details: &ApiKeyDetails,
+        ttl: Duration,
+        config: &TokenIssuerConfig,
+        secrets: &SecretsConfig,
+    ) -> Result<String, AppError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut claims = json!({
+            "sub": details.user_id,
+            "roles": details.roles,
+            "iat": now,
+            "exp": now + ttl.as_secs(),
+            "iss": config.issuer,
+            "jti": Uuid::new_v4().to_string(),
+        });
+        if let Some(audience) = &config.audience {
+            claims["aud"] = audience.clone().into();
+        }
+        // Legacy entries are keyed by the key itself, which must not leak
+        if details.hash.is_some() {
+            claims["client_id"] = Value::from(key_id);
+        }
+
+        let key = self
+            .keys
+            .get(
+                config.algorithm,
+                config.private_key_path.as_deref(),
+                "JWT_SECRET",
+                || secrets.jwt_secret.clone(),
+            )
+            .await
+            .map_err(|e| {
+                error!("Failed to load token issuer signing key: {}", e);
+                AppError::InternalServerError
+            })?;
+
+        let mut header = Header::new(config.algorithm);
+        header.kid = config.kid.clone();
+        encode(&header, &claims, &key).map_err(|e| {
+            error!("Failed to sign access token: {}", e);
+            AppError::InternalServerError
+        })
+    }
+}
+
+// Verifies a presented key, returning the ID of its key store entry
+async fn authenticate(
+    key_store: &dyn KeyStore,
+    key: &str,
+) -> Result<(String, ApiKeyDetails), AppError> {
+    let details = verify_key(key_store, key).await?;
+    let key_id = match key.split_once('.') {
+        Some((id, _)) if details.hash.is_some() => id,
+        _ => key,
+    };
+    Ok((key_id.to_string(), details))
+}
+
+// Tokens carry no route, method, address or rate restrictions, so restricted
+// keys can't be exchanged for them
+fn check_exchangeable(details: &ApiKeyDetails) -> Result<(), AppError> {
+    if details.allowed_routes.is_empty()
+        && details.allowed_methods.is_empty()
+        && details.allowed_cidrs.is_empty()
+        && details.rate_limit.is_none()
+    {
+        return Ok(());
+    }
+    Err(AppError::ApiKeyNotAllowed(
+        "be exchanged for tokens".to_string(),
+    ))
+}
@@ -0,0 +1,150 @@
+use std::net::{IpAddr, SocketAddr};
+
+use http::{HeaderMap, HeaderName, HeaderValue, header};
+use tracing::debug;
+
+use crate::config::{ClientIpConfig, ClientIpSource, ForwardedHeadersConfig};
+
+pub const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+pub const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+pub const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+pub const X_REAL_IP: HeaderName = HeaderName::from_static("x-real-ip");
+pub const CF_CONNECTING_IP: HeaderName = HeaderName::from_static("cf-connecting-ip");
+
+// The gateway itself only terminates plain HTTP.
+const GATEWAY_PROTO: &str = "http";
+
+/// Determines the real client IP for a connection from `peer`.
+///
+/// Client-supplied headers are only believed when the peer is one of the
+/// configured trusted proxies; otherwise the peer address is the client.
+pub fn resolve_client_ip(peer: IpAddr, headers: &HeaderMap, config: &ClientIpConfig) -> IpAddr {
+    if config.source == ClientIpSource::ConnectInfo || !config.is_trusted(&peer) {
+        return peer;
+    }
+
+    let resolved = match config.source {
+        ClientIpSource::ConnectInfo => None,
+        ClientIpSource::XForwardedFor => rightmost_untrusted(headers, config),
+        ClientIpSource::XRealIp => single_ip_header(headers, &X_REAL_IP),
+        ClientIpSource::CfConnectingIp => single_ip_header(headers, &CF_CONNECTING_IP),
+    };
+
+    resolved.unwrap_or_else(|| {
+        debug!(%peer, source = ?config.source, "No usable client IP header, using peer address");
+        peer
+    })
+}
+
+// Walks X-Forwarded-For from the right, skipping the hops added by our own
+// trusted proxies. Everything left of the first untrusted hop is client controlled.
+fn rightmost_untrusted(headers: &HeaderMap, config: &ClientIpConfig) -> Option<IpAddr> {
+    let hops: Vec<IpAddr> = headers
+        .get_all(&X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(parse_ip)
+        .collect();
+
+    hops.iter()
+        .rev()
+        .find(|ip| !config.is_trusted(ip))
+        .or_else(|| hops.first())
+        .copied()
+}
+
+fn single_ip_header(headers: &HeaderMap, name: &HeaderName) -> Option<IpAddr> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_ip)
+}
+
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+    value
+        .parse::<IpAddr>()
+        .or_else(|_| value.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .ok()
+}
+
+/// Adds forwarding headers to a request about to be sent upstream.
+///
+/// When the peer is a trusted proxy its forwarding chain is extended,
+/// otherwise any client-supplied forwarding headers are discarded first.
+pub fn apply_forwarded_headers(
+    headers: &mut HeaderMap,
+    peer: IpAddr,
+    client_ip_config: &ClientIpConfig,
+    config: &ForwardedHeadersConfig,
+) {
+    let trusted = client_ip_config.is_trusted(&peer);
+    let peer = peer.to_canonical();
+    let host = headers
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    if !trusted {
+        for name in [
+            X_FORWARDED_FOR,
+            X_FORWARDED_PROTO,
+            X_FORWARDED_HOST,
+            header::FORWARDED,
+        ] {
+            headers.remove(name);
+        }
+    }
+
+    if config.x_forwarded {
+        let forwarded_for = match joined(headers, &X_FORWARDED_FOR) {
+            Some(chain) => format!("{}, {}", chain, peer),
+            None => peer.to_string(),
+        };
+        insert(headers, X_FORWARDED_FOR, &forwarded_for);
+
+        if !headers.contains_key(&X_FORWARDED_PROTO) {
+            insert(headers, X_FORWARDED_PROTO, GATEWAY_PROTO);
+        }
+        if !headers.contains_key(&X_FORWARDED_HOST)
+            && let Some(host) = &host
+        {
+            insert(headers, X_FORWARDED_HOST, host);
+        }
+    }
+
+    if config.forwarded {
+        let node = match peer {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => format!("\"[{}]\"", ip),
+        };
+        let mut element = format!("for={};proto={}", node, GATEWAY_PROTO);
+        if let Some(host) = &host {
+            element.push_str(&format!(";host=\"{}\"", host));
+        }
+
+        let forwarded = match joined(headers, &header::FORWARDED) {
+            Some(chain) => format!("{}, {}", chain, element),
+            None => element,
+        };
+        insert(headers, header::FORWARDED, &forwarded);
+    }
+}
+
+// Folds repeated header lines into a single comma separated value.
+fn joined(headers: &HeaderMap, name: &HeaderName) -> Option<String> {
+    let values: Vec<&str> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+
+    (!values.is_empty()).then(|| values.join(", "))
+}
+
+fn insert(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
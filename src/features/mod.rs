@@ -1,3 +1,6 @@
 pub mod auth;
 pub mod circuit_breaker;
+pub mod client_ip;
+pub mod proxy;
+pub mod proxy_protocol;
 pub mod rate_limiter;
@@ -0,0 +1,40 @@
+use http::{HeaderMap, HeaderName, header};
+
+// Hop-by-hop headers (RFC 9110 section 7.6.1, plus the legacy ones from RFC 2616)
+// describe a single connection and must never be forwarded by a proxy.
+const HOP_BY_HOP_HEADERS: [HeaderName; 9] = [
+    header::CONNECTION,
+    HeaderName::from_static("proxy-connection"),
+    HeaderName::from_static("keep-alive"),
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+];
+
+/// Removes hop-by-hop headers, including any extra header the sender listed
+/// in its `Connection` header, so they are not relayed to the next hop.
+pub fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+
+    for name in listed.iter().chain(HOP_BY_HOP_HEADERS.iter()) {
+        headers.remove(name);
+    }
+}
+
+/// Prepares the `Host` header for the upstream request. Unless the route asks
+/// to preserve the client's `Host`, it is dropped so the HTTP client derives it
+/// from the upstream URL's authority.
+pub fn apply_host_policy(headers: &mut HeaderMap, preserve_host: bool) {
+    if !preserve_host {
+        headers.remove(header::HOST);
+    }
+}
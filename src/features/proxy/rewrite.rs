@@ -0,0 +1,127 @@
+// Rewrites upstream response headers that embed upstream URLs so clients only
+// ever see the gateway's public route prefix (like nginx `proxy_redirect`,
+// `proxy_cookie_path` and `proxy_cookie_domain`).
+
+use http::{HeaderMap, HeaderValue, header};
+use reqwest::Url;
+use tracing::debug;
+
+use crate::config::{ResponseRewriteConfig, RouteConfig};
+
+pub fn rewrite_response_headers(headers: &mut HeaderMap, route: &RouteConfig) {
+    let Some(config) = &route.response_rewrite else {
+        return;
+    };
+    let Ok(upstream) = Url::parse(&route.destination) else {
+        return;
+    };
+    let rewriter = Rewriter {
+        upstream,
+        public_prefix: &route.path,
+        config,
+    };
+
+    if config.redirects {
+        for name in [header::LOCATION, header::CONTENT_LOCATION] {
+            if let Some(rewritten) = headers
+                .get(&name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| rewriter.location(value))
+                .and_then(|value| HeaderValue::from_str(&value).ok())
+            {
+                debug!(header = %name, value = ?rewritten, "Rewrote upstream header");
+                headers.insert(name, rewritten);
+            }
+        }
+    }
+
+    if config.cookie_path || config.cookie_domain.is_some() {
+        let cookies: Vec<HeaderValue> = headers
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|value| match value.to_str() {
+                Ok(cookie) => HeaderValue::from_str(&rewriter.set_cookie(cookie))
+                    .unwrap_or_else(|_| value.clone()),
+                Err(_) => value.clone(),
+            })
+            .collect();
+
+        headers.remove(header::SET_COOKIE);
+        for cookie in cookies {
+            headers.append(header::SET_COOKIE, cookie);
+        }
+    }
+}
+
+struct Rewriter<'a> {
+    upstream: Url,
+    public_prefix: &'a str,
+    config: &'a ResponseRewriteConfig,
+}
+
+impl Rewriter<'_> {
+    // Returns the rewritten value, or `None` when it does not point at the upstream.
+    fn location(&self, value: &str) -> Option<String> {
+        if value.starts_with('/') && !value.starts_with("//") {
+            return self.public_path(value);
+        }
+
+        let location = Url::parse(value).ok()?;
+        if location.origin() != self.upstream.origin() {
+            return None;
+        }
+
+        let mut path = location.path().to_string();
+        if let Some(query) = location.query() {
+            path = format!("{}?{}", path, query);
+        }
+        if let Some(fragment) = location.fragment() {
+            path = format!("{}#{}", path, fragment);
+        }
+        self.public_path(&path)
+    }
+
+    // Maps an upstream path (with optional query/fragment) under the
+    // destination's base path onto the public route prefix.
+    fn public_path(&self, path: &str) -> Option<String> {
+        let base = self.upstream.path().trim_end_matches('/');
+        let rest = path.strip_prefix(base)?;
+        if !(rest.is_empty() || rest.starts_with(['/', '?', '#'])) {
+            return None;
+        }
+
+        let rewritten = format!("{}{}", self.public_prefix.trim_end_matches('/'), rest);
+        if rewritten.is_empty() || rewritten.starts_with(['?', '#']) {
+            Some(format!("/{}", rewritten))
+        } else {
+            Some(rewritten)
+        }
+    }
+
+    fn set_cookie(&self, cookie: &str) -> String {
+        let mut parts = cookie.split(';');
+        let mut rewritten = vec![parts.next().unwrap_or_default().to_string()];
+
+        for attribute in parts {
+            let (name, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let name = name.trim();
+
+            if self.config.cookie_path && name.eq_ignore_ascii_case("path") {
+                let path = self
+                    .public_path(value.trim())
+                    .unwrap_or_else(|| value.trim().to_string());
+                rewritten.push(format!(" Path={}", path));
+            } else if name.eq_ignore_ascii_case("domain")
+                && let Some(domain) = &self.config.cookie_domain
+            {
+                if !domain.is_empty() {
+                    rewritten.push(format!(" Domain={}", domain));
+                }
+            } else {
+                rewritten.push(attribute.to_string());
+            }
+        }
+
+        rewritten.join(";")
+    }
+}
@@ -0,0 +1,159 @@
+// HAProxy PROXY protocol (v1 text and v2 binary) header parsing.
+// Spec: https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V1_MAX_LEN: usize = 107;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProxyProtocolError {
+    #[error("connection did not start with a PROXY protocol header")]
+    MissingHeader,
+    #[error("malformed PROXY protocol header: {0}")]
+    Malformed(&'static str),
+    #[error("failed to read PROXY protocol header: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Reads a PROXY protocol header from the start of `stream`, consuming
+/// exactly the header bytes and nothing that follows.
+///
+/// Returns the original client address, or `None` when the header carries no
+/// usable address (`LOCAL` health checks, `UNKNOWN` or unix sockets), in which
+/// case the caller should keep using the peer address.
+pub async fn read_header<R>(stream: &mut R) -> Result<Option<SocketAddr>, ProxyProtocolError>
+where
+    R: AsyncRead + Unpin,
+{
+    // The shortest valid v1 header ("PROXY UNKNOWN\r\n") is longer than the
+    // v2 signature, so this never reads past either kind of header.
+    let mut prefix = [0u8; 12];
+    stream.read_exact(&mut prefix).await?;
+
+    if prefix == V2_SIGNATURE {
+        read_v2(stream).await
+    } else if prefix.starts_with(V1_PREFIX) {
+        read_v1(stream, &prefix).await
+    } else {
+        Err(ProxyProtocolError::MissingHeader)
+    }
+}
+
+async fn read_v1<R>(stream: &mut R, prefix: &[u8]) -> Result<Option<SocketAddr>, ProxyProtocolError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut line = prefix.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LEN {
+            return Err(ProxyProtocolError::Malformed("v1 header too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| ProxyProtocolError::Malformed("v1 header is not ASCII"))?;
+    parse_v1(line)
+}
+
+/// Parses a v1 header line without its trailing CRLF,
+/// e.g. `PROXY TCP4 192.168.0.1 192.168.0.11 56324 443`.
+pub fn parse_v1(line: &str) -> Result<Option<SocketAddr>, ProxyProtocolError> {
+    let mut parts = line.split(' ');
+    if parts.next() != Some("PROXY") {
+        return Err(ProxyProtocolError::Malformed("missing PROXY keyword"));
+    }
+
+    let protocol = parts
+        .next()
+        .ok_or(ProxyProtocolError::Malformed("missing protocol"))?;
+    if protocol == "UNKNOWN" {
+        return Ok(None);
+    }
+
+    let fields: Vec<&str> = parts.collect();
+    let [src_ip, _dst_ip, src_port, _dst_port] = fields[..] else {
+        return Err(ProxyProtocolError::Malformed("wrong number of v1 fields"));
+    };
+
+    let ip: IpAddr = match protocol {
+        "TCP4" => src_ip
+            .parse::<Ipv4Addr>()
+            .map(IpAddr::V4)
+            .map_err(|_| ProxyProtocolError::Malformed("invalid TCP4 source address"))?,
+        "TCP6" => src_ip
+            .parse::<Ipv6Addr>()
+            .map(IpAddr::V6)
+            .map_err(|_| ProxyProtocolError::Malformed("invalid TCP6 source address"))?,
+        _ => return Err(ProxyProtocolError::Malformed("unsupported v1 protocol")),
+    };
+    let port: u16 = src_port
+        .parse()
+        .map_err(|_| ProxyProtocolError::Malformed("invalid source port"))?;
+
+    Ok(Some(SocketAddr::new(ip, port)))
+}
+
+async fn read_v2<R>(stream: &mut R) -> Result<Option<SocketAddr>, ProxyProtocolError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).await?;
+
+    parse_v2(header[0], header[1], &payload)
+}
+
+/// Parses the body of a v2 header given its version/command byte,
+/// address family byte and the address block (including any TLVs).
+pub fn parse_v2(
+    version_command: u8,
+    family: u8,
+    payload: &[u8],
+) -> Result<Option<SocketAddr>, ProxyProtocolError> {
+    if version_command >> 4 != 2 {
+        return Err(ProxyProtocolError::Malformed("unsupported v2 version"));
+    }
+
+    match version_command & 0x0F {
+        // LOCAL: sent by the proxy itself, e.g. for health checks
+        0x0 => return Ok(None),
+        0x1 => {}
+        _ => return Err(ProxyProtocolError::Malformed("unsupported v2 command")),
+    }
+
+    match family >> 4 {
+        // AF_INET
+        0x1 => {
+            let addr = payload.get(..12).ok_or(ProxyProtocolError::Malformed(
+                "truncated IPv4 address block",
+            ))?;
+            let ip = Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3]);
+            let port = u16::from_be_bytes([addr[8], addr[9]]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+        }
+        // AF_INET6
+        0x2 => {
+            let addr = payload.get(..36).ok_or(ProxyProtocolError::Malformed(
+                "truncated IPv6 address block",
+            ))?;
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&addr[..16]);
+            let port = u16::from_be_bytes([addr[32], addr[33]]);
+            Ok(Some(SocketAddr::new(
+                IpAddr::V6(Ipv6Addr::from(octets)),
+                port,
+            )))
+        }
+        // AF_UNSPEC / AF_UNIX carry no client IP
+        _ => Ok(None),
+    }
+}
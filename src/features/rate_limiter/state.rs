@@ -30,17 +30,24 @@ impl InMemoryRateLimitState {
             clients: DashMap::new(),
         }
     }
+
+    fn bucket(&self, key: &str, capacity: u64) -> Arc<RwLock<Bucket>> {
+        self.clients
+            .entry(key.to_string())
+            .or_insert_with(|| {
+                Arc::new(RwLock::new(Bucket {
+                    tokens: capacity as f64,
+                    last_refill: Instant::now(),
+                }))
+            })
+            .clone()
+    }
 }
 
 #[async_trait]
 impl RateLimitState for InMemoryRateLimitState {
     async fn check_and_update(&self, key: &str, capacity: u64, refill_rate: f64) -> bool {
-        let entry = self.clients.entry(key.to_string()).or_insert_with(|| {
-            Arc::new(RwLock::new(Bucket {
-                tokens: capacity as f64,
-                last_refill: Instant::now(),
-            }))
-        });
+        let entry = self.bucket(key, capacity);
 
         let last_refill_time = {
             let bucket = entry.read().await;
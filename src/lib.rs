@@ -8,24 +8,41 @@ pub mod proxy;
 pub mod state;
 pub mod utils;
 
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{net::SocketAddr, os::fd::AsRawFd, path::PathBuf, sync::Arc, time::Duration};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use axum::serve::ListenerExt;
 use axum_prometheus::PrometheusMetricLayer;
 use dotenvy::dotenv;
 use moka::future::Cache;
 use reqwest::Client;
-use tokio::{net::TcpListener, sync::RwLock};
-use tracing::{Level, info};
+use tokio::{
+    net::TcpListener,
+    signal::unix::{Signal, SignalKind, signal},
+    sync::{RwLock, watch},
+};
+use tracing::{Level, error, info, warn};
 
 use crate::state::{AppState, CachedResponse};
 use crate::{
-    config::{ApiKeyStore, GatewayConfig, SecretsConfig},
+    config::{GatewayConfig, SecretsConfig},
     features::{
+        auth::{
+            auth::AuthBackends,
+            basic::BasicCredentials,
+            jwt::JwtProviders,
+            key_store::open_key_store,
+            lockout::Lockouts,
+            revocation::{HANDED_OVER_REVOCATIONS_ENV, RevocationList, Revocations},
+        },
         circuit_breaker::circuit_breaker::CircuitBreakerStore,
         rate_limiter::state::{InMemoryRateLimitState, RateLimitState},
     },
-    utils::hot_reload,
+    middleware::rate_limiter::rate_limit::parse_duration,
+    utils::{
+        hot_reload,
+        listener::{self, GatewayListener},
+    },
 };
 
 pub async fn run(config_path: PathBuf) -> Result<()> {
@@ -40,11 +57,58 @@ pub async fn run(config_path: PathBuf) -> Result<()> {
     let config = Arc::new(RwLock::new(GatewayConfig::load(config_path.clone())?));
     info!("Configuration loaded successfully.");
 
-    let key_store_path = config.read().await.identity.api_key_store_path.clone();
+    let (key_store, yaml_key_store) = {
+        let config_guard = config.read().await;
+        let identity = &config_guard.identity;
+        info!(path = ?identity.api_key_store_path, backend = ?identity.api_key_store_backend, "Loading API key store...");
+        open_key_store(identity)?
+    };
+
+    let basic_auth_path = config.read().await.identity.basic_auth_path.clone();
+    let basic_credentials = match &basic_auth_path {
+        Some(path) => {
+            info!(path = ?path, "Loading Basic auth credentials...");
+            BasicCredentials::load(path)?
+        }
+        None => BasicCredentials::default(),
+    };
+
+    let revocation_list_path = config.read().await.identity.revocation_list_path.clone();
+    let revocation_list = match &revocation_list_path {
+        Some(path) => {
+            info!(path = ?path, "Loading JWT revocation list...");
+            RevocationList::load(path)?
+        }
+        None => RevocationList::default(),
+    };
+    let runtime_revocation_ttl = {
+        let config_guard = config.read().await;
+        let ttl = &config_guard.identity.runtime_revocation_ttl;
+        parse_duration(ttl)
+            .map_err(|_| anyhow::anyhow!("Invalid identity.runtime_revocation_ttl '{}'", ttl))?
+    };
 
-    info!(path = ?key_store_path, "Loading API key store...");
+    let http_client = Client::new();
 
-    let key_store = Arc::new(RwLock::new(ApiKeyStore::load(&key_store_path)?));
+    info!("Loading JWT verification keys...");
+    let jwt_providers = {
+        let config_guard = config.read().await;
+        JwtProviders::from_config(&config_guard.identity, &secrets, http_client.clone()).await?
+    };
+    let auth_backends = Arc::new(AuthBackends::new(
+        jwt_providers,
+        http_client.clone(),
+        basic_credentials,
+        revocation_list,
+        runtime_revocation_ttl,
+    ));
+    if let Some(path) = std::env::var_os(HANDED_OVER_REVOCATIONS_ENV) {
+        let handed_over = RevocationList::load(&path);
+        let _ = std::fs::remove_file(&path);
+        let handed_over = handed_over
+            .with_context(|| format!("Failed to load revocations handed over in {:?}", path))?;
+        auth_backends.revocations.restore(handed_over).await;
+    }
 
     let cache: Arc<Cache<String, Arc<CachedResponse>>> = Arc::new(
         Cache::builder()
@@ -53,6 +117,7 @@ pub async fn run(config_path: PathBuf) -> Result<()> {
     );
 
     let rate_limit_store: Arc<dyn RateLimitState> = Arc::new(InMemoryRateLimitState::new());
+    let lockouts = Arc::new(Lockouts::new());
 
     let (prometheus_layer, prometheus_handle) = {
         let config_guard = config.read().await;
@@ -72,39 +137,187 @@ pub async fn run(config_path: PathBuf) -> Result<()> {
     let app_state = Arc::new(AppState {
         config: config.clone(),
         secrets,
-        key_store: key_store.clone(),
+        auth_backends,
+        key_store,
         rate_limit_store,
+        lockouts,
         cache,
-        http_client: Client::new(),
+        http_client,
         prometheus_handle,
         circuit_breaker_store,
         plugin_registry,
     });
+    let auth_backends = app_state.auth_backends.clone();
 
     // start hot reloader
     tokio::spawn(hot_reload::watch_config_files(
         config_path,
         config.clone(),
-        key_store.clone(), // Clone for the watcher task
+        yaml_key_store,
+        app_state.auth_backends.basic.credentials(),
+        app_state.auth_backends.revocations.list(),
     ));
 
-    let mut app = app::create_app(app_state)?;
+    let admin_addr = config.read().await.server.admin_addr.clone();
+    let admin_app = admin_addr
+        .is_some()
+        .then(|| app::create_admin_app(app_state.clone()));
+    let mut app = app::create_app(app_state, admin_addr.is_none())?;
 
     if let Some(layer) = prometheus_layer {
         app = app.layer(layer);
     }
 
-    let config_guard = config.read().await;
+    let (listener, proxy_protocol, drain_timeout) = {
+        let config_guard = config.read().await;
+        let listener = TcpListener::from_std(listener::bind(&config_guard.server)?)?;
+        let drain_timeout = parse_duration(&config_guard.server.drain_timeout).map_err(|_| {
+            anyhow::anyhow!(
+                "Invalid server.drain_timeout '{}'",
+                config_guard.server.drain_timeout
+            )
+        })?;
+        (
+            listener,
+            config_guard.server.proxy_protocol.clone(),
+            drain_timeout,
+        )
+    };
+    info!("Gateway listening on {}", listener.local_addr()?);
+    let admin_listener = match &admin_addr {
+        Some(addr) => {
+            let admin_listener = TcpListener::from_std(listener::bind_admin(addr)?)?;
+            info!(
+                "Admin endpoint listening on {}",
+                admin_listener.local_addr()?
+            );
+            Some(admin_listener)
+        }
+        None => None,
+    };
+    // Installed before reporting ready: a parent that hands over drains at
+    // once, and a signal to us must not hit its default action then
+    let signals = ShutdownSignals::install()?;
+    // Lets a parent that handed the socket over start draining
+    listener::notify_ready()?;
+    if let Some(proxy_protocol) = proxy_protocol.as_ref().filter(|c| c.enabled) {
+        info!(trusted = ?proxy_protocol.trusted_cidrs, "PROXY protocol is enabled");
+    }
 
-    let addr = config_guard.server.addr.clone();
+    let listener_fd = listener.as_raw_fd();
+    let (draining, mut drain_started) = watch::channel(false);
 
-    let listener = TcpListener::bind(&addr).await?;
-    info!("Gateway listening on {}", &addr);
-    axum::serve(
-        listener,
+    // Stops taking revocations as soon as draining starts; a successor may
+    // already be serving them.
+    if let (Some(admin_listener), Some(admin_app)) = (admin_listener, admin_app) {
+        let mut admin_drain_started = draining.subscribe();
+        tokio::spawn(async move {
+            let admin = axum::serve(admin_listener, admin_app).with_graceful_shutdown(async move {
+                let _ = admin_drain_started.wait_for(|draining| *draining).await;
+            });
+            if let Err(e) = admin.await {
+                error!("Admin endpoint failed: {}", e);
+            }
+        });
+    }
+
+    // `tap_io` is a no-op here; it lets axum derive `ConnectInfo<SocketAddr>`
+    // from our listener's address type.
+    let server = axum::serve(
+        GatewayListener::new(listener, proxy_protocol).tap_io(|_| {}),
         app.into_make_service_with_connect_info::<SocketAddr>(),
     )
-    .await?;
+    .with_graceful_shutdown(shutdown_signal(
+        signals,
+        listener_fd,
+        auth_backends,
+        draining,
+    ));
+
+    tokio::select! {
+        result = server => result?,
+        _ = async {
+            let _ = drain_started.wait_for(|draining| *draining).await;
+            tokio::time::sleep(drain_timeout).await;
+        } => warn!(timeout = ?drain_timeout, "Drain timeout elapsed, dropping remaining connections"),
+    }
 
+    info!("Gateway stopped");
     Ok(())
 }
+
+// How long a successor may take to load its configuration and start serving
+const SUCCESSOR_READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+struct ShutdownSignals {
+    interrupt: Signal,
+    terminate: Signal,
+    upgrade: Signal,
+}
+
+impl ShutdownSignals {
+    fn install() -> Result<Self> {
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+            upgrade: signal(SignalKind::user_defined2())?,
+        })
+    }
+}
+
+// Resolves once the gateway should stop accepting connections and drain.
+// SIGUSR2 first hands the listening socket to a new gateway process, so the
+// upgrade happens without refusing a single connection. The old process only
+// stops accepting once the new one reports that it serves.
+async fn shutdown_signal(
+    mut signals: ShutdownSignals,
+    listener_fd: std::os::fd::RawFd,
+    auth_backends: Arc<AuthBackends>,
+    draining: watch::Sender<bool>,
+) {
+    loop {
+        tokio::select! {
+            _ = signals.interrupt.recv() => {
+                info!("Received SIGINT, draining connections");
+                break;
+            }
+            _ = signals.terminate.recv() => {
+                info!("Received SIGTERM, draining connections");
+                break;
+            }
+            _ = signals.upgrade.recv() => {
+                info!("Received SIGUSR2, handing listener over to a new gateway process");
+                match hand_over(listener_fd, &auth_backends.revocations).await {
+                    Ok(()) => break,
+                    Err(e) => error!("Binary upgrade failed, continuing to serve: {:#}", e),
+                }
+            }
+        }
+    }
+
+    draining.send_replace(true);
+}
+
+// Runtime revocations go along in a file; this process refuses new ones from
+// the snapshot on, unless the successor fails and it keeps serving.
+async fn hand_over(listener_fd: std::os::fd::RawFd, revocations: &Revocations) -> Result<()> {
+    let mut successor = tokio::process::Command::new(std::env::current_exe()?);
+    successor.args(std::env::args_os().skip(1));
+
+    let handed_over = match revocations.hand_over().await.save_for_successor() {
+        Ok(path) => {
+            successor.env(HANDED_OVER_REVOCATIONS_ENV, &path);
+            let handed_over =
+                listener::hand_over(listener_fd, successor, SUCCESSOR_READY_TIMEOUT).await;
+            if handed_over.is_err() {
+                let _ = std::fs::remove_file(&path);
+            }
+            handed_over
+        }
+        Err(e) => Err(e.context("Failed to save runtime revocations for the successor")),
+    };
+    if handed_over.is_err() {
+        revocations.resume().await;
+    }
+    handed_over
+}
@@ -1,8 +1,247 @@
-use rustway::run;
-use std::path::PathBuf;
+use std::path::Path;
+
+use anyhow::Context;
+use clap::Parser;
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Uri};
+use rustway::{
+    config::{ApiKeyDetails, GatewayConfig, SecretsConfig},
+    features::auth::{api_key, key_store::open_key_store},
+    run,
+    utils::{
+        config_path::{Cli, Command, KeysCommand},
+        inspect,
+    },
+};
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    let config_path = PathBuf::from("gateway.yaml");
-    run(config_path).await
+    let cli = Cli::parse();
+
+    match cli.command {
+        None | Some(Command::Run) => run(cli.config).await,
+        Some(Command::Validate) => validate(&cli.config).await,
+        Some(Command::Routes) => routes(&cli.config),
+        Some(Command::TestRoute {
+            method,
+            url,
+            headers,
+        }) => test_route(&cli.config, &method, &url, &headers),
+        Some(Command::Keys { command }) => keys(&cli.config, command).await,
+    }
+}
+
+fn load_config(config_path: &Path) -> Result<GatewayConfig, anyhow::Error> {
+    GatewayConfig::load(config_path)
+        .with_context(|| format!("Failed to load {}", config_path.display()))
+}
+
+async fn validate(config_path: &Path) -> Result<(), anyhow::Error> {
+    dotenvy::dotenv().ok();
+    let config = load_config(config_path)?;
+    let problems = inspect::validate(&config, &SecretsConfig::from_env()?).await;
+    if problems.is_empty() {
+        println!(
+            "{} is valid ({} routes)",
+            config_path.display(),
+            config.routes.len()
+        );
+        return Ok(());
+    }
+    for problem in &problems {
+        eprintln!("error: {}", problem);
+    }
+    anyhow::bail!(
+        "{} problem(s) found in {}",
+        problems.len(),
+        config_path.display()
+    )
+}
+
+fn routes(config_path: &Path) -> Result<(), anyhow::Error> {
+    let config = load_config(config_path)?;
+    // Longest prefix wins, so sorted paths show which routes nest
+    let mut routes: Vec<_> = config.routes.iter().collect();
+    routes.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut rows = vec![
+        [
+            "NAME",
+            "PATH",
+            "DESTINATION",
+            "AUTH",
+            "RATE LIMIT",
+            "CACHE",
+            "CIRCUIT BREAKER",
+        ]
+        .map(str::to_string),
+    ];
+    for route in routes {
+        rows.push([
+            route.name.clone(),
+            route.path.clone(),
+            route.destination.clone(),
+            inspect::describe_auth(route),
+            inspect::describe_rate_limit(route),
+            inspect::describe_cache(route),
+            inspect::describe_circuit_breaker(route),
+        ]);
+    }
+
+    let mut widths = [0; 7];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for row in &rows {
+        let line: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+    Ok(())
+}
+
+fn test_route(
+    config_path: &Path,
+    method: &str,
+    url: &str,
+    headers: &[String],
+) -> Result<(), anyhow::Error> {
+    let config = load_config(config_path)?;
+    let method = Method::from_bytes(method.to_uppercase().as_bytes())
+        .with_context(|| format!("Invalid method '{}'", method))?;
+    let uri: Uri = url
+        .parse()
+        .with_context(|| format!("Invalid URL '{}'", url))?;
+
+    let mut header_map = HeaderMap::new();
+    for header in headers {
+        let (name, value) = header
+            .split_once(':')
+            .with_context(|| format!("Invalid header '{}', expected 'Name: value'", header))?;
+        header_map.append(
+            HeaderName::from_bytes(name.trim().as_bytes())?,
+            HeaderValue::from_str(value.trim())?,
+        );
+    }
+
+    let Some(lines) = inspect::trace_request(&config, &method, &uri, &header_map) else {
+        anyhow::bail!("No route matches {}", uri.path());
+    };
+    let width = lines
+        .iter()
+        .map(|(label, _)| label.len() + 1)
+        .max()
+        .unwrap_or(0);
+    for (label, value) in lines {
+        println!("{:<width$}  {}", format!("{}:", label), value);
+    }
+    Ok(())
+}
+
+async fn keys(config_path: &Path, command: KeysCommand) -> Result<(), anyhow::Error> {
+    dotenvy::dotenv().ok();
+    let config = load_config(config_path)?;
+    let (store, _) = open_key_store(&config.identity)?;
+
+    match command {
+        KeysCommand::Create {
+            user_id,
+            roles,
+            not_before,
+            expires_at,
+            allowed_routes,
+            allowed_methods,
+            allowed_cidrs,
+        } => {
+            let details = ApiKeyDetails {
+                user_id,
+                roles,
+                not_before,
+                expires_at,
+                allowed_routes,
+                allowed_methods,
+                allowed_cidrs,
+                ..Default::default()
+            };
+            let generated = api_key::create_key(store.as_ref(), details).await?;
+            println!("Created {}", generated.id);
+            println!("API key (shown only once): {}", generated.key);
+        }
+        KeysCommand::List => {
+            println!(
+                "{:<24} {:<10} {:<24} {:<30} ROLES",
+                "ID", "STATUS", "EXPIRES", "USER"
+            );
+            for (id, details) in store.list().await? {
+                let expires_at = details
+                    .expires_at
+                    .map_or("-".to_string(), |expires_at| expires_at.to_rfc3339());
+                println!(
+                    "{:<24} {:<10} {:<24} {:<30} {}",
+                    display_id(&id, &details),
+                    format!("{:?}", details.status).to_lowercase(),
+                    expires_at,
+                    details.user_id,
+                    details.roles.join(",")
+                );
+            }
+        }
+        KeysCommand::Revoke { id } => {
+            if !store.revoke(&id).await? {
+                anyhow::bail!("API key '{}' not found", id);
+            }
+            println!("Revoked {}", id);
+        }
+        KeysCommand::Rotate { id } => {
+            let generated = api_key::rotate_key(store.as_ref(), &id).await?;
+            println!("Revoked {}, replaced by {}", id, generated.id);
+            println!("API key (shown only once): {}", generated.key);
+        }
+        KeysCommand::Show { id } => {
+            let mut details = store
+                .get(&id)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("API key '{}' not found", id))?;
+            println!("{}:", display_id(&id, &details));
+            // Shown only as present, like the key itself after `keys create`
+            let redacted = |value: &mut Option<String>| {
+                if value.is_some() {
+                    *value = Some("<redacted>".to_string());
+                }
+            };
+            redacted(&mut details.hash);
+            redacted(&mut details.signing_secret);
+            for line in serde_yaml::to_string(&details)?.lines() {
+                println!("  {}", line);
+            }
+        }
+        // Read from stdin so the key stays out of shell history and `ps`
+        KeysCommand::Hash => {
+            let mut key = String::new();
+            std::io::stdin().read_line(&mut key)?;
+            let key = key.trim();
+            if key.is_empty() {
+                anyhow::bail!("Expected the key on stdin");
+            }
+            let hash = api_key::hash_key(key, store.hmac_secret());
+            // Legacy keys have no ID prefix and are stored under their digest
+            let id = key.split_once('.').map_or(hash.as_str(), |(id, _)| id);
+            println!("  \"{}\":\n    hash: \"{}\"", id, hash);
+        }
+    }
+    Ok(())
+}
+
+// Legacy plaintext entries are keyed by the key itself, which must not be
+// printed in full
+fn display_id(id: &str, details: &ApiKeyDetails) -> String {
+    if details.hash.is_some() {
+        return id.to_string();
+    }
+    let visible: String = id.chars().take(4).collect();
+    format!("{}... (plaintext)", visible)
 }
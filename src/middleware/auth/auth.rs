@@ -1,17 +1,29 @@
-use std::sync::Arc;
+use std::{net::IpAddr, sync::Arc};
 
 use axum::{
+    body::{Body, to_bytes},
     extract::{Request, State},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
 };
+use axum_client_ip::ClientIp;
 
-use http::Uri;
+use http::{HeaderMap, Method, Uri};
 
 use crate::{
-    config::RouteConfig,
+    config::{AuthConfig, AuthType, RouteConfig},
     errors::AppError,
-    features::auth::auth::{check_roles, verify_token},
+    features::auth::{
+        api_key::{KeyRateLimit, check_key_access, verify_key},
+        auth::{Claims, api_key_claims, check_route_roles, verify_token},
+        credentials::{extract_credential, strip_credential},
+        external::{Decision, strip_upstream_headers},
+        key_store::KeyStore,
+        lockout::{AuthAttempt, is_guess, presented_credential},
+        oidc::strip_session_cookies,
+        policy::{self, PolicyContext},
+        propagation::strip_identity_headers,
+    },
     state::AppState,
 };
 
@@ -24,23 +36,230 @@ pub async fn layer(
     let route = find_route_for_uri(req.uri(), state.clone()).await?;
 
     if let Some(auth_config) = &route.auth {
-        let claims = {
-            // Acquire read lock on the key store for API key checks
-            let key_store_guard = state.key_store.read().await;
+        let oidc = auth_config.auth_type == AuthType::Oidc;
+        if oidc
+            && let Some(response) = state
+                .auth_backends
+                .oidc
+                .handle_endpoint(
+                    req.uri(),
+                    req.headers(),
+                    &route,
+                    auth_config,
+                    &state.auth_backends.jwt_providers,
+                )
+                .await?
+        {
+            return Ok(response);
+        }
+
+        // Identity headers only ever come from the gateway
+        if let Some(propagate) = &auth_config.propagate {
+            strip_identity_headers(req.headers_mut(), propagate);
+        }
+
+        if let Some(external) = auth_config
+            .external
+            .as_ref()
+            .filter(|_| auth_config.auth_type == AuthType::External)
+        {
+            strip_upstream_headers(req.headers_mut(), external);
+            let client_ip = req.extensions().get::<ClientIp>().map(|ip| ip.0);
+            let decision = state
+                .auth_backends
+                .external
+                .authorize(req.method(), req.uri(), req.headers(), client_ip, external)
+                .await?;
+
+            let claims = match decision {
+                Decision::Allow { claims, headers } => {
+                    req.headers_mut().extend(headers);
+                    claims
+                }
+                // The service's answer (e.g. a 401 or a login redirect) goes back as-is
+                Decision::Deny {
+                    status,
+                    headers,
+                    body,
+                } => return Ok((status, headers, body).into_response()),
+            };
+            check_route_roles(&claims.roles, auth_config, req.method())?;
+            check_policies(&req, auth_config, &claims)?;
+            propagate_identity(&state, &mut req, auth_config, &claims).await?;
+            req.extensions_mut().insert(claims);
+            return Ok(next.run(req).await);
+        }
+
+        if let Some(signature) = auth_config
+            .signature
+            .as_ref()
+            .filter(|_| auth_config.auth_type == AuthType::Signature)
+        {
+            // The body is signed, so it is buffered and handed on afterwards
+            let (parts, body) = req.into_parts();
+            let body = to_bytes(body, signature.max_body_size)
+                .await
+                .map_err(|_| AppError::PayloadTooLarge)?;
+            let claims = state
+                .auth_backends
+                .signatures
+                .verify(
+                    &parts.method,
+                    &parts.uri,
+                    &parts.headers,
+                    &body,
+                    signature,
+                    state.key_store.as_ref(),
+                )
+                .await?;
+            req = Request::from_parts(parts, Body::from(body));
+
+            check_route_roles(&claims.roles, auth_config, req.method())?;
+            check_policies(&req, auth_config, &claims)?;
+            propagate_identity(&state, &mut req, auth_config, &claims).await?;
+            req.extensions_mut().insert(claims);
+            return Ok(next.run(req).await);
+        }
+
+        // Brute-force protection counts guesses per client IP and credential
+        let lockout = state.config.read().await.identity.lockout.clone();
+        let attempt = lockout.as_ref().map(|config| {
+            AuthAttempt::new(
+                req.extensions().get::<ClientIp>().map(|ip| ip.0),
+                presented_credential(req.headers(), req.uri(), auth_config),
+                config,
+            )
+        });
+        if let Some(attempt) = &attempt {
+            state.lockouts.check(attempt).await?;
+        }
+
+        let mut key_rate_limit = None;
+        let verified = if auth_config.auth_type == AuthType::ApiKey {
+            let client_ip = req.extensions().get::<ClientIp>().map(|ip| ip.0);
+            verify_api_key_request(
+                req.headers(),
+                req.uri(),
+                req.method(),
+                client_ip,
+                &route,
+                auth_config,
+                state.key_store.as_ref(),
+            )
+            .await
+            .map(|(claims, rate_limit)| {
+                key_rate_limit = rate_limit;
+                claims
+            })
+        } else {
             // Pass all necessary configs to the verification function
-            verify_token(req.headers(), auth_config, &state.secrets, &key_store_guard)?
+            verify_token(
+                req.headers(),
+                req.uri(),
+                auth_config,
+                &state.auth_backends,
+                state.key_store.as_ref(),
+            )
+            .await
+        };
+        let claims = match verified {
+            Ok(claims) => claims,
+            // Browsers without a valid session are sent to the provider to log in
+            Err(AppError::MissingAuthToken | AppError::AuthFailed(_)) if oidc => {
+                return state
+                    .auth_backends
+                    .oidc
+                    .login(req.method(), req.uri(), &route, auth_config)
+                    .await;
+            }
+            Err(e) => {
+                if let Some(attempt) = attempt.as_ref().filter(|_| is_guess(&e)) {
+                    state.lockouts.record_failure(attempt).await?;
+                }
+                return Err(e);
+            }
         };
 
-        if let Some(required_roles) = &auth_config.roles {
-            check_roles(&claims.roles, required_roles)?;
+        check_route_roles(&claims.roles, auth_config, req.method())?;
+        check_policies(&req, auth_config, &claims)?;
+
+        // Keep the credential out of upstream logs
+        if auth_config.strips_credential() {
+            let (mut parts, body) = req.into_parts();
+            strip_credential(&mut parts.headers, &mut parts.uri, &auth_config.credentials);
+            req = Request::from_parts(parts, body);
+        }
+        if oidc {
+            strip_session_cookies(req.headers_mut(), auth_config);
         }
 
+        propagate_identity(&state, &mut req, auth_config, &claims).await?;
+        if let Some(rate_limit) = key_rate_limit {
+            req.extensions_mut().insert(rate_limit);
+        }
         req.extensions_mut().insert(claims);
     }
 
     Ok(next.run(req).await)
 }
 
+async fn propagate_identity(
+    state: &AppState,
+    req: &mut Request,
+    auth_config: &AuthConfig,
+    claims: &Claims,
+) -> Result<(), AppError> {
+    match &auth_config.propagate {
+        Some(propagate) => {
+            state
+                .auth_backends
+                .propagator
+                .apply(req.headers_mut(), claims, propagate)
+                .await
+        }
+        None => Ok(()),
+    }
+}
+
+fn check_policies(
+    req: &Request,
+    auth_config: &AuthConfig,
+    claims: &Claims,
+) -> Result<(), AppError> {
+    policy::evaluate(
+        &auth_config.policies,
+        &PolicyContext {
+            method: req.method(),
+            uri: req.uri(),
+            headers: req.headers(),
+            client_ip: req.extensions().get::<ClientIp>().map(|ip| ip.0),
+            claims,
+        },
+    )
+}
+
+// API keys carry restrictions on the route, method and source address, and
+// may bring their own rate limit.
+async fn verify_api_key_request(
+    headers: &HeaderMap,
+    uri: &Uri,
+    method: &Method,
+    client_ip: Option<IpAddr>,
+    route: &RouteConfig,
+    auth_config: &AuthConfig,
+    key_store: &dyn KeyStore,
+) -> Result<(Claims, Option<KeyRateLimit>), AppError> {
+    let key = extract_credential(headers, uri, &auth_config.credentials)?;
+    let details = verify_key(key_store, &key).await?;
+    check_key_access(&details, route, uri.path(), method, client_ip)?;
+
+    let rate_limit = details
+        .rate_limit
+        .clone()
+        .map(|config| KeyRateLimit::new(&key, config));
+    Ok((api_key_claims(&details), rate_limit))
+}
+
 async fn find_route_for_uri(uri: &Uri, state: Arc<AppState>) -> Result<Arc<RouteConfig>, AppError> {
     let config_guard = state.config.read().await;
 
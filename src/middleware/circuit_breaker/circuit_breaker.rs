@@ -109,13 +109,11 @@ pub async fn layer(
             }
             CircuitStateEnum::Closed {
                 consecutive_failures,
-            } => {
-                if consecutive_failures > 0 {
-                    // Reset failure count on success.
-                    *final_state = CircuitStateEnum::Closed {
-                        consecutive_failures: 0,
-                    };
-                }
+            } if consecutive_failures > 0 => {
+                // Reset failure count on success.
+                *final_state = CircuitStateEnum::Closed {
+                    consecutive_failures: 0,
+                };
             }
             _ => {}
         }
@@ -0,0 +1,28 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use axum_client_ip::ClientIp;
+
+use crate::{errors::AppError, features::client_ip::client_ip::resolve_client_ip, state::AppState};
+
+// Resolves the real client IP once per request and stores it as the
+// `ClientIp` extension used by the rate limiter and other layers.
+pub async fn layer(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let client_ip = {
+        let config_guard = state.config.read().await;
+        resolve_client_ip(peer.ip(), req.headers(), &config_guard.server.client_ip)
+    };
+
+    req.extensions_mut().insert(ClientIp(client_ip));
+
+    Ok(next.run(req).await)
+}
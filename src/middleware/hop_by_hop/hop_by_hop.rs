@@ -0,0 +1,11 @@
+use axum::{body::Body, http::Request, middleware::Next, response::Response};
+
+use crate::features::proxy::headers::strip_hop_by_hop;
+
+// Runs before anything reads or sets request headers. Stripping later would let
+// a client name gateway-set headers (identity, X-Forwarded-For) in its
+// `Connection` header and have them removed on the way upstream.
+pub async fn layer(mut req: Request<Body>, next: Next) -> Response {
+    strip_hop_by_hop(req.headers_mut());
+    next.run(req).await
+}
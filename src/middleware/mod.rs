@@ -1,5 +1,7 @@
 pub mod auth;
 pub mod cache;
 pub mod circuit_breaker;
+pub mod client_ip;
+pub mod hop_by_hop;
 pub mod rate_limiter;
 pub mod request_id;
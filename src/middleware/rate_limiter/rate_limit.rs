@@ -1,6 +1,7 @@
 use std::{sync::Arc, time::Duration};
 
 use axum::{
+    Extension,
     extract::{Request, State},
     middleware::Next,
     response::Response,
@@ -8,11 +9,11 @@ use axum::{
 use axum_client_ip::ClientIp;
 use tracing::{info, warn};
 
-use crate::{errors::AppError, state::AppState};
+use crate::{errors::AppError, features::auth::api_key::KeyRateLimit, state::AppState};
 
 pub async fn layer(
     State(state): State<Arc<AppState>>,
-    ClientIp(client_ip): ClientIp,
+    Extension(ClientIp(client_ip)): Extension<ClientIp>,
     req: Request,
     next: Next,
 ) -> Result<Response, AppError> {
@@ -20,16 +21,22 @@ pub async fn layer(
     let config_guard = state.config.read().await;
     let route = config_guard.find_route_for_path(req.uri().path());
 
-    if let Some(route_config) = route
-        && let Some(rate_limit_config) = route_config.rate_limit.as_ref()
-    {
+    // A per-key limit set by the auth layer replaces the route's per-IP one
+    let key_rate_limit = req.extensions().get::<KeyRateLimit>();
+    let rate_limit_config = match key_rate_limit {
+        Some(key_rate_limit) => Some(&key_rate_limit.config),
+        None => route.as_ref().and_then(|route| route.rate_limit.as_ref()),
+    };
+
+    if let Some(rate_limit_config) = rate_limit_config {
         let period =
             parse_duration(&rate_limit_config.period).unwrap_or_else(|_| Duration::from_secs(60));
         let capacity = rate_limit_config.requests;
         let refill_rate = rate_limit_config.requests as f64 / period.as_secs_f64();
 
         // clinets Ip address as key to rate limiting
-        let key = client_ip.to_string();
+        let key =
+            key_rate_limit.map_or_else(|| client_ip.to_string(), |limit| limit.bucket.clone());
         let allowed = state
             .rate_limit_store
             .check_and_update(&key, capacity, refill_rate)
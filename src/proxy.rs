@@ -1,22 +1,34 @@
 use axum::{
     Extension,
     body::Body,
-    extract::{Path, State},
+    extract::{ConnectInfo, Path, State},
     http::HeaderMap,
     response::Response,
 };
 use bytes::Bytes;
 use http::{HeaderValue, Method};
 use http_body_util::BodyExt;
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 use tracing::info;
 
-use crate::{app::REQUEST_ID_HEADER, errors::AppError, state::AppState};
+use crate::{
+    app::REQUEST_ID_HEADER,
+    errors::AppError,
+    features::{
+        client_ip::client_ip::apply_forwarded_headers,
+        proxy::{
+            headers::{apply_host_policy, strip_hop_by_hop},
+            rewrite::rewrite_response_headers,
+        },
+    },
+    state::AppState,
+};
 
 #[axum::debug_handler]
 pub async fn proxy_handler(
     State(state): State<Arc<AppState>>,
     Extension(request_id): Extension<Arc<String>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     Path(path): Path<String>,
     method: Method,
     mut headers: HeaderMap,
@@ -33,16 +45,22 @@ pub async fn proxy_handler(
         None => return Err(AppError::RouteNotFound),
     };
 
-    let destination_path = request_path.strip_prefix(&route.path).unwrap_or("");
-
-    let destination_url = format!("{}{}", route.destination, destination_path);
+    let destination_url = route.upstream_url(&request_path);
 
     info!(destination = %destination_url, "Forwarding request to backend");
 
+    // Hop-by-hop request headers are already gone, see the hop_by_hop layer
     headers.insert(
         REQUEST_ID_HEADER,
         HeaderValue::from_str(&request_id).unwrap(),
     );
+    apply_forwarded_headers(
+        &mut headers,
+        peer.ip(),
+        &config_guard.server.client_ip,
+        &config_guard.server.forwarded_headers,
+    );
+    apply_host_policy(&mut headers, route.preserve_host);
 
     let body_bytes: Bytes = body
         .collect()
@@ -67,7 +85,9 @@ pub async fn proxy_handler(
     let response = state.http_client.execute(request).await?;
 
     let status = response.status();
-    let headers = response.headers().clone();
+    let mut headers = response.headers().clone();
+    strip_hop_by_hop(&mut headers);
+    rewrite_response_headers(&mut headers, &route);
     let bytes = response.bytes().await.map_err(AppError::from)?;
     let body = Body::from(bytes);
 
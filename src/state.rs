@@ -6,9 +6,11 @@ use reqwest::Client;
 use std::{sync::Arc, time::Instant};
 
 use crate::{
-    config::{ApiKeyStore, GatewayConfig, SecretsConfig},
+    config::{GatewayConfig, SecretsConfig},
     features::{
-        circuit_breaker::circuit_breaker::CircuitBreakerStore, rate_limiter::state::RateLimitState,
+        auth::{auth::AuthBackends, key_store::KeyStore, lockout::Lockouts},
+        circuit_breaker::circuit_breaker::CircuitBreakerStore,
+        rate_limiter::state::RateLimitState,
     },
     plugins::PluginRegistry,
 };
@@ -25,8 +27,10 @@ pub struct CachedResponse {
 pub struct AppState {
     pub config: Arc<RwLock<GatewayConfig>>,
     pub secrets: Arc<SecretsConfig>,
-    pub key_store: Arc<RwLock<ApiKeyStore>>,
+    pub auth_backends: Arc<AuthBackends>,
+    pub key_store: Arc<dyn KeyStore>,
     pub rate_limit_store: Arc<dyn RateLimitState>,
+    pub lockouts: Arc<Lockouts>,
     pub cache: Arc<Cache<String, Arc<CachedResponse>>>,
     pub http_client: Client,
     pub prometheus_handle: Option<PrometheusHandle>,
@@ -1,4 +1,6 @@
-use clap::Parser;
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use ipnet::IpNet;
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
@@ -7,8 +9,70 @@ pub struct Cli {
     #[arg(
         short,
         long,
+        global = true,
         value_name = "FILE",
-        default_value = "gateway_config.yaml"
+        default_value = "gateway.yaml"
     )]
     pub config: PathBuf,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Start the gateway (the default without a subcommand)
+    Run,
+    /// Check the configuration and the files it references; exits non-zero on
+    /// problems
+    Validate,
+    /// Print the route table
+    Routes,
+    /// Show which route and settings a request would hit
+    TestRoute {
+        method: String,
+        /// Full URL or just the path, e.g. /api/users?page=2
+        url: String,
+        /// Request header, as "Name: value"
+        #[arg(short = 'H', long = "header")]
+        headers: Vec<String>,
+    },
+    /// Manage the keys in the configured API key store
+    Keys {
+        #[command(subcommand)]
+        command: KeysCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum KeysCommand {
+    /// Generate a key, add it to the store and print it (shown only once)
+    Create {
+        #[arg(long)]
+        user_id: String,
+        #[arg(long, value_delimiter = ',')]
+        roles: Vec<String>,
+        #[arg(long)]
+        not_before: Option<DateTime<Utc>>,
+        #[arg(long)]
+        expires_at: Option<DateTime<Utc>>,
+        /// Route names or path patterns
+        #[arg(long, value_delimiter = ',')]
+        allowed_routes: Vec<String>,
+        #[arg(long, value_delimiter = ',')]
+        allowed_methods: Vec<String>,
+        #[arg(long, value_delimiter = ',')]
+        allowed_cidrs: Vec<IpNet>,
+    },
+    /// List the keys in the store
+    List,
+    /// Revoke a key by its ID
+    Revoke { id: String },
+    /// Replace a key with a new one with the same metadata and revoke it
+    Rotate { id: String },
+    /// Print the stored entry of a key
+    Show { id: String },
+    /// Read a key from stdin and print its hashed entry, e.g. to migrate a
+    /// plaintext one
+    Hash,
 }
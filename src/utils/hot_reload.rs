@@ -1,4 +1,5 @@
-// Watches the main config and API key files for changes and reloads them
+// Watches the main config, API key, Basic auth credential and JWT revocation
+// files for changes and reloads them
 
 use std::{fs, path::PathBuf, sync::Arc};
 
@@ -6,18 +7,40 @@ use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
 use tokio::sync::{RwLock, mpsc};
 use tracing::{error, info};
 
-use crate::config::{ApiKeyStore, GatewayConfig};
+use crate::{
+    config::GatewayConfig,
+    features::auth::{
+        basic::BasicCredentials, key_store::YamlKeyStore, revocation::RevocationList,
+    },
+};
 
 pub async fn watch_config_files(
     config_path: PathBuf,
     gateway_config: Arc<RwLock<GatewayConfig>>,
-    api_key_store: Arc<RwLock<ApiKeyStore>>,
+    // Unset when keys live in a database
+    api_key_store: Option<Arc<YamlKeyStore>>,
+    basic_credentials: Arc<RwLock<BasicCredentials>>,
+    revocation_list: Arc<RwLock<RevocationList>>,
 ) {
     info!("Starting Configuration file watcher...");
 
-    let api_key_store_path_rel = {
+    let (api_key_store_path_rel, basic_auth_path_rel, revocation_list_path_rel) = {
         let config_guard = gateway_config.read().await;
-        PathBuf::from(config_guard.identity.api_key_store_path.clone())
+        (
+            api_key_store
+                .as_ref()
+                .map(|_| PathBuf::from(config_guard.identity.api_key_store_path.clone())),
+            config_guard
+                .identity
+                .basic_auth_path
+                .clone()
+                .map(PathBuf::from),
+            config_guard
+                .identity
+                .revocation_list_path
+                .clone()
+                .map(PathBuf::from),
+        )
     };
 
     let gateway_config_path = match fs::canonicalize(&config_path) {
@@ -27,26 +50,58 @@ pub async fn watch_config_files(
             return;
         }
     };
-    let api_key_store_path = match fs::canonicalize(&api_key_store_path_rel) {
-        Ok(path) => path,
-        Err(e) => {
-            error!(path = ?api_key_store_path_rel, "Failed to get absolute path for API key store: {}", e);
-            return;
+    let api_key_store_path =
+        match api_key_store_path_rel.map(|path| (fs::canonicalize(&path), path)) {
+            Some((Ok(path), _)) => Some(path),
+            Some((Err(e), path)) => {
+                error!(path = ?path, "Failed to get absolute path for API key store: {}", e);
+                return;
+            }
+            None => None,
+        };
+
+    // Optional; Basic auth is not used when unset
+    let basic_auth_path = match basic_auth_path_rel.map(|path| (fs::canonicalize(&path), path)) {
+        Some((Ok(path), _)) => Some(path),
+        Some((Err(e), path)) => {
+            error!(path = ?path, "Failed to get absolute path for Basic auth credentials: {}", e);
+            None
         }
+        None => None,
     };
+    let revocation_list_path =
+        match revocation_list_path_rel.map(|path| (fs::canonicalize(&path), path)) {
+            Some((Ok(path), _)) => Some(path),
+            Some((Err(e), path)) => {
+                error!(path = ?path, "Failed to get absolute path for JWT revocation list: {}", e);
+                None
+            }
+            None => None,
+        };
 
     info!(gateway_config_path = ?gateway_config_path);
     info!(api_key_store_path = ?api_key_store_path);
 
     let gateway_config_clone = gateway_config.clone();
-    let api_key_store_clone = api_key_store.clone();
 
     let (tx, mut rx) = mpsc::channel(1);
 
+    // The API key store's directory is watched, so other files in it show up too
+    let watched: Vec<PathBuf> = [Some(gateway_config_path.clone())]
+        .into_iter()
+        .chain([
+            api_key_store_path.clone(),
+            basic_auth_path.clone(),
+            revocation_list_path.clone(),
+        ])
+        .flatten()
+        .collect();
+
     let mut watcher: RecommendedWatcher = match Watcher::new(
         move |res: Result<Event, notify::Error>| {
             if let Ok(event) = res
                 && (event.kind.is_modify() || event.kind.is_create())
+                && event.paths.iter().any(|path| watched.contains(path))
             {
                 tx.blocking_send(event)
                     .expect("Failed to send file change event");
@@ -65,8 +120,22 @@ pub async fn watch_config_files(
     if let Err(e) = watcher.watch(&gateway_config_path, RecursiveMode::NonRecursive) {
         error!(path = ?gateway_config_path, "Failed to watch gateway config file: {}", e);
     }
-    if let Err(e) = watcher.watch(&api_key_store_path, RecursiveMode::NonRecursive) {
-        error!(path = ?api_key_store_path, "Failed to watch API key store file: {}", e);
+    // `rustygw keys` replaces the file by renaming over it, which would end a
+    // watch on the file itself
+    if let Some(dir) = api_key_store_path.as_ref().and_then(|path| path.parent())
+        && let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive)
+    {
+        error!(path = ?dir, "Failed to watch API key store directory: {}", e);
+    }
+    if let Some(path) = &basic_auth_path
+        && let Err(e) = watcher.watch(path, RecursiveMode::NonRecursive)
+    {
+        error!(path = ?path, "Failed to watch Basic auth credentials file: {}", e);
+    }
+    if let Some(path) = &revocation_list_path
+        && let Err(e) = watcher.watch(path, RecursiveMode::NonRecursive)
+    {
+        error!(path = ?path, "Failed to watch JWT revocation list: {}", e);
     }
 
     //Process file change events
@@ -88,17 +157,49 @@ pub async fn watch_config_files(
                 }
             }
         }
-        if event.paths.contains(&api_key_store_path) {
-            match ApiKeyStore::load(&api_key_store_path) {
-                Ok(new_store) => {
-                    let mut store_writer = api_key_store_clone.write().await;
-                    *store_writer = new_store;
-                    info!("Successfully reloaded api_keys.yaml");
-                }
+        if let (Some(path), Some(store)) = (&api_key_store_path, &api_key_store)
+            && event.paths.contains(path)
+        {
+            match store.reload().await {
+                Ok(()) => info!("Successfully reloaded api_keys.yaml"),
                 Err(e) => {
                     error!("Failed to reload api_keys.yaml: {}. Keeping old config.", e);
                 }
             }
         }
+        if let Some(path) = &basic_auth_path
+            && event.paths.contains(path)
+        {
+            match BasicCredentials::load(path) {
+                Ok(new_credentials) => {
+                    let mut credentials_writer = basic_credentials.write().await;
+                    *credentials_writer = new_credentials;
+                    info!("Successfully reloaded Basic auth credentials");
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to reload Basic auth credentials: {}. Keeping old credentials.",
+                        e
+                    );
+                }
+            }
+        }
+        if let Some(path) = &revocation_list_path
+            && event.paths.contains(path)
+        {
+            match RevocationList::load(path) {
+                Ok(new_list) => {
+                    let mut list_writer = revocation_list.write().await;
+                    *list_writer = new_list;
+                    info!("Successfully reloaded JWT revocation list");
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to reload JWT revocation list: {}. Keeping old list.",
+                        e
+                    );
+                }
+            }
+        }
     }
 }
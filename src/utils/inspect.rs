@@ -0,0 +1,492 @@
+// Offline views of a config for the CLI: the semantic problems deserializing
+// it doesn't catch (`rustygw validate`), route summaries (`rustygw routes`)
+// and what a given request would hit (`rustygw test-route`).
+
+use std::{collections::HashSet, fmt::Display, net::SocketAddr, path::Path};
+
+use http::{HeaderMap, Method, Uri};
+use reqwest::{Client, Url};
+
+use crate::{
+    config::{
+        AuthConfig, AuthType, CredentialSource, GatewayConfig, IdentityConfig, KeyStoreBackend,
+        RoleExpr, RouteConfig, SecretsConfig,
+    },
+    errors::AppError,
+    features::auth::{
+        basic::{BasicCredentials, basic_credentials},
+        credentials::extract_credential,
+        jwt::{DEFAULT_PROVIDER, JwtProviders},
+        key_store::{SqliteKeyStore, YamlKeyStore},
+        policy,
+        propagation::{load_key, signing_key},
+        revocation::RevocationList,
+    },
+    middleware::rate_limiter::rate_limit::parse_duration,
+};
+
+// Answered by the gateway itself, ahead of any route
+const GATEWAY_PREFIX: &str = "/_gateway";
+const HEALTH_PATH: &str = "/health";
+const METRICS_PATH: &str = "/metrics";
+
+#[derive(Default)]
+struct Problems(Vec<String>);
+
+impl Problems {
+    fn add(&mut self, at: &str, problem: impl Display) {
+        self.0.push(format!("{}: {}", at, problem));
+    }
+
+    fn duration(&mut self, at: &str, field: &str, value: &str) {
+        if let Err(e) = parse_duration(value) {
+            self.add(at, format!("invalid {} '{}': {}", field, value, e));
+        }
+    }
+
+    fn url(&mut self, at: &str, field: &str, value: &str) {
+        match Url::parse(value) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            Ok(_) => self.add(at, format!("{} '{}' must be an http(s) URL", field, value)),
+            Err(e) => self.add(at, format!("invalid {} '{}': {}", field, value, e)),
+        }
+    }
+}
+
+/// Everything that would stop `config` from starting or serving as intended,
+/// one message per problem. Referenced files are loaded and JWKS fetched, as
+/// at startup.
+pub async fn validate(config: &GatewayConfig, secrets: &SecretsConfig) -> Vec<String> {
+    let mut problems = Problems::default();
+
+    if let Err(e) = config.server.addr.parse::<SocketAddr>() {
+        problems.add(
+            "server",
+            format!("invalid addr '{}': {}", config.server.addr, e),
+        );
+    }
+    if let Some(admin_addr) = &config.server.admin_addr
+        && let Err(e) = admin_addr.parse::<SocketAddr>()
+    {
+        problems.add(
+            "server",
+            format!("invalid admin_addr '{}': {}", admin_addr, e),
+        );
+    }
+    problems.duration("server", "drain_timeout", &config.server.drain_timeout);
+
+    check_identity(&config.identity, secrets, &mut problems).await;
+
+    let mut names = HashSet::new();
+    let mut paths = HashSet::new();
+    for route in &config.routes {
+        let at = format!("routes.{}", route.name);
+        if !names.insert(&route.name) {
+            problems.add(&at, "duplicate route name");
+        }
+        if !paths.insert(&route.path) {
+            problems.add(
+                &at,
+                format!("path '{}' is used by another route", route.path),
+            );
+        }
+        check_route(config, route, &at, &mut problems);
+    }
+    problems.0
+}
+
+async fn check_identity(
+    identity: &IdentityConfig,
+    secrets: &SecretsConfig,
+    problems: &mut Problems,
+) {
+    let at = "identity";
+    let path = &identity.api_key_store_path;
+    let key_store = match identity.api_key_store_backend {
+        KeyStoreBackend::Yaml => YamlKeyStore::load(path).map(|_| ()),
+        KeyStoreBackend::Sqlite if !Path::new(path).exists() => {
+            Err(anyhow::anyhow!("file does not exist"))
+        }
+        KeyStoreBackend::Sqlite => SqliteKeyStore::check(path),
+    };
+    if let Err(e) = key_store {
+        problems.add(at, format!("API key store '{}': {}", path, e));
+    }
+    problems.duration(at, "api_key_cache.ttl", &identity.api_key_cache.ttl);
+
+    if let Some(path) = &identity.basic_auth_path
+        && let Err(e) = BasicCredentials::load(path)
+    {
+        problems.add(at, format!("Basic auth credentials '{}': {}", path, e));
+    }
+    if let Some(path) = &identity.revocation_list_path
+        && let Err(e) = RevocationList::load(path)
+    {
+        problems.add(at, format!("JWT revocation list '{}': {}", path, e));
+    }
+    problems.duration(
+        at,
+        "runtime_revocation_ttl",
+        &identity.runtime_revocation_ttl,
+    );
+
+    if let Err(e) = JwtProviders::from_config(identity, secrets, Client::new()).await {
+        problems.add(at, format!("JWT providers: {}", e));
+    }
+    for provider in &identity.jwt_providers {
+        let at = format!("identity.jwt_providers.{}", provider.name);
+        problems.duration(&at, "leeway", &provider.validation.leeway);
+    }
+
+    if let Some(issuer) = &identity.token_issuer {
+        let at = "identity.token_issuer";
+        problems.duration(at, "access_token_ttl", &issuer.access_token_ttl);
+        problems.duration(at, "refresh_token_ttl", &issuer.refresh_token_ttl);
+        if let Err(e) = signing_key(
+            issuer.algorithm,
+            secrets.jwt_secret.as_deref(),
+            issuer.private_key_path.as_deref(),
+        ) {
+            problems.add(at, format!("signing key: {}", e));
+        }
+    }
+
+    if let Some(lockout) = &identity.lockout {
+        let at = "identity.lockout";
+        if lockout.ip_failures == 0 || lockout.credential_failures == 0 {
+            problems.add(at, "failure thresholds must be greater than 0");
+        }
+        problems.duration(at, "window", &lockout.window);
+        problems.duration(at, "duration", &lockout.duration);
+        problems.duration(at, "delay", &lockout.delay);
+        problems.duration(at, "max_delay", &lockout.max_delay);
+    }
+}
+
+fn check_route(config: &GatewayConfig, route: &RouteConfig, at: &str, problems: &mut Problems) {
+    if !route.path.starts_with('/') {
+        problems.add(at, format!("path '{}' must start with '/'", route.path));
+    } else if is_gateway_path(config, &route.path) {
+        problems.add(
+            at,
+            format!("path '{}' is answered by the gateway itself", route.path),
+        );
+    }
+    problems.url(at, "destination", &route.destination);
+
+    if let Some(rate_limit) = &route.rate_limit {
+        if rate_limit.requests == 0 {
+            problems.add(at, "rate_limit.requests must be greater than 0");
+        }
+        if parse_duration(&rate_limit.period).is_ok_and(|period| period.is_zero()) {
+            problems.add(at, "rate_limit.period must be longer than 0s");
+        }
+        problems.duration(at, "rate_limit.period", &rate_limit.period);
+    }
+    if let Some(cache) = &route.cache {
+        problems.duration(at, "cache.ttl", &cache.ttl);
+    }
+    if let Some(breaker) = &route.circuit_breaker {
+        if breaker.failure_threshold == 0 || breaker.success_threshold == 0 {
+            problems.add(at, "circuit_breaker thresholds must be greater than 0");
+        }
+        problems.duration(at, "circuit_breaker.open_duration", &breaker.open_duration);
+    }
+    if let Some(auth) = &route.auth {
+        check_auth(config, route, auth, at, problems);
+    }
+}
+
+fn check_auth(
+    config: &GatewayConfig,
+    route: &RouteConfig,
+    auth: &AuthConfig,
+    at: &str,
+    problems: &mut Problems,
+) {
+    let identity = &config.identity;
+    let provider_exists = |name: &str| {
+        name == DEFAULT_PROVIDER || identity.jwt_providers.iter().any(|p| p.name == name)
+    };
+    let missing = |block: &str| format!("{:?} auth needs its '{}' block", auth.auth_type, block);
+
+    match auth.auth_type {
+        AuthType::Jwt | AuthType::ApiKey => {}
+        AuthType::Introspection => match &auth.introspection {
+            Some(introspection) => {
+                problems.url(at, "introspection.endpoint", &introspection.endpoint);
+                problems.duration(at, "introspection.cache_ttl", &introspection.cache_ttl);
+                problems.duration(
+                    at,
+                    "introspection.negative_cache_ttl",
+                    &introspection.negative_cache_ttl,
+                );
+                problems.duration(at, "introspection.timeout", &introspection.timeout);
+            }
+            None => problems.add(at, missing("introspection")),
+        },
+        AuthType::Oidc => match &auth.oidc {
+            Some(oidc) => {
+                problems.url(
+                    at,
+                    "oidc.authorization_endpoint",
+                    &oidc.authorization_endpoint,
+                );
+                problems.url(at, "oidc.token_endpoint", &oidc.token_endpoint);
+                problems.url(at, "oidc.redirect_uri", &oidc.redirect_uri);
+                if Url::parse(&oidc.redirect_uri)
+                    .is_ok_and(|url| !url.path().starts_with(&route.path))
+                {
+                    problems.add(
+                        at,
+                        format!("oidc.redirect_uri must be under '{}'", route.path),
+                    );
+                }
+                if !provider_exists(&oidc.jwt_provider) {
+                    problems.add(
+                        at,
+                        format!("unknown oidc.jwt_provider '{}'", oidc.jwt_provider),
+                    );
+                }
+                problems.duration(at, "oidc.session_ttl", &oidc.session_ttl);
+            }
+            None => problems.add(at, missing("oidc")),
+        },
+        AuthType::External => match &auth.external {
+            Some(external) => {
+                problems.url(at, "external.url", &external.url);
+                problems.duration(at, "external.timeout", &external.timeout);
+                if let Some(ttl) = &external.cache_ttl {
+                    problems.duration(at, "external.cache_ttl", ttl);
+                }
+            }
+            None => problems.add(at, missing("external")),
+        },
+        AuthType::Basic => {
+            if identity.basic_auth_path.is_none() {
+                problems.add(at, "Basic auth needs identity.basic_auth_path");
+            }
+        }
+        AuthType::Signature => match &auth.signature {
+            Some(signature) => {
+                if signature.key_id.is_none() && signature.key_id_header.is_none() {
+                    problems.add(at, "signature needs 'key_id' or 'key_id_header'");
+                }
+                if let Some(unsigned) = signature.unsigned_header() {
+                    problems.add(
+                        at,
+                        format!(
+                            "signature.canonical must include the value of '{}'",
+                            unsigned
+                        ),
+                    );
+                }
+                problems.duration(at, "signature.tolerance", &signature.tolerance);
+            }
+            None => problems.add(at, missing("signature")),
+        },
+    }
+
+    for name in auth.providers.iter().flatten() {
+        if !provider_exists(name) {
+            problems.add(at, format!("unknown JWT provider '{}'", name));
+        }
+    }
+    if let Some(leeway) = auth.jwt.as_ref().and_then(|jwt| jwt.leeway.as_ref()) {
+        problems.duration(at, "jwt.leeway", leeway);
+    }
+    for rule in &auth.policies {
+        if rule
+            .path
+            .as_ref()
+            .is_some_and(|path| !path.starts_with('/'))
+        {
+            problems.add(
+                at,
+                format!("path of policy '{}' must start with '/'", rule.name),
+            );
+        }
+    }
+    if let Some(internal_jwt) = auth
+        .propagate
+        .as_ref()
+        .and_then(|propagate| propagate.internal_jwt.as_ref())
+    {
+        problems.duration(at, "propagate.internal_jwt.ttl", &internal_jwt.ttl);
+        if let Err(e) = load_key(internal_jwt) {
+            problems.add(at, format!("propagate.internal_jwt signing key: {}", e));
+        }
+    }
+}
+
+fn is_gateway_path(config: &GatewayConfig, path: &str) -> bool {
+    path == HEALTH_PATH
+        || path.starts_with(GATEWAY_PREFIX)
+        || (config.observability.metrics.enabled && path == METRICS_PATH)
+}
+
+pub fn describe_auth(route: &RouteConfig) -> String {
+    let Some(auth) = &route.auth else {
+        return "none".to_string();
+    };
+    let mut description = format!("{:?}", auth.auth_type);
+    if let Some(roles) = &auth.roles {
+        description.push_str(&format!(", roles {}", describe_roles(roles)));
+    }
+    if let Some(providers) = &auth.providers {
+        description.push_str(&format!(", providers {}", providers.join(",")));
+    }
+    description
+}
+
+pub fn describe_roles(expr: &RoleExpr) -> String {
+    let list = |exprs: &[RoleExpr]| {
+        exprs
+            .iter()
+            .map(describe_roles)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match expr {
+        RoleExpr::Role(role) => role.clone(),
+        RoleExpr::All(exprs) => format!("all_of({})", list(exprs)),
+        RoleExpr::Block(block) => [
+            ("any_of", &block.any_of),
+            ("all_of", &block.all_of),
+            ("none_of", &block.none_of),
+        ]
+        .into_iter()
+        .filter_map(|(name, exprs)| {
+            exprs
+                .as_ref()
+                .map(|exprs| format!("{}({})", name, list(exprs)))
+        })
+        .collect::<Vec<_>>()
+        .join(" "),
+    }
+}
+
+pub fn describe_rate_limit(route: &RouteConfig) -> String {
+    route.rate_limit.as_ref().map_or("-".to_string(), |limit| {
+        format!("{} per {} per client IP", limit.requests, limit.period)
+    })
+}
+
+pub fn describe_cache(route: &RouteConfig) -> String {
+    route
+        .cache
+        .as_ref()
+        .map_or("-".to_string(), |cache| format!("ttl {}", cache.ttl))
+}
+
+pub fn describe_circuit_breaker(route: &RouteConfig) -> String {
+    route
+        .circuit_breaker
+        .as_ref()
+        .map_or("-".to_string(), |breaker| {
+            format!(
+                "opens after {} failures for {}, closes after {} successes",
+                breaker.failure_threshold, breaker.open_duration, breaker.success_threshold
+            )
+        })
+}
+
+/// How the gateway would handle a request, as label and value pairs; `None`
+/// when no route matches.
+pub fn trace_request(
+    config: &GatewayConfig,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Option<Vec<(&'static str, String)>> {
+    let path = uri.path();
+    if is_gateway_path(config, path) {
+        return Some(vec![(
+            "route",
+            "answered by the gateway itself".to_string(),
+        )]);
+    }
+    let route = config.find_route_for_path(path)?;
+
+    let mut lines = vec![
+        ("route", format!("{} ({})", route.name, route.path)),
+        (
+            "upstream",
+            format!("{} {}", method, route.upstream_url(path)),
+        ),
+        ("auth", describe_auth(&route)),
+    ];
+
+    if let Some(auth) = &route.auth {
+        if let Some((_, roles)) = auth
+            .method_roles
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(method.as_str()))
+        {
+            lines.push(("method roles", describe_roles(roles)));
+        }
+        if let Some(credential) = describe_credential(auth, headers, uri) {
+            lines.push(("credential", credential));
+        }
+        let policies: Vec<_> = auth
+            .policies
+            .iter()
+            .filter(|rule| policy::applies(rule, method, path))
+            .map(|rule| rule.name.as_str())
+            .collect();
+        if !policies.is_empty() {
+            lines.push(("policies", policies.join(", ")));
+        }
+    }
+
+    let mut rate_limit = describe_rate_limit(&route);
+    if route
+        .auth
+        .as_ref()
+        .is_some_and(|auth| auth.auth_type == AuthType::ApiKey)
+    {
+        rate_limit.push_str(" (unless the key sets its own)");
+    }
+    lines.push(("rate limit", rate_limit));
+
+    let cache = match &route.cache {
+        Some(_) if !method.is_safe() => format!("bypassed for {}", method),
+        _ => describe_cache(&route),
+    };
+    lines.push(("cache", cache));
+    lines.push(("circuit breaker", describe_circuit_breaker(&route)));
+    Some(lines)
+}
+
+fn describe_credential(auth: &AuthConfig, headers: &HeaderMap, uri: &Uri) -> Option<String> {
+    match auth.auth_type {
+        AuthType::Jwt | AuthType::ApiKey | AuthType::Introspection => {
+            let sources = auth
+                .credentials
+                .iter()
+                .map(|source| match source {
+                    CredentialSource::Bearer => "bearer".to_string(),
+                    CredentialSource::Header(name) => format!("header:{}", name),
+                    CredentialSource::Query(name) => format!("query:{}", name),
+                    CredentialSource::Cookie(name) => format!("cookie:{}", name),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            Some(match extract_credential(headers, uri, &auth.credentials) {
+                Ok(_) => format!("present (from {})", sources),
+                Err(AppError::InvalidAuthHeader) => {
+                    format!(
+                        "Authorization header is not Bearer (expected in {})",
+                        sources
+                    )
+                }
+                Err(_) => format!("missing (expected in {})", sources),
+            })
+        }
+        AuthType::Basic => Some(match basic_credentials(headers) {
+            Some((username, _)) => format!("Basic credentials for '{}'", username),
+            None => "missing Basic credentials".to_string(),
+        }),
+        _ => None,
+    }
+}
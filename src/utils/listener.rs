@@ -0,0 +1,262 @@
+// Binds the gateway's listening socket and hands it over to a freshly spawned
+// gateway process so a new binary can take over traffic without a gap.
+// Also wraps the socket to strip PROXY protocol headers from trusted peers.
+
+use std::{
+    io,
+    net::{SocketAddr, TcpListener},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, Error, anyhow};
+use socket2::{Domain, SockRef, Socket, Type};
+use tokio::{
+    io::AsyncReadExt,
+    net::{TcpStream, UnixStream},
+    process::Command,
+    task::JoinSet,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    config::{ProxyProtocolConfig, ServerConfig},
+    features::proxy_protocol::proxy_protocol::read_header,
+};
+
+// Set by the parent process to the inherited listening socket's descriptor
+pub const LISTEN_FD_ENV: &str = "RUSTYGW_LISTEN_FD";
+// Socket the successor writes a byte to once it serves
+pub const READY_FD_ENV: &str = "RUSTYGW_READY_FD";
+
+const LISTEN_BACKLOG: i32 = 1024;
+
+// Upper bound for a trusted peer to send its PROXY header
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn bind(server: &ServerConfig) -> Result<TcpListener, Error> {
+    if let Some(listener) = inherited_listener()? {
+        return Ok(listener);
+    }
+    bind_addr(&server.addr, server.reuse_port)
+}
+
+/// Binds `server.admin_addr`. It isn't handed over on upgrade; SO_REUSEPORT
+/// lets the successor bind it while this process still holds it.
+pub fn bind_admin(addr: &str) -> Result<TcpListener, Error> {
+    bind_addr(addr, true)
+}
+
+fn bind_addr(addr: &str, reuse_port: bool) -> Result<TcpListener, Error> {
+    let addr: SocketAddr = addr
+        .parse()
+        .with_context(|| format!("Invalid server address '{}'", addr))?;
+
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    socket.set_reuse_address(true)?;
+    if reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+
+    Ok(socket.into())
+}
+
+/// Starts `successor` with the listening socket and waits until it reports
+/// that it is serving. Until then (and if it exits or times out instead) the
+/// caller keeps accepting; once this returns Ok it should stop and drain.
+pub async fn hand_over(
+    listener_fd: RawFd,
+    mut successor: Command,
+    ready_timeout: Duration,
+) -> Result<(), Error> {
+    let (ready, successor_end) = Socket::pair(Domain::UNIX, Type::STREAM, None)?;
+
+    // SAFETY: the descriptor belongs to the listener that is still being served.
+    let socket = unsafe { std::os::fd::BorrowedFd::borrow_raw(listener_fd) };
+    let sock_ref = SockRef::from(&socket);
+
+    // Both descriptors must survive exec for the child to pick them up.
+    sock_ref.set_cloexec(false)?;
+    successor_end.set_cloexec(false)?;
+    let child = successor
+        .env(LISTEN_FD_ENV, listener_fd.to_string())
+        .env(READY_FD_ENV, successor_end.as_raw_fd().to_string())
+        .kill_on_drop(false)
+        .spawn();
+    sock_ref.set_cloexec(true)?;
+    // Only the child may hold its end, so its exit shows up as EOF
+    drop(successor_end);
+
+    let mut child = child.context("Failed to spawn successor gateway process")?;
+    let pid = child.id();
+    info!(pid, fd = listener_fd, "Spawned successor gateway process");
+
+    ready.set_nonblocking(true)?;
+    let mut ready =
+        UnixStream::from_std(std::os::unix::net::UnixStream::from(OwnedFd::from(ready)))?;
+    let outcome = tokio::time::timeout(ready_timeout, async {
+        tokio::select! {
+            signal = ready.read_u8() => signal.is_ok(),
+            _ = child.wait() => false,
+        }
+    })
+    .await;
+
+    match outcome {
+        Ok(true) => {
+            info!(pid, "Successor gateway process is ready");
+            // Reaped here in case it exits while this process still drains
+            tokio::spawn(async move {
+                match child.wait().await {
+                    Ok(status) => info!(pid, %status, "Successor gateway process exited"),
+                    Err(e) => error!(pid, "Failed to wait for successor gateway process: {}", e),
+                }
+            });
+            Ok(())
+        }
+        Ok(false) => {
+            let status = child.wait().await?;
+            Err(anyhow!(
+                "successor gateway process exited before it was ready ({})",
+                status
+            ))
+        }
+        Err(_) => {
+            child.kill().await?;
+            Err(anyhow!(
+                "successor gateway process was not ready within {:?}",
+                ready_timeout
+            ))
+        }
+    }
+}
+
+/// Tells the parent of a handed over process that it is serving now.
+pub fn notify_ready() -> Result<(), Error> {
+    let Ok(fd) = std::env::var(READY_FD_ENV) else {
+        return Ok(());
+    };
+    let fd: RawFd = fd
+        .parse()
+        .with_context(|| format!("Invalid {} value '{}'", READY_FD_ENV, fd))?;
+
+    // SAFETY: the parent process passed us ownership of this descriptor.
+    let socket = unsafe { Socket::from_raw_fd(fd) };
+    socket.send(&[1])?;
+    Ok(())
+}
+
+fn inherited_listener() -> Result<Option<TcpListener>, Error> {
+    let fd = match std::env::var(LISTEN_FD_ENV) {
+        Ok(fd) => fd,
+        Err(_) => return Ok(None),
+    };
+
+    let fd: RawFd = fd
+        .parse()
+        .with_context(|| format!("Invalid {} value '{}'", LISTEN_FD_ENV, fd))?;
+
+    // SAFETY: the parent process passed us ownership of this descriptor.
+    let socket = unsafe { Socket::from_raw_fd(fd) };
+    socket.set_cloexec(true)?;
+    socket.set_nonblocking(true)?;
+
+    let listener: TcpListener = socket.into();
+    info!(fd = listener.as_raw_fd(), addr = ?listener.local_addr()?, "Inherited listening socket from parent process");
+    Ok(Some(listener))
+}
+
+// axum listener that reports the original client address for connections
+// arriving through a trusted PROXY protocol speaking load balancer.
+pub struct GatewayListener {
+    inner: tokio::net::TcpListener,
+    proxy_protocol: Option<Arc<ProxyProtocolConfig>>,
+    // Trusted connections whose PROXY header is still being read
+    pending: JoinSet<Option<(TcpStream, SocketAddr)>>,
+}
+
+impl GatewayListener {
+    pub fn new(
+        inner: tokio::net::TcpListener,
+        proxy_protocol: Option<ProxyProtocolConfig>,
+    ) -> Self {
+        Self {
+            inner,
+            proxy_protocol: proxy_protocol.filter(|c| c.enabled).map(Arc::new),
+            pending: JoinSet::new(),
+        }
+    }
+}
+
+impl axum::serve::Listener for GatewayListener {
+    type Io = TcpStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            tokio::select! {
+                accepted = self.inner.accept() => {
+                    let (mut stream, peer) = match accepted {
+                        Ok(conn) => conn,
+                        Err(e) => {
+                            handle_accept_error(e).await;
+                            continue;
+                        }
+                    };
+
+                    let trusted = self
+                        .proxy_protocol
+                        .as_ref()
+                        .is_some_and(|config| config.is_trusted(&peer.ip()));
+                    if !trusted {
+                        return (stream, peer);
+                    }
+
+                    // Read the header off the accept loop so a slow peer cannot stall it.
+                    self.pending.spawn(async move {
+                        match tokio::time::timeout(PROXY_HEADER_TIMEOUT, read_header(&mut stream)).await {
+                            Ok(Ok(Some(client))) => Some((stream, client)),
+                            Ok(Ok(None)) => Some((stream, peer)),
+                            Ok(Err(e)) => {
+                                warn!(%peer, "Dropping connection: {}", e);
+                                None
+                            }
+                            Err(_) => {
+                                warn!(%peer, "Dropping connection: timed out waiting for PROXY header");
+                                None
+                            }
+                        }
+                    });
+                }
+                Some(parsed) = self.pending.join_next() => {
+                    if let Ok(Some(conn)) = parsed {
+                        return conn;
+                    }
+                }
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        self.inner.local_addr()
+    }
+}
+
+async fn handle_accept_error(e: io::Error) {
+    if matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    ) {
+        return;
+    }
+
+    // Most likely out of file descriptors; give in-flight connections a chance to close.
+    error!("accept error: {}", e);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+}
@@ -1,3 +1,7 @@
 pub mod config_path;
 pub mod hot_reload;
+pub mod inspect;
+pub mod listener;
 pub mod metric_handler;
+pub mod revocation_handler;
+pub mod token_handler;
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use axum::{Json, extract::State};
+use chrono::{DateTime, Utc};
+use http::{HeaderMap, StatusCode, header};
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+
+use crate::{errors::AppError, state::AppState};
+
+// `{"jti": "..."}` revokes one token; `{"sub": "..."}` revokes every token of
+// the subject issued at or before `issued_before` (default: now).
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum RevocationRequest {
+    Token {
+        jti: String,
+    },
+    Subject {
+        sub: String,
+        issued_before: Option<DateTime<Utc>>,
+    },
+}
+
+// Only served when REVOCATION_ADMIN_TOKEN is set. Entries are kept in memory
+// and handed to the new process on a binary upgrade, during which this one
+// answers 503; add them to the revocation list file to survive restarts.
+pub async fn revocation_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<RevocationRequest>,
+) -> Result<StatusCode, AppError> {
+    let Some(admin_token) = &state.secrets.revocation_admin_token else {
+        return Err(AppError::RouteNotFound);
+    };
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(AppError::MissingAuthToken)?
+        .strip_prefix("Bearer ")
+        .ok_or(AppError::InvalidAuthHeader)?;
+    if !bool::from(presented.as_bytes().ct_eq(admin_token.as_bytes())) {
+        return Err(AppError::AuthFailed("Invalid admin token.".to_string()));
+    }
+
+    let revocations = &state.auth_backends.revocations;
+    match request {
+        RevocationRequest::Token { jti } => revocations.revoke_token(jti).await?,
+        RevocationRequest::Subject { sub, issued_before } => {
+            revocations
+                .revoke_subject(sub, issued_before.unwrap_or_else(Utc::now))
+                .await?
+        }
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use axum::{
+    Form, Json,
+    extract::{FromRequest, Request, State},
+    response::IntoResponse,
+};
+use axum_client_ip::ClientIp;
+use http::header;
+
+use crate::{
+    errors::AppError,
+    features::auth::{
+        lockout::{AuthAttempt, is_guess},
+        token_issuer::{TokenRequest, TokenResponse},
+    },
+    state::AppState,
+};
+
+// Only served when `identity.token_issuer` is configured. Accepts form or JSON
+// bodies.
+pub async fn token_handler(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+) -> Result<impl IntoResponse, AppError> {
+    let (config, lockout) = {
+        let config_guard = state.config.read().await;
+        (
+            config_guard.identity.token_issuer.clone(),
+            config_guard.identity.lockout.clone(),
+        )
+    };
+    let Some(config) = config else {
+        return Err(AppError::RouteNotFound);
+    };
+
+    let headers = request.headers().clone();
+    let client_ip = request.extensions().get::<ClientIp>().map(|ip| ip.0);
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let token_request = if is_json {
+        Json::<TokenRequest>::from_request(request, &())
+            .await
+            .map(|Json(request)| request)
+            .map_err(|e| AppError::InvalidRequest(e.body_text()))?
+    } else {
+        Form::<TokenRequest>::from_request(request, &())
+            .await
+            .map(|Form(request)| request)
+            .map_err(|e| AppError::InvalidRequest(e.body_text()))?
+    };
+
+    let attempt = lockout
+        .as_ref()
+        .map(|lockout| AuthAttempt::new(client_ip, token_request.credential(&headers), lockout));
+    if let Some(attempt) = &attempt {
+        state.lockouts.check(attempt).await?;
+    }
+
+    let issued = state
+        .auth_backends
+        .token_issuer
+        .issue(
+            token_request,
+            &headers,
+            state.key_store.as_ref(),
+            &config,
+            &state.secrets,
+        )
+        .await;
+    match issued {
+        // Tokens must not be kept by caches along the way (RFC 6749 5.1)
+        Ok(response) => Ok((
+            [
+                (header::CACHE_CONTROL, "no-store"),
+                (header::PRAGMA, "no-cache"),
+            ],
+            Json::<TokenResponse>(response),
+        )),
+        Err(e) => {
+            if let Some(attempt) = attempt.as_ref().filter(|_| is_guess(&e)) {
+                state.lockouts.record_failure(attempt).await?;
+            }
+            Err(e)
+        }
+    }
+}
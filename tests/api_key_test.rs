@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use axum::response::IntoResponse;
+use chrono::{Duration, Utc};
+use http::{Method, StatusCode};
+use rustway::{
+    config::{ApiKeyDetails, ApiKeyStore, KeyStatus, RouteConfig},
+    errors::AppError,
+    features::auth::{
+        api_key::{check_key_access, find_key, generate_key, hash_key, verify_key},
+        key_store::YamlKeyStore,
+    },
+};
+
+fn details(hash: Option<String>, user_id: &str) -> ApiKeyDetails {
+    ApiKeyDetails {
+        hash,
+        user_id: user_id.to_string(),
+        roles: vec!["user".to_string()],
+        ..Default::default()
+    }
+}
+
+fn store(entries: Vec<(String, ApiKeyDetails)>, hmac_secret: Option<&[u8]>) -> YamlKeyStore {
+    YamlKeyStore::from(ApiKeyStore {
+        keys: entries.into_iter().collect::<HashMap<_, _>>(),
+        hmac_secret: hmac_secret.map(<[u8]>::to_vec),
+    })
+}
+
+async fn find(store: &YamlKeyStore, key: &str) -> Option<ApiKeyDetails> {
+    find_key(store, key).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_generated_key_matches_only_its_digest() {
+    let generated = generate_key(None);
+    assert!(generated.key.starts_with(&format!("{}.", generated.id)));
+    assert!(generated.hash.starts_with("sha256:"));
+
+    let store = store(
+        vec![(generated.id.clone(), details(Some(generated.hash), "alice"))],
+        None,
+    );
+    assert_eq!(find(&store, &generated.key).await.unwrap().user_id, "alice");
+
+    let forged = format!("{}.not-the-secret", generated.id);
+    assert!(find(&store, &forged).await.is_none());
+    // The ID alone is not a key.
+    assert!(find(&store, &generated.id).await.is_none());
+}
+
+#[tokio::test]
+async fn test_hmac_digests_need_the_secret() {
+    let generated = generate_key(Some(b"pepper"));
+    assert!(generated.hash.starts_with("hmac-sha256:"));
+    assert_ne!(generated.hash, hash_key(&generated.key, None));
+
+    let entries = vec![(generated.id.clone(), details(Some(generated.hash), "bob"))];
+    let with_secret = store(entries.clone(), Some(b"pepper"));
+    assert!(find(&with_secret, &generated.key).await.is_some());
+
+    let without_secret = store(entries.clone(), None);
+    assert!(find(&without_secret, &generated.key).await.is_none());
+
+    let wrong_secret = store(entries, Some(b"salt"));
+    assert!(find(&wrong_secret, &generated.key).await.is_none());
+}
+
+#[tokio::test]
+async fn test_plaintext_entries_still_work() {
+    let store = store(
+        vec![("legacy-key".to_string(), details(None, "carol"))],
+        None,
+    );
+    assert_eq!(find(&store, "legacy-key").await.unwrap().user_id, "carol");
+    assert!(find(&store, "legacy-key.extra").await.is_none());
+}
+
+#[tokio::test]
+async fn test_migrated_legacy_keys_are_found_by_digest() {
+    // What `keys hash` prints for a key without an ID prefix
+    for hmac_secret in [None, Some(&b"pepper"[..])] {
+        let digest = hash_key("legacy-key", hmac_secret);
+        let store = store(
+            vec![(digest.clone(), details(Some(digest), "carol"))],
+            hmac_secret,
+        );
+        assert_eq!(find(&store, "legacy-key").await.unwrap().user_id, "carol");
+        assert!(find(&store, "other-key").await.is_none());
+    }
+
+    // A digest is not a key
+    let digest = hash_key("legacy-key", None);
+    let store = store(
+        vec![(digest.clone(), details(Some(digest.clone()), "carol"))],
+        None,
+    );
+    assert!(find(&store, &digest).await.is_none());
+}
+
+fn route(name: &str, path: &str) -> RouteConfig {
+    serde_yaml::from_str(&format!(
+        "name: \"{}\"\npath: \"{}\"\ndestination: \"http://upstream\"",
+        name, path
+    ))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_status_and_validity_window() {
+    let cases = [
+        (
+            ApiKeyDetails {
+                status: KeyStatus::Revoked,
+                ..details(None, "a")
+            },
+            StatusCode::UNAUTHORIZED,
+        ),
+        (
+            ApiKeyDetails {
+                status: KeyStatus::Suspended,
+                ..details(None, "a")
+            },
+            StatusCode::FORBIDDEN,
+        ),
+        (
+            ApiKeyDetails {
+                expires_at: Some(Utc::now() - Duration::minutes(1)),
+                ..details(None, "a")
+            },
+            StatusCode::UNAUTHORIZED,
+        ),
+        (
+            ApiKeyDetails {
+                not_before: Some(Utc::now() + Duration::hours(1)),
+                ..details(None, "a")
+            },
+            StatusCode::UNAUTHORIZED,
+        ),
+    ];
+
+    for (entry, status) in cases {
+        let store = store(vec![("key".to_string(), entry)], None);
+        let error = verify_key(&store, "key").await.unwrap_err();
+        assert!(!matches!(error, AppError::AuthFailed(_)));
+        assert_eq!(error.into_response().status(), status);
+    }
+
+    let active = ApiKeyDetails {
+        not_before: Some(Utc::now() - Duration::hours(1)),
+        expires_at: Some(Utc::now() + Duration::hours(1)),
+        ..details(None, "a")
+    };
+    let store = store(vec![("key".to_string(), active)], None);
+    assert!(verify_key(&store, "key").await.is_ok());
+}
+
+#[test]
+fn test_metadata_from_yaml() {
+    let entry: ApiKeyDetails = serde_yaml::from_str(
+        "user_id: \"ci\"\nroles: []\nstatus: suspended\nexpires_at: \"2030-01-01T00:00:00Z\"\nallowed_cidrs: [\"10.0.0.0/8\"]\nrate_limit:\n  requests: 5\n  period: \"1m\"\n",
+    )
+    .unwrap();
+    assert_eq!(entry.status, KeyStatus::Suspended);
+    assert_eq!(
+        entry.expires_at.unwrap().to_rfc3339(),
+        "2030-01-01T00:00:00+00:00"
+    );
+    assert_eq!(entry.rate_limit.unwrap().requests, 5);
+}
+
+#[test]
+fn test_route_method_and_cidr_restrictions() {
+    let entry = ApiKeyDetails {
+        allowed_routes: vec!["reports".to_string(), "/hooks/github/*".to_string()],
+        allowed_methods: vec!["get".to_string(), "POST".to_string()],
+        allowed_cidrs: vec!["10.0.0.0/8".parse().unwrap()],
+        ..details(None, "ci")
+    };
+    let internal = Some("10.1.2.3".parse().unwrap());
+    let reports = route("reports", "/reports");
+    let hooks = route("hooks", "/hooks");
+
+    assert!(check_key_access(&entry, &reports, "/reports/daily", &Method::GET, internal).is_ok());
+    assert!(
+        check_key_access(
+            &entry,
+            &hooks,
+            "/hooks/github/push",
+            &Method::POST,
+            internal
+        )
+        .is_ok()
+    );
+    assert!(
+        check_key_access(
+            &entry,
+            &hooks,
+            "/hooks/github/./push",
+            &Method::POST,
+            internal
+        )
+        .is_ok()
+    );
+
+    for (route, path, method, ip) in [
+        (&hooks, "/hooks/gitlab/push", Method::POST, internal),
+        // Upstreams resolve these to /hooks/gitlab/push
+        (
+            &hooks,
+            "/hooks/github/../gitlab/push",
+            Method::POST,
+            internal,
+        ),
+        (
+            &hooks,
+            "/hooks/github/%2e%2e/gitlab/push",
+            Method::POST,
+            internal,
+        ),
+        (
+            &hooks,
+            "/hooks/github/%2E%2E/gitlab/push",
+            Method::POST,
+            internal,
+        ),
+        // Upstreams disagree on what an encoded '/' means
+        (
+            &hooks,
+            "/hooks/github%2f..%2fgitlab/push",
+            Method::POST,
+            internal,
+        ),
+        (&reports, "/reports", Method::DELETE, internal),
+        (
+            &reports,
+            "/reports",
+            Method::GET,
+            Some("192.0.2.1".parse().unwrap()),
+        ),
+        (&reports, "/reports", Method::GET, None),
+    ] {
+        let error = check_key_access(&entry, route, path, &method, ip).unwrap_err();
+        assert!(matches!(error, AppError::ApiKeyNotAllowed(_)));
+        assert_eq!(error.into_response().status(), StatusCode::FORBIDDEN);
+    }
+}
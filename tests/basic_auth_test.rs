@@ -0,0 +1,136 @@
+use std::{path::PathBuf, sync::Arc};
+
+use argon2::{Argon2, PasswordHasher, password_hash::SaltString};
+use axum::response::IntoResponse;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use http::{HeaderMap, HeaderValue, StatusCode, header};
+use rustway::{
+    errors::AppError,
+    features::auth::basic::{BasicAuthenticator, BasicCredentials},
+};
+use tokio::sync::RwLock;
+
+fn write_file(name: &str, content: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rustygw-{}-{}", std::process::id(), name));
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+fn bcrypt_hash(password: &str) -> String {
+    bcrypt::hash(password, 4).unwrap()
+}
+
+fn argon2_hash(password: &str) -> String {
+    let salt = SaltString::encode_b64(b"fixed-test-salt").unwrap();
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .unwrap()
+        .to_string()
+}
+
+fn basic(username: &str, password: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let encoded = STANDARD.encode(format!("{}:{}", username, password));
+    headers.insert(
+        header::AUTHORIZATION,
+        HeaderValue::from_str(&format!("Basic {}", encoded)).unwrap(),
+    );
+    headers
+}
+
+fn authenticator(credentials: BasicCredentials) -> BasicAuthenticator {
+    BasicAuthenticator::new(Arc::new(RwLock::new(credentials)))
+}
+
+#[tokio::test]
+async fn test_htpasswd_users_with_roles() {
+    let path = write_file(
+        "htpasswd",
+        &format!(
+            "# legacy clients\nalice:{}:admin, user\nbob:{}\n",
+            bcrypt_hash("wonderland"),
+            argon2_hash("builder")
+        ),
+    );
+    let authenticator = authenticator(BasicCredentials::load(&path).unwrap());
+
+    let claims = authenticator
+        .verify(&basic("alice", "wonderland"), None)
+        .await
+        .unwrap();
+    assert_eq!(claims.sub, "alice");
+    assert_eq!(claims.roles, vec!["admin", "user"]);
+
+    let claims = authenticator
+        .verify(&basic("bob", "builder"), None)
+        .await
+        .unwrap();
+    assert!(claims.roles.is_empty());
+}
+
+#[tokio::test]
+async fn test_yaml_credentials() {
+    let path = write_file(
+        "users.yaml",
+        &format!(
+            "users:\n  reporting:\n    password_hash: \"{}\"\n    roles: [\"reports\"]\n",
+            argon2_hash("s3cret")
+        ),
+    );
+    let authenticator = authenticator(BasicCredentials::load(&path).unwrap());
+
+    let claims = authenticator
+        .verify(&basic("reporting", "s3cret"), None)
+        .await
+        .unwrap();
+    assert_eq!(claims.roles, vec!["reports"]);
+}
+
+#[tokio::test]
+async fn test_failures_carry_a_basic_challenge() {
+    let path = write_file(
+        "challenge",
+        &format!("alice:{}\n", bcrypt_hash("wonderland")),
+    );
+    let authenticator = authenticator(BasicCredentials::load(&path).unwrap());
+
+    for headers in [
+        basic("alice", "guess"),
+        basic("mallory", "wonderland"),
+        HeaderMap::new(),
+    ] {
+        let error = authenticator
+            .verify(&headers, Some("legacy"))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, AppError::BasicAuthFailed { .. }));
+
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[header::WWW_AUTHENTICATE],
+            "Basic realm=\"legacy\", charset=\"UTF-8\""
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_reloaded_credentials_take_effect() {
+    let credentials = Arc::new(RwLock::new(BasicCredentials::default()));
+    let authenticator = BasicAuthenticator::new(credentials.clone());
+    assert!(
+        authenticator
+            .verify(&basic("carol", "pw"), None)
+            .await
+            .is_err()
+    );
+
+    let path = write_file("reload", &format!("carol:{}\n", bcrypt_hash("pw")));
+    *credentials.write().await = BasicCredentials::load(&path).unwrap();
+    assert!(
+        authenticator
+            .verify(&basic("carol", "pw"), None)
+            .await
+            .is_ok()
+    );
+}
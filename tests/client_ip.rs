@@ -0,0 +1,114 @@
+use std::net::IpAddr;
+
+use http::{HeaderMap, HeaderValue};
+use rustway::{
+    config::{ClientIpConfig, ClientIpSource, ForwardedHeadersConfig},
+    features::client_ip::client_ip::{apply_forwarded_headers, resolve_client_ip},
+};
+
+fn config(source: ClientIpSource) -> ClientIpConfig {
+    ClientIpConfig {
+        source,
+        trusted_proxies: vec!["10.0.0.0/8".parse().unwrap()],
+    }
+}
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn test_untrusted_peer_headers_are_ignored() {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", HeaderValue::from_static("1.2.3.4"));
+
+    let resolved = resolve_client_ip(
+        ip("198.51.100.1"),
+        &headers,
+        &config(ClientIpSource::XForwardedFor),
+    );
+    assert_eq!(resolved, ip("198.51.100.1"));
+}
+
+#[test]
+fn test_rightmost_untrusted_forwarded_for() {
+    let mut headers = HeaderMap::new();
+    headers.append(
+        "x-forwarded-for",
+        HeaderValue::from_static("6.6.6.6, 203.0.113.5"),
+    );
+    headers.append("x-forwarded-for", HeaderValue::from_static("10.1.1.1"));
+
+    let resolved = resolve_client_ip(
+        ip("10.0.0.2"),
+        &headers,
+        &config(ClientIpSource::XForwardedFor),
+    );
+    assert_eq!(resolved, ip("203.0.113.5"));
+}
+
+#[test]
+fn test_single_ip_headers() {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-real-ip", HeaderValue::from_static("203.0.113.8"));
+    headers.insert("cf-connecting-ip", HeaderValue::from_static("203.0.113.9"));
+
+    let peer = ip("10.0.0.2");
+    assert_eq!(
+        resolve_client_ip(peer, &headers, &config(ClientIpSource::XRealIp)),
+        ip("203.0.113.8")
+    );
+    assert_eq!(
+        resolve_client_ip(peer, &headers, &config(ClientIpSource::CfConnectingIp)),
+        ip("203.0.113.9")
+    );
+    assert_eq!(
+        resolve_client_ip(peer, &HeaderMap::new(), &config(ClientIpSource::XRealIp)),
+        peer
+    );
+}
+
+#[test]
+fn test_forwarded_headers_from_untrusted_client_are_replaced() {
+    let mut headers = HeaderMap::new();
+    headers.insert("host", HeaderValue::from_static("gateway.example.com"));
+    headers.insert("x-forwarded-for", HeaderValue::from_static("1.1.1.1"));
+    headers.insert("x-forwarded-proto", HeaderValue::from_static("https"));
+
+    let forwarded = ForwardedHeadersConfig {
+        x_forwarded: true,
+        forwarded: true,
+    };
+    apply_forwarded_headers(
+        &mut headers,
+        ip("198.51.100.1"),
+        &config(ClientIpSource::XForwardedFor),
+        &forwarded,
+    );
+
+    assert_eq!(headers["x-forwarded-for"], "198.51.100.1");
+    assert_eq!(headers["x-forwarded-proto"], "http");
+    assert_eq!(headers["x-forwarded-host"], "gateway.example.com");
+    assert_eq!(
+        headers["forwarded"],
+        "for=198.51.100.1;proto=http;host=\"gateway.example.com\""
+    );
+}
+
+#[test]
+fn test_forwarded_for_chain_is_extended_for_trusted_proxy() {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", HeaderValue::from_static("203.0.113.5"));
+    headers.insert("x-forwarded-proto", HeaderValue::from_static("https"));
+
+    apply_forwarded_headers(
+        &mut headers,
+        ip("10.0.0.2"),
+        &config(ClientIpSource::XForwardedFor),
+        &ForwardedHeadersConfig::default(),
+    );
+
+    assert_eq!(headers["x-forwarded-for"], "203.0.113.5, 10.0.0.2");
+    assert_eq!(headers["x-forwarded-proto"], "https");
+    assert!(!headers.contains_key("forwarded"));
+}
@@ -0,0 +1,62 @@
+// Helpers shared by the integration tests. Each test binary compiles its own
+// copy and only uses some of them.
+#![allow(dead_code)]
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use http::{HeaderMap, HeaderValue};
+use reqwest::Client;
+use rustway::{
+    config::{ApiKeyStore, IdentityConfig, SecretsConfig},
+    features::auth::{auth::AuthBackends, jwt::JwtProviders, key_store::YamlKeyStore},
+};
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+pub fn bearer(token: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "Authorization",
+        HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+    );
+    headers
+}
+
+pub fn empty_key_store() -> YamlKeyStore {
+    YamlKeyStore::from(serde_yaml::from_str::<ApiKeyStore>("keys: {}").unwrap())
+}
+
+pub fn secrets(jwt_secret: Option<&str>) -> SecretsConfig {
+    SecretsConfig {
+        jwt_secret: jwt_secret.map(str::to_string),
+        revocation_admin_token: None,
+    }
+}
+
+// `identity` section with the default key store and the given extra YAML
+pub fn identity(yaml: &str) -> IdentityConfig {
+    serde_yaml::from_str(&format!(
+        "api_key_store_path: \"./api_keys.yaml\"\n{}",
+        yaml
+    ))
+    .unwrap()
+}
+
+// Backends as the gateway builds them, with no Basic users or revocations
+pub async fn backends(identity: &IdentityConfig, jwt_secret: Option<&str>) -> AuthBackends {
+    let jwt_providers = JwtProviders::from_config(identity, &secrets(jwt_secret), Client::new())
+        .await
+        .unwrap();
+    AuthBackends::new(
+        jwt_providers,
+        Client::new(),
+        Default::default(),
+        Default::default(),
+        Duration::from_secs(86400),
+    )
+}
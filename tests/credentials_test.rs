@@ -0,0 +1,88 @@
+use http::{HeaderMap, HeaderValue, Uri, header};
+use rustway::{
+    config::{AuthConfig, CredentialSource},
+    errors::AppError,
+    features::auth::credentials::{extract_credential, strip_credential},
+};
+
+fn sources() -> Vec<CredentialSource> {
+    let auth: AuthConfig = serde_yaml::from_str(
+        "type: ApiKey\ncredentials: [\"header:X-API-Key\", \"query:api_key\", \"cookie:gw_key\", \"bearer\"]",
+    )
+    .unwrap();
+    auth.credentials
+}
+
+fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+        headers.insert(name.clone(), HeaderValue::from_str(value).unwrap());
+    }
+    headers
+}
+
+#[test]
+fn test_sources_are_tried_in_order() {
+    let uri: Uri = "/hooks?api_key=from-query".parse().unwrap();
+    let x_api_key = header::HeaderName::from_static("x-api-key");
+
+    let both = headers(&[(x_api_key, "from-header")]);
+    assert_eq!(
+        extract_credential(&both, &uri, &sources()).unwrap(),
+        "from-header"
+    );
+    assert_eq!(
+        extract_credential(&HeaderMap::new(), &uri, &sources()).unwrap(),
+        "from-query"
+    );
+
+    let cookie = headers(&[(header::COOKIE, "theme=dark; gw_key=from-cookie")]);
+    assert_eq!(
+        extract_credential(&cookie, &Uri::from_static("/hooks"), &sources()).unwrap(),
+        "from-cookie"
+    );
+}
+
+#[test]
+fn test_default_is_bearer_only() {
+    let auth: AuthConfig = serde_yaml::from_str("type: Jwt").unwrap();
+    assert_eq!(auth.credentials, vec![CredentialSource::Bearer]);
+    assert!(!auth.strips_credential());
+
+    let uri: Uri = "/api?api_key=ignored".parse().unwrap();
+    let result = extract_credential(&HeaderMap::new(), &uri, &auth.credentials);
+    assert!(matches!(result, Err(AppError::MissingAuthToken)));
+
+    let basic = headers(&[(header::AUTHORIZATION, "Basic Zm9vOmJhcg==")]);
+    let result = extract_credential(&basic, &uri, &auth.credentials);
+    assert!(matches!(result, Err(AppError::InvalidAuthHeader)));
+}
+
+#[test]
+fn test_used_credential_is_stripped() {
+    let auth: AuthConfig = serde_yaml::from_str("type: ApiKey").unwrap();
+    assert!(auth.strips_credential());
+
+    let mut uri: Uri = "/hooks?event=push&api_key=secret&id=7".parse().unwrap();
+    let mut query_only = HeaderMap::new();
+    strip_credential(&mut query_only, &mut uri, &sources());
+    assert_eq!(uri, "/hooks?event=push&id=7");
+
+    let mut uri: Uri = "/hooks?api_key=secret".parse().unwrap();
+    let mut cookie = headers(&[(header::COOKIE, "theme=dark; gw_key=secret")]);
+    strip_credential(&mut cookie, &mut uri, &sources());
+    // Only the first matching source is removed
+    assert_eq!(uri, "/hooks");
+    assert_eq!(cookie[header::COOKIE], "theme=dark; gw_key=secret");
+
+    let mut uri = Uri::from_static("/hooks");
+    strip_credential(&mut cookie, &mut uri, &sources());
+    assert_eq!(cookie[header::COOKIE], "theme=dark");
+}
+
+#[test]
+fn test_unknown_source_is_rejected() {
+    let result: Result<AuthConfig, _> =
+        serde_yaml::from_str("type: ApiKey\ncredentials: [\"form:api_key\"]");
+    assert!(result.is_err());
+}
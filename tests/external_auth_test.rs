@@ -0,0 +1,245 @@
+mod common;
+
+use std::{
+    net::IpAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{Router, extract::State, response::IntoResponse, routing::get};
+use common::bearer;
+use http::{HeaderMap, HeaderValue, Method, StatusCode, Uri};
+use rustway::{
+    config::ExternalAuthConfig,
+    errors::AppError,
+    features::auth::external::{Decision, ExternalAuthorizer, strip_upstream_headers},
+};
+use tokio::net::TcpListener;
+
+// Policy service stub: "Bearer alice" may read, "Bearer slow" times out,
+// "Bearer broken" fails, requests without a token are sent to a login page and
+// everyone else gets a 401 with a challenge.
+async fn check(State(calls): State<Arc<AtomicUsize>>, headers: HeaderMap) -> impl IntoResponse {
+    calls.fetch_add(1, Ordering::SeqCst);
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    match header("authorization") {
+        Some("Bearer alice")
+            if header("x-forwarded-method") == Some("GET")
+                && header("x-forwarded-uri") == Some("/orders/42?full=1")
+                && header("x-forwarded-for") == Some("203.0.113.7") =>
+        {
+            (
+                StatusCode::OK,
+                [
+                    ("x-user-id", "alice"),
+                    ("x-user-roles", "reader, billing"),
+                    ("x-tenant", "acme"),
+                ],
+            )
+                .into_response()
+        }
+        Some("Bearer slow") => {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            StatusCode::OK.into_response()
+        }
+        Some("Bearer broken") => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+        None => (StatusCode::FOUND, [("location", "/login")]).into_response(),
+        _ => (
+            StatusCode::UNAUTHORIZED,
+            [("www-authenticate", "Bearer realm=\"orders\"")],
+            "token rejected",
+        )
+            .into_response(),
+    }
+}
+
+async fn start_service() -> (String, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = Router::new()
+        .route("/check", get(check))
+        .route("/login", get(|| async { "login page" }))
+        .with_state(calls.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{}/check", addr), calls)
+}
+
+fn config(url: &str, extra: &str) -> ExternalAuthConfig {
+    serde_yaml::from_str(&format!(
+        "url: \"{}\"\nupstream_headers: [\"x-user-id\", \"x-tenant\"]\ntimeout: \"1s\"\n{}",
+        url, extra
+    ))
+    .unwrap()
+}
+
+fn client_ip() -> Option<IpAddr> {
+    Some("203.0.113.7".parse().unwrap())
+}
+
+fn uri() -> Uri {
+    "/orders/42?full=1".parse().unwrap()
+}
+
+#[tokio::test]
+async fn test_allowed_request_gets_identity_headers() {
+    let (url, _) = start_service().await;
+    let authorizer = ExternalAuthorizer::new();
+
+    let decision = authorizer
+        .authorize(
+            &Method::GET,
+            &uri(),
+            &bearer("alice"),
+            client_ip(),
+            &config(&url, ""),
+        )
+        .await
+        .unwrap();
+    let Decision::Allow { claims, headers } = decision else {
+        panic!("request should be allowed");
+    };
+    assert_eq!(claims.sub, "alice");
+    assert_eq!(claims.roles, vec!["reader", "billing"]);
+    assert_eq!(headers["x-user-id"], "alice");
+    assert_eq!(headers["x-tenant"], "acme");
+    assert!(!headers.contains_key("x-user-roles"));
+}
+
+#[tokio::test]
+async fn test_denial_is_relayed_to_client() {
+    let (url, _) = start_service().await;
+    let authorizer = ExternalAuthorizer::new();
+
+    let decision = authorizer
+        .authorize(
+            &Method::GET,
+            &uri(),
+            &bearer("mallory"),
+            client_ip(),
+            &config(&url, ""),
+        )
+        .await
+        .unwrap();
+    let Decision::Deny {
+        status,
+        headers,
+        body,
+    } = decision
+    else {
+        panic!("request should be denied");
+    };
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(headers["www-authenticate"], "Bearer realm=\"orders\"");
+    assert_eq!(body, "token rejected");
+}
+
+#[tokio::test]
+async fn test_login_redirect_is_relayed_not_followed() {
+    let (url, _) = start_service().await;
+    let authorizer = ExternalAuthorizer::new();
+
+    let decision = authorizer
+        .authorize(
+            &Method::GET,
+            &uri(),
+            &HeaderMap::new(),
+            client_ip(),
+            &config(&url, ""),
+        )
+        .await
+        .unwrap();
+    let Decision::Deny {
+        status, headers, ..
+    } = decision
+    else {
+        panic!("redirected request should be denied");
+    };
+    assert_eq!(status, StatusCode::FOUND);
+    assert_eq!(headers["location"], "/login");
+}
+
+#[tokio::test]
+async fn test_server_errors_follow_failure_mode_and_are_not_cached() {
+    let (url, calls) = start_service().await;
+    let authorizer = ExternalAuthorizer::new();
+    let deny = config(&url, "cache_ttl: \"1m\"");
+
+    for _ in 0..2 {
+        let result = authorizer
+            .authorize(&Method::GET, &uri(), &bearer("broken"), client_ip(), &deny)
+            .await;
+        assert!(matches!(result, Err(AppError::ServiceUnavailable)));
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    let decision = authorizer
+        .authorize(
+            &Method::GET,
+            &uri(),
+            &bearer("broken"),
+            client_ip(),
+            &config(&url, "on_error: Allow"),
+        )
+        .await
+        .unwrap();
+    assert!(matches!(decision, Decision::Allow { .. }));
+}
+
+#[tokio::test]
+async fn test_timeout_follows_failure_mode() {
+    let (url, _) = start_service().await;
+    let authorizer = ExternalAuthorizer::new();
+
+    let result = authorizer
+        .authorize(
+            &Method::GET,
+            &uri(),
+            &bearer("slow"),
+            client_ip(),
+            &config(&url, ""),
+        )
+        .await;
+    assert!(matches!(result, Err(AppError::ServiceUnavailable)));
+
+    let decision = authorizer
+        .authorize(
+            &Method::GET,
+            &uri(),
+            &bearer("slow"),
+            client_ip(),
+            &config(&url, "on_error: Allow"),
+        )
+        .await
+        .unwrap();
+    assert!(matches!(decision, Decision::Allow { claims, .. } if claims.roles.is_empty()));
+}
+
+#[tokio::test]
+async fn test_decisions_are_cached_per_request_identity() {
+    let (url, calls) = start_service().await;
+    let authorizer = ExternalAuthorizer::new();
+    let config = config(&url, "cache_ttl: \"1m\"");
+
+    for token in ["alice", "alice", "mallory", "mallory"] {
+        authorizer
+            .authorize(&Method::GET, &uri(), &bearer(token), client_ip(), &config)
+            .await
+            .unwrap();
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_client_cannot_spoof_upstream_headers() {
+    let mut headers = bearer("alice");
+    headers.insert("x-user-id", HeaderValue::from_static("admin"));
+
+    strip_upstream_headers(&mut headers, &config("http://authz", ""));
+    assert!(!headers.contains_key("x-user-id"));
+    assert!(headers.contains_key("authorization"));
+}
@@ -0,0 +1,62 @@
+use std::{net::TcpListener, os::fd::AsRawFd, time::Duration};
+
+use rustway::utils::listener::hand_over;
+use tokio::process::Command;
+
+fn successor(script: &str) -> Command {
+    let mut command = Command::new("bash");
+    command.args(["-c", script]);
+    command
+}
+
+#[tokio::test]
+async fn test_hand_over_waits_for_ready_successor() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let script = r#"[ -n "$RUSTYGW_LISTEN_FD" ] || exit 1; sleep 0.2; printf x >&"$RUSTYGW_READY_FD"; sleep 1"#;
+
+    let started = std::time::Instant::now();
+    hand_over(
+        listener.as_raw_fd(),
+        successor(script),
+        Duration::from_secs(5),
+    )
+    .await
+    .unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(200));
+}
+
+#[tokio::test]
+async fn test_hand_over_fails_when_successor_exits() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let err = hand_over(
+        listener.as_raw_fd(),
+        successor("exit 3"),
+        Duration::from_secs(5),
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        err.to_string().contains("exited before it was ready"),
+        "{err}"
+    );
+
+    // The old process keeps its socket usable after a failed handoff
+    assert!(listener.local_addr().is_ok());
+}
+
+#[tokio::test]
+async fn test_hand_over_gives_up_on_silent_successor() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let started = std::time::Instant::now();
+    let err = hand_over(
+        listener.as_raw_fd(),
+        successor("sleep 10"),
+        Duration::from_millis(300),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("not ready"), "{err}");
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use common::secrets;
+use http::{HeaderMap, HeaderValue, Method, Uri};
+use rustway::{
+    config::GatewayConfig,
+    utils::inspect::{trace_request, validate},
+};
+
+fn config(routes: &str) -> GatewayConfig {
+    let key_store =
+        std::env::temp_dir().join(format!("rustygw-{}-inspect-keys.yaml", std::process::id()));
+    std::fs::write(&key_store, "keys: {}\n").unwrap();
+    let yaml = format!(
+        "server:\n  addr: \"127.0.0.1:8094\"\nidentity:\n  api_key_store_path: \"{}\"\nroutes:\n{}",
+        key_store.display(),
+        routes
+    );
+    serde_yaml::from_str(&yaml).unwrap()
+}
+
+const ROUTES: &str = r#"
+  - name: "users"
+    path: "/api/users"
+    destination: "http://127.0.0.1:8091/users"
+    auth:
+      type: "ApiKey"
+      credentials: ["header:X-API-Key"]
+      method_roles:
+        DELETE: ["admin"]
+      policies:
+        - name: "own-user"
+          methods: ["DELETE"]
+          path: "/api/users/{id}"
+          require: ["path.id == claims.sub"]
+    rate_limit: { requests: 10, period: "1m" }
+    cache: { ttl: "30s" }
+  - name: "frontend"
+    path: "/"
+    destination: "http://127.0.0.1:8090/"
+"#;
+
+#[tokio::test]
+async fn test_valid_config_has_no_problems() {
+    assert!(
+        validate(&config(ROUTES), &secrets(Some("secret")))
+            .await
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn test_validate_reports_every_problem() {
+    let routes = r#"
+  - name: "users"
+    path: "api/users"
+    destination: "127.0.0.1:8091"
+    rate_limit: { requests: 0, period: "1d" }
+  - name: "users"
+    path: "/_gateway/users"
+    destination: "http://127.0.0.1:8091"
+    auth:
+      type: "Oidc"
+  - name: "hooks"
+    path: "/hooks"
+    destination: "http://127.0.0.1:8092"
+    auth:
+      type: "Jwt"
+      providers: ["partner"]
+  - name: "webhooks"
+    path: "/webhooks"
+    destination: "http://127.0.0.1:8093"
+    auth:
+      type: "Signature"
+      signature:
+        header: "X-Signature"
+        key_id: "partner"
+        timestamp_header: "X-Timestamp"
+"#;
+    let problems = validate(&config(routes), &secrets(Some("secret"))).await;
+    let expected = [
+        "routes.users: path 'api/users' must start with '/'",
+        "routes.users: invalid destination '127.0.0.1:8091'",
+        "routes.users: rate_limit.requests must be greater than 0",
+        "routes.users: invalid rate_limit.period '1d'",
+        "routes.users: duplicate route name",
+        "routes.users: path '/_gateway/users' is answered by the gateway itself",
+        "routes.users: Oidc auth needs its 'oidc' block",
+        "routes.hooks: unknown JWT provider 'partner'",
+        "routes.webhooks: signature.canonical must include the value of 'X-Timestamp'",
+    ];
+    for problem in expected {
+        assert!(
+            problems.iter().any(|p| p.starts_with(problem)),
+            "missing {:?} in {:#?}",
+            problem,
+            problems
+        );
+    }
+    assert_eq!(problems.len(), expected.len());
+
+    // Startup needs JWT_SECRET for the default provider
+    let problems = validate(&config(ROUTES), &secrets(None)).await;
+    assert!(problems[0].contains("JWT_SECRET"));
+
+    let mut gateway = config(ROUTES);
+    gateway.server.admin_addr = Some("localhost".to_string());
+    gateway.identity.runtime_revocation_ttl = "1d".to_string();
+    let problems = validate(&gateway, &secrets(Some("secret"))).await;
+    assert!(problems[0].starts_with("server: invalid admin_addr 'localhost'"));
+    assert!(problems[1].starts_with("identity: invalid runtime_revocation_ttl '1d'"));
+    assert_eq!(problems.len(), 2);
+}
+
+#[test]
+fn test_trace_request() {
+    let config = config(ROUTES);
+    let mut headers = HeaderMap::new();
+    headers.insert("x-api-key", HeaderValue::from_static("gw_1.secret"));
+
+    let uri: Uri = "/api/users/42?verbose=1".parse().unwrap();
+    let lines = trace_request(&config, &Method::DELETE, &uri, &headers).unwrap();
+    let line = |label: &str| {
+        lines
+            .iter()
+            .find(|(l, _)| *l == label)
+            .map(|(_, value)| value.as_str())
+    };
+    assert_eq!(line("route"), Some("users (/api/users)"));
+    assert_eq!(
+        line("upstream"),
+        Some("DELETE http://127.0.0.1:8091/users/42")
+    );
+    assert_eq!(line("method roles"), Some("all_of(admin)"));
+    assert_eq!(line("policies"), Some("own-user"));
+    assert!(line("credential").unwrap().starts_with("present"));
+    assert_eq!(line("cache"), Some("bypassed for DELETE"));
+
+    let lines = trace_request(&config, &Method::GET, &uri, &HeaderMap::new()).unwrap();
+    assert!(!lines.iter().any(|(label, _)| *label == "policies"));
+    assert!(lines.contains(&("cache", "ttl 30s".to_string())));
+    assert!(
+        lines
+            .iter()
+            .any(|(label, value)| *label == "credential" && value.starts_with("missing"))
+    );
+
+    let health: Uri = "/health".parse().unwrap();
+    let lines = trace_request(&config, &Method::GET, &health, &HeaderMap::new()).unwrap();
+    assert_eq!(lines[0].1, "answered by the gateway itself");
+}
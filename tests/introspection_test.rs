@@ -0,0 +1,177 @@
+mod common;
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{Form, Json, Router, extract::State, http::HeaderMap, routing::post};
+use common::{bearer, empty_key_store, identity, now};
+use http::Uri;
+use rustway::{
+    config::AuthConfig,
+    errors::AppError,
+    features::auth::auth::{AuthBackends, verify_token},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+
+const CLIENT_SECRET_ENV: &str = "INTROSPECTION_TEST_CLIENT_SECRET";
+
+#[derive(Deserialize)]
+struct IntrospectionRequest {
+    token: String,
+}
+
+// Authorization server stub that counts introspection calls
+async fn introspect(
+    State(calls): State<Arc<AtomicUsize>>,
+    headers: HeaderMap,
+    Form(request): Form<IntrospectionRequest>,
+) -> Json<Value> {
+    calls.fetch_add(1, Ordering::SeqCst);
+    // "gateway:s3cret" in Basic auth
+    if headers["authorization"] != "Basic Z2F0ZXdheTpzM2NyZXQ=" {
+        return Json(json!({ "active": false }));
+    }
+
+    Json(match request.token.as_str() {
+        "live-token" => json!({
+            "active": true,
+            "sub": "alice",
+            "scope": "orders:read orders:write",
+            "client_id": "web-app",
+            "exp": now() + 3600,
+        }),
+        "short-lived-token" => json!({
+            "active": true,
+            "sub": "bob",
+            "exp": now() + 1,
+        }),
+        _ => json!({ "active": false }),
+    })
+}
+
+async fn start_server() -> (String, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let app = Router::new()
+        .route("/introspect", post(introspect))
+        .with_state(calls.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{}/introspect", addr), calls)
+}
+
+fn auth_config(endpoint: &str) -> AuthConfig {
+    // SAFETY: every test sets the same value, and nothing reads it concurrently
+    // from outside the gateway code under test.
+    unsafe { std::env::set_var(CLIENT_SECRET_ENV, "s3cret") };
+    serde_yaml::from_str(&format!(
+        r#"
+type: Introspection
+introspection:
+  endpoint: "{}"
+  client_id: "gateway"
+  client_secret_env: "{}"
+"#,
+        endpoint, CLIENT_SECRET_ENV
+    ))
+    .unwrap()
+}
+
+async fn backends() -> AuthBackends {
+    common::backends(&identity(""), Some("test-secret")).await
+}
+
+#[tokio::test]
+async fn test_active_token_is_mapped_and_cached() {
+    let (endpoint, calls) = start_server().await;
+    let (auth, backends) = (auth_config(&endpoint), backends().await);
+
+    for _ in 0..3 {
+        let claims = verify_token(
+            &bearer("live-token"),
+            &Uri::from_static("/"),
+            &auth,
+            &backends,
+            &empty_key_store(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(claims.sub, "alice");
+        assert_eq!(claims.roles, vec!["orders:read", "orders:write"]);
+        assert_eq!(claims.extra["client_id"], "web-app");
+        assert!(!claims.extra.contains_key("active"));
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_inactive_token_is_rejected_and_cached() {
+    let (endpoint, calls) = start_server().await;
+    let (auth, backends) = (auth_config(&endpoint), backends().await);
+
+    for _ in 0..2 {
+        let result = verify_token(
+            &bearer("revoked-token"),
+            &Uri::from_static("/"),
+            &auth,
+            &backends,
+            &empty_key_store(),
+        )
+        .await;
+        assert!(matches!(result, Err(AppError::AuthFailed(_))));
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_cache_entry_does_not_outlive_token_exp() {
+    let (endpoint, calls) = start_server().await;
+    let (auth, backends) = (auth_config(&endpoint), backends().await);
+
+    let headers = bearer("short-lived-token");
+    assert!(
+        verify_token(
+            &headers,
+            &Uri::from_static("/"),
+            &auth,
+            &backends,
+            &empty_key_store()
+        )
+        .await
+        .is_ok()
+    );
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+    let _ = verify_token(
+        &headers,
+        &Uri::from_static("/"),
+        &auth,
+        &backends,
+        &empty_key_store(),
+    )
+    .await;
+
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_unreachable_endpoint_is_not_cached() {
+    let auth = auth_config("http://127.0.0.1:9/introspect");
+    let backends = backends().await;
+
+    let result = verify_token(
+        &bearer("live-token"),
+        &Uri::from_static("/"),
+        &auth,
+        &backends,
+        &empty_key_store(),
+    )
+    .await;
+    assert!(matches!(result, Err(AppError::ServiceUnavailable)));
+}
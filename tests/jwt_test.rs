@@ -0,0 +1,449 @@
+mod common;
+
+use common::{backends, bearer, empty_key_store, identity, now};
+use http::Uri;
+use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+use reqwest::Client;
+use rustway::{
+    config::{AuthConfig, IdentityConfig},
+    errors::AppError,
+    features::auth::auth::{AuthBackends, Claims, verify_token},
+};
+use serde_json::json;
+
+const PRIVATE_KEY: &[u8] = include_bytes!("fixtures/ed25519_private.pem");
+
+fn claims() -> Claims {
+    Claims {
+        sub: "alice@example.com".to_string(),
+        roles: vec!["user".to_string()],
+        exp: (now() + 300) as usize,
+        provider: None,
+        extra: Default::default(),
+    }
+}
+
+fn hs256_token(claims: &serde_json::Value) -> String {
+    encode(
+        &Header::default(),
+        claims,
+        &EncodingKey::from_secret(b"test-secret"),
+    )
+    .unwrap()
+}
+
+fn ed25519_token(kid: Option<&str>) -> String {
+    let mut header = Header::new(Algorithm::EdDSA);
+    header.kid = kid.map(str::to_string);
+    let key = EncodingKey::from_ed_pem(PRIVATE_KEY).unwrap();
+    encode(&header, &claims(), &key).unwrap()
+}
+
+fn jwt_auth() -> AuthConfig {
+    serde_yaml::from_str("type: Jwt").unwrap()
+}
+
+async fn provider(config: &str, secret: Option<&str>) -> AuthBackends {
+    let identity = IdentityConfig {
+        jwt: serde_yaml::from_str(config).unwrap(),
+        ..identity("")
+    };
+    backends(&identity, secret).await
+}
+
+#[tokio::test]
+async fn test_default_provider_verifies_hs256() {
+    let provider = provider("null", Some("test-secret")).await;
+    let token = encode(
+        &Header::default(),
+        &claims(),
+        &EncodingKey::from_secret(b"test-secret"),
+    )
+    .unwrap();
+
+    let claims = verify_token(
+        &bearer(&token),
+        &Uri::from_static("/"),
+        &jwt_auth(),
+        &provider,
+        &empty_key_store(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(claims.sub, "alice@example.com");
+}
+
+#[tokio::test]
+async fn test_jwks_selects_key_by_kid() {
+    let provider = provider(
+        r#"
+algorithms: ["EdDSA"]
+jwks:
+  path: "tests/fixtures/jwks.json"
+"#,
+        None,
+    )
+    .await;
+
+    let token = ed25519_token(Some("test-ed25519"));
+    let claims = verify_token(
+        &bearer(&token),
+        &Uri::from_static("/"),
+        &jwt_auth(),
+        &provider,
+        &empty_key_store(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(claims.roles, vec!["user"]);
+
+    let token = ed25519_token(Some("rotated-away"));
+    let result = verify_token(
+        &bearer(&token),
+        &Uri::from_static("/"),
+        &jwt_auth(),
+        &provider,
+        &empty_key_store(),
+    )
+    .await;
+    assert!(matches!(result, Err(AppError::AuthFailed(_))));
+}
+
+#[tokio::test]
+async fn test_static_public_key_rejects_other_algorithms() {
+    let provider = provider(
+        r#"
+algorithms: ["EdDSA"]
+public_key_path: "tests/fixtures/ed25519_public.pem"
+"#,
+        None,
+    )
+    .await;
+
+    let token = ed25519_token(None);
+    assert!(
+        verify_token(
+            &bearer(&token),
+            &Uri::from_static("/"),
+            &jwt_auth(),
+            &provider,
+            &empty_key_store()
+        )
+        .await
+        .is_ok()
+    );
+
+    // An HS256 token must not be accepted by an EdDSA-only provider.
+    let token = encode(
+        &Header::default(),
+        &claims(),
+        &EncodingKey::from_secret(b"guess"),
+    )
+    .unwrap();
+    let result = verify_token(
+        &bearer(&token),
+        &Uri::from_static("/"),
+        &jwt_auth(),
+        &provider,
+        &empty_key_store(),
+    )
+    .await;
+    assert!(matches!(result, Err(AppError::AuthFailed(_))));
+}
+
+fn keycloak_auth() -> AuthConfig {
+    serde_yaml::from_str(
+        r#"
+type: Jwt
+jwt:
+  issuers: ["https://idp.example.com/realms/main"]
+  audiences: ["gateway"]
+  leeway: "5s"
+  subject_claim: "/preferred_username"
+  roles_claims: ["/realm_access/roles", "/scope"]
+"#,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_claims_are_mapped_with_json_pointers() {
+    let provider = provider("null", Some("test-secret")).await;
+    let token = hs256_token(&json!({
+        "sub": "f3a1",
+        "preferred_username": "alice",
+        "iss": "https://idp.example.com/realms/main",
+        "aud": ["gateway", "account"],
+        "exp": now() + 300,
+        "nbf": now() - 10,
+        "realm_access": { "roles": ["admin", "user"] },
+        "scope": "openid orders:read user",
+        "tenant": "acme"
+    }));
+
+    let claims = verify_token(
+        &bearer(&token),
+        &Uri::from_static("/"),
+        &keycloak_auth(),
+        &provider,
+        &empty_key_store(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(claims.sub, "alice");
+    assert_eq!(claims.roles, vec!["admin", "user", "openid", "orders:read"]);
+    assert_eq!(claims.extra["tenant"], "acme");
+    assert!(!claims.extra.contains_key("exp"));
+}
+
+#[tokio::test]
+async fn test_issuer_audience_and_nbf_are_enforced() {
+    let provider = provider("null", Some("test-secret")).await;
+    let valid = json!({
+        "preferred_username": "alice",
+        "iss": "https://idp.example.com/realms/main",
+        "aud": "gateway",
+        "exp": now() + 300,
+    });
+
+    for (claim, value) in [
+        ("iss", json!("https://evil.example.com")),
+        ("aud", json!("another-api")),
+        ("nbf", json!(now() + 3600)),
+    ] {
+        let mut claims = valid.clone();
+        claims[claim] = value;
+        let result = verify_token(
+            &bearer(&hs256_token(&claims)),
+            &Uri::from_static("/"),
+            &keycloak_auth(),
+            &provider,
+            &empty_key_store(),
+        )
+        .await;
+        assert!(
+            matches!(result, Err(AppError::AuthFailed(_))),
+            "{} should be rejected",
+            claim
+        );
+    }
+}
+
+fn multi_issuer_identity() -> IdentityConfig {
+    identity(
+        r#"
+jwt_providers:
+  - name: "internal"
+    algorithms: ["HS256"]
+    issuers: ["https://auth.internal"]
+  - name: "partner"
+    algorithms: ["EdDSA"]
+    jwks:
+      path: "tests/fixtures/jwks.json"
+    issuers: ["https://partner.example.com"]
+    audiences: ["gateway"]
+    roles_claims: ["/scope"]
+"#,
+    )
+}
+
+fn multi_issuer_auth() -> AuthConfig {
+    serde_yaml::from_str(
+        r#"
+type: Jwt
+providers: ["internal", "partner"]
+"#,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_route_accepts_each_listed_provider() {
+    let providers = backends(&multi_issuer_identity(), Some("test-secret")).await;
+
+    let internal = hs256_token(&json!({
+        "sub": "svc-billing",
+        "iss": "https://auth.internal",
+        "roles": ["service"],
+        "exp": now() + 300,
+    }));
+    let claims = verify_token(
+        &bearer(&internal),
+        &Uri::from_static("/"),
+        &multi_issuer_auth(),
+        &providers,
+        &empty_key_store(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(claims.provider.as_deref(), Some("internal"));
+    assert_eq!(claims.roles, vec!["service"]);
+
+    let mut header = Header::new(Algorithm::EdDSA);
+    header.kid = Some("test-ed25519".to_string());
+    let partner = encode(
+        &header,
+        &json!({
+            "sub": "partner-42",
+            "iss": "https://partner.example.com",
+            "aud": "gateway",
+            "scope": "orders:read",
+            "exp": now() + 300,
+        }),
+        &EncodingKey::from_ed_pem(PRIVATE_KEY).unwrap(),
+    )
+    .unwrap();
+    let claims = verify_token(
+        &bearer(&partner),
+        &Uri::from_static("/"),
+        &multi_issuer_auth(),
+        &providers,
+        &empty_key_store(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(claims.provider.as_deref(), Some("partner"));
+    assert_eq!(claims.roles, vec!["orders:read"]);
+}
+
+#[tokio::test]
+async fn test_route_mapping_keeps_provider_audiences() {
+    let providers = backends(&multi_issuer_identity(), Some("test-secret")).await;
+    let auth: AuthConfig = serde_yaml::from_str(
+        r#"
+type: Jwt
+providers: ["internal", "partner"]
+jwt:
+  roles_claims: ["/scope"]
+"#,
+    )
+    .unwrap();
+
+    let mut header = Header::new(Algorithm::EdDSA);
+    header.kid = Some("test-ed25519".to_string());
+    let partner = |aud: &str| {
+        encode(
+            &header,
+            &json!({
+                "sub": "partner-42",
+                "iss": "https://partner.example.com",
+                "aud": aud,
+                "scope": "orders:read",
+                "exp": now() + 300,
+            }),
+            &EncodingKey::from_ed_pem(PRIVATE_KEY).unwrap(),
+        )
+        .unwrap()
+    };
+
+    let claims = verify_token(
+        &bearer(&partner("gateway")),
+        &Uri::from_static("/"),
+        &auth,
+        &providers,
+        &empty_key_store(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(claims.roles, vec!["orders:read"]);
+
+    let result = verify_token(
+        &bearer(&partner("someone-else")),
+        &Uri::from_static("/"),
+        &auth,
+        &providers,
+        &empty_key_store(),
+    )
+    .await;
+    assert!(
+        matches!(result, Err(AppError::AuthFailed(reason)) if reason == "Invalid JWT audience.")
+    );
+}
+
+#[tokio::test]
+async fn test_issuer_cannot_borrow_another_providers_key() {
+    let providers = backends(&multi_issuer_identity(), Some("test-secret")).await;
+
+    // Claims to be the partner but is HMAC signed with the internal secret.
+    let forged = hs256_token(&json!({
+        "sub": "partner-42",
+        "iss": "https://partner.example.com",
+        "aud": "gateway",
+        "exp": now() + 300,
+    }));
+    let result = verify_token(
+        &bearer(&forged),
+        &Uri::from_static("/"),
+        &multi_issuer_auth(),
+        &providers,
+        &empty_key_store(),
+    )
+    .await;
+    assert!(matches!(result, Err(AppError::AuthFailed(_))));
+
+    let unknown = hs256_token(&json!({
+        "sub": "mallory",
+        "iss": "https://unknown.example.com",
+        "exp": now() + 300,
+    }));
+    let result = verify_token(
+        &bearer(&unknown),
+        &Uri::from_static("/"),
+        &multi_issuer_auth(),
+        &providers,
+        &empty_key_store(),
+    )
+    .await;
+    assert!(matches!(result, Err(AppError::AuthFailed(_))));
+}
+
+#[tokio::test]
+async fn test_revoked_tokens_are_rejected_after_validation() {
+    let provider = provider("null", Some("test-secret")).await;
+    let token = hs256_token(&json!({
+        "sub": "alice@example.com",
+        "roles": ["user"],
+        "exp": now() + 300,
+        "jti": "token-1",
+    }));
+    let (headers, auth, store) = (bearer(&token), jwt_auth(), empty_key_store());
+    let uri = Uri::from_static("/");
+    let verify = || verify_token(&headers, &uri, &auth, &provider, &store);
+
+    assert!(verify().await.is_ok());
+    provider
+        .revocations
+        .revoke_token("token-1".to_string())
+        .await
+        .unwrap();
+    assert!(matches!(verify().await, Err(AppError::AuthFailed(_))));
+}
+
+#[tokio::test]
+async fn test_jwks_fetch_is_bounded() {
+    use rustway::{config::JwksConfig, features::auth::jwks::JwksStore};
+
+    // Accepts connections but never answers
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            held.push(stream);
+        }
+    });
+
+    let config: JwksConfig = serde_yaml::from_str(&format!(
+        "url: \"http://{}/jwks.json\"\nfetch_timeout: \"1s\"\n",
+        addr
+    ))
+    .unwrap();
+    let started = std::time::Instant::now();
+    let error = JwksStore::load(&config, Client::new()).await.err().unwrap();
+    assert!(
+        error.to_string().starts_with("Failed to fetch JWKS"),
+        "{error:#}"
+    );
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+}
@@ -0,0 +1,187 @@
+use std::path::PathBuf;
+
+use rustway::{
+    config::{ApiKeyDetails, KeyCacheConfig, KeyStatus},
+    errors::AppError,
+    features::auth::{
+        api_key::{create_key, generate_key, rotate_key, verify_key},
+        key_store::{KeyStore, SqliteKeyStore, YamlKeyStore},
+    },
+};
+
+fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rustygw-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn details(user_id: &str, hash: String) -> ApiKeyDetails {
+    ApiKeyDetails {
+        hash: Some(hash),
+        user_id: user_id.to_string(),
+        roles: vec!["user".to_string()],
+        ..Default::default()
+    }
+}
+
+// Exercises the trait the same way against every backend
+async fn create_verify_and_revoke(store: &dyn KeyStore) {
+    let alice = generate_key(None);
+    let bob = generate_key(None);
+    store
+        .create(&alice.id, details("alice", alice.hash.clone()))
+        .await
+        .unwrap();
+    store
+        .create(&bob.id, details("bob", bob.hash.clone()))
+        .await
+        .unwrap();
+    assert!(
+        store
+            .create(&alice.id, details("mallory", alice.hash))
+            .await
+            .is_err()
+    );
+
+    assert_eq!(
+        verify_key(store, &alice.key).await.unwrap().user_id,
+        "alice"
+    );
+    assert_eq!(store.list().await.unwrap().len(), 2);
+
+    assert!(store.revoke(&alice.id).await.unwrap());
+    assert!(!store.revoke("gw_unknown").await.unwrap());
+    assert!(matches!(
+        verify_key(store, &alice.key).await,
+        Err(AppError::ApiKeyRevoked)
+    ));
+    assert!(verify_key(store, &bob.key).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_sqlite_store() {
+    let path = temp_path("keys.db");
+    let store = SqliteKeyStore::open(&path, &KeyCacheConfig::default()).unwrap();
+    create_verify_and_revoke(&store).await;
+
+    // Entries persist across connections
+    let reopened = SqliteKeyStore::open(&path, &KeyCacheConfig::default()).unwrap();
+    let entries = reopened.list().await.unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(
+        entries
+            .iter()
+            .any(|(_, details)| details.status == KeyStatus::Revoked)
+    );
+}
+
+#[tokio::test]
+async fn test_sqlite_store_does_not_cache_misses() {
+    let path = temp_path("misses.db");
+    let store = SqliteKeyStore::open(&path, &KeyCacheConfig::default()).unwrap();
+    let key = generate_key(None);
+    assert!(store.get(&key.id).await.unwrap().is_none());
+
+    // Written by another process, e.g. `rustygw keys create`
+    let writer = SqliteKeyStore::open(&path, &KeyCacheConfig::default()).unwrap();
+    writer
+        .create(&key.id, details("carol", key.hash.clone()))
+        .await
+        .unwrap();
+    assert!(store.get(&key.id).await.unwrap().is_some());
+}
+
+#[test]
+fn test_sqlite_store_config_and_check() {
+    let path = temp_path("checked.db");
+    let invalid = KeyCacheConfig {
+        ttl: "soon".to_string(),
+        ..KeyCacheConfig::default()
+    };
+    assert!(SqliteKeyStore::open(&path, &invalid).is_err());
+
+    // Checking never creates the database or its table
+    assert!(SqliteKeyStore::check(&path).is_err());
+    assert!(!path.exists());
+    SqliteKeyStore::open(&path, &KeyCacheConfig::default()).unwrap();
+    assert!(SqliteKeyStore::check(&path).is_ok());
+}
+
+#[tokio::test]
+async fn test_yaml_store_writes_the_file() {
+    let path = temp_path("api_keys.yaml");
+    std::fs::write(&path, "keys: {}\n").unwrap();
+    let store = YamlKeyStore::load(&path).unwrap();
+    create_verify_and_revoke(&store).await;
+
+    let reloaded = YamlKeyStore::load(&path).unwrap();
+    assert_eq!(reloaded.list().await.unwrap().len(), 2);
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.contains("status: revoked"));
+}
+
+#[tokio::test]
+async fn test_yaml_store_keeps_file_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = temp_path("private_keys.yaml");
+    std::fs::write(&path, "keys: {}\n").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+    let store = YamlKeyStore::load(&path).unwrap();
+    create_verify_and_revoke(&store).await;
+
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+
+    // Temporary siblings never outlive a save
+    let name = path.file_name().unwrap().to_str().unwrap().to_string();
+    let leftovers = std::fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let file = entry.file_name();
+            let file = file.to_string_lossy();
+            file.starts_with(&name) && file.ends_with(".tmp")
+        })
+        .count();
+    assert_eq!(leftovers, 0);
+}
+
+#[tokio::test]
+async fn test_rotate_key() {
+    let path = temp_path("rotate_keys.yaml");
+    std::fs::write(&path, "keys: {}\n").unwrap();
+    let store = YamlKeyStore::load(&path).unwrap();
+
+    let old = create_key(
+        &store,
+        ApiKeyDetails {
+            user_id: "ci".to_string(),
+            roles: vec!["deploy".to_string()],
+            allowed_methods: vec!["POST".to_string()],
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let new = rotate_key(&store, &old.id).await.unwrap();
+    assert_ne!(new.id, old.id);
+
+    // The new key keeps the metadata, the old one stops working
+    let details = verify_key(&store, &new.key).await.unwrap();
+    assert_eq!(details.user_id, "ci");
+    assert_eq!(details.allowed_methods, vec!["POST".to_string()]);
+    assert!(matches!(
+        verify_key(&store, &old.key).await,
+        Err(AppError::ApiKeyRevoked)
+    ));
+    assert!(rotate_key(&store, &old.id).await.is_err());
+    assert!(rotate_key(&store, "gw_unknown").await.is_err());
+
+    // Written in full, by rename, and without the key itself
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(!content.contains(&new.key));
+    assert!(!path.with_extension("yaml.tmp").exists());
+    let reloaded = YamlKeyStore::load(&path).unwrap();
+    assert!(verify_key(&reloaded, &new.key).await.is_ok());
+}
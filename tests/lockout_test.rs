@@ -0,0 +1,111 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use axum::response::IntoResponse;
+use http::{HeaderMap, HeaderValue, StatusCode, Uri, header};
+use rustway::{
+    config::{AuthConfig, LockoutConfig},
+    errors::AppError,
+    features::auth::lockout::{AuthAttempt, Lockouts, is_guess, presented_credential},
+};
+
+const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+
+fn config(yaml: &str) -> LockoutConfig {
+    serde_yaml::from_str(yaml).unwrap()
+}
+
+#[tokio::test]
+async fn test_credential_is_locked_out_after_threshold() {
+    let config = config("credential_failures: 3\nduration: \"10m\"\ndelay: \"0s\"\n");
+    let lockouts = Lockouts::new();
+    let attempt = AuthAttempt::new(Some(CLIENT), Some("gw_0123".to_string()), &config);
+
+    for _ in 0..2 {
+        assert!(lockouts.check(&attempt).await.is_ok());
+        assert!(lockouts.record_failure(&attempt).await.is_ok());
+    }
+    assert!(matches!(
+        lockouts.record_failure(&attempt).await,
+        Err(AppError::LockedOut { retry_after: 600 })
+    ));
+
+    // Other clients can't guess the locked key either, but the key's holder
+    // still gets in and other keys are not affected
+    let elsewhere = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1));
+    let same_key = AuthAttempt::new(Some(elsewhere), Some("gw_0123".to_string()), &config);
+    assert!(lockouts.check(&same_key).await.is_ok());
+    let error = lockouts.record_failure(&same_key).await.unwrap_err();
+    let response = error.into_response();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()[header::RETRY_AFTER]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=600).contains(&retry_after));
+
+    let other_key = AuthAttempt::new(Some(elsewhere), Some("gw_4567".to_string()), &config);
+    assert!(lockouts.check(&other_key).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_client_ip_is_locked_out_across_credentials() {
+    let config = config("ip_failures: 2\ndelay: \"0s\"\n");
+    let lockouts = Lockouts::new();
+
+    let first = AuthAttempt::new(Some(CLIENT), Some("gw_a".to_string()), &config);
+    assert!(lockouts.record_failure(&first).await.is_ok());
+    let second = AuthAttempt::new(Some(CLIENT), Some("gw_b".to_string()), &config);
+    assert!(lockouts.record_failure(&second).await.is_err());
+
+    let third = AuthAttempt::new(Some(CLIENT), None, &config);
+    assert!(matches!(
+        lockouts.check(&third).await,
+        Err(AppError::LockedOut { .. })
+    ));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_failures_are_delayed_progressively() {
+    let config = config("delay: \"1s\"\nmax_delay: \"2s\"\n");
+    let lockouts = Lockouts::new();
+    let attempt = AuthAttempt::new(Some(CLIENT), None, &config);
+
+    let mut delays = Vec::new();
+    for _ in 0..4 {
+        let started = tokio::time::Instant::now();
+        lockouts.record_failure(&attempt).await.unwrap();
+        delays.push(started.elapsed().as_secs());
+    }
+    assert_eq!(delays, vec![0, 1, 2, 2]);
+}
+
+#[test]
+fn test_presented_credentials_and_guesses() {
+    let api_key: AuthConfig =
+        serde_yaml::from_str("type: ApiKey\ncredentials: [\"header:X-API-Key\"]\n").unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert("x-api-key", HeaderValue::from_static("gw_0123.secret"));
+    assert_eq!(
+        presented_credential(&headers, &Uri::from_static("/"), &api_key).as_deref(),
+        Some("gw_0123")
+    );
+
+    // Only key IDs are tracked, never the secret
+    headers.insert("x-api-key", HeaderValue::from_static("legacy-key"));
+    assert!(presented_credential(&headers, &Uri::from_static("/"), &api_key).is_none());
+
+    assert!(is_guess(&AppError::AuthFailed(
+        "Invalid API Key.".to_string()
+    )));
+    assert!(!is_guess(&AppError::MissingAuthToken));
+    assert!(!is_guess(&AppError::ApiKeyExpired));
+
+    // A browser's first request carries no Basic credentials yet
+    let basic = |reason: &str| AppError::BasicAuthFailed {
+        realm: "gateway".to_string(),
+        reason: reason.to_string(),
+    };
+    assert!(is_guess(&basic("Invalid username or password.")));
+    assert!(!is_guess(&basic("Missing Basic credentials.")));
+}
@@ -0,0 +1,360 @@
+mod common;
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use axum::{Form, Json, Router, extract::State, response::IntoResponse, routing::post};
+use common::{empty_key_store, identity, now};
+use http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, header};
+use jsonwebtoken::{EncodingKey, Header, encode};
+use reqwest::Url;
+use rustway::{
+    config::{AuthConfig, RouteConfig},
+    errors::AppError,
+    features::auth::{
+        auth::{AuthBackends, verify_token},
+        oidc::strip_session_cookies,
+    },
+};
+use serde_json::json;
+use tokio::net::TcpListener;
+
+fn id_token(nonce: Option<&str>, groups: &[&str], exp: u64) -> String {
+    let mut claims = json!({
+        "iss": "https://idp.test",
+        "aud": "dashboard",
+        "sub": "alice",
+        "groups": groups,
+        "exp": exp,
+    });
+    if let Some(nonce) = nonce {
+        claims["nonce"] = json!(nonce);
+    }
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(b"test-secret"),
+    )
+    .unwrap()
+}
+
+// Token endpoint stub. Tests pass the login nonce as the authorization code so
+// the stub can echo it into the ID token; a "stale-" prefix issues a token
+// that has already expired. Refresh tokens are single use, as with providers
+// that rotate them.
+async fn token(
+    State(redeemed): State<Arc<Mutex<HashSet<String>>>>,
+    Form(form): Form<HashMap<String, String>>,
+) -> axum::response::Response {
+    if form.get("client_id").map(String::as_str) != Some("dashboard") {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    match form["grant_type"].as_str() {
+        "authorization_code" if form.contains_key("code_verifier") => {
+            let code = &form["code"];
+            let (nonce, exp) = match code.strip_prefix("stale-") {
+                Some(nonce) => (nonce, now() - 5),
+                None => (code.as_str(), now() + 300),
+            };
+            Json(json!({
+                "id_token": id_token(Some(nonce), &["admin"], exp),
+                "refresh_token": "refresh-1",
+                "expires_in": 300,
+            }))
+            .into_response()
+        }
+        "refresh_token"
+            if form["refresh_token"] == "refresh-1"
+                && redeemed
+                    .lock()
+                    .unwrap()
+                    .insert(form["refresh_token"].clone()) =>
+        {
+            Json(json!({
+                "id_token": id_token(None, &["admin", "ops"], now() + 300),
+                "refresh_token": "refresh-2",
+                "expires_in": 300,
+            }))
+            .into_response()
+        }
+        _ => StatusCode::BAD_REQUEST.into_response(),
+    }
+}
+
+async fn start_idp() -> String {
+    let app = Router::new()
+        .route("/token", post(token))
+        .with_state(Arc::new(Mutex::new(HashSet::new())));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}", addr)
+}
+
+fn route(idp: &str) -> RouteConfig {
+    serde_yaml::from_str(&format!(
+        r#"
+name: "dashboard"
+path: "/dashboard"
+destination: "http://localhost:9000"
+auth:
+  type: Oidc
+  roles: ["admin"]
+  oidc:
+    authorization_endpoint: "{idp}/authorize"
+    token_endpoint: "{idp}/token"
+    client_id: "dashboard"
+    client_secret_env: "OIDC_TEST_UNSET_SECRET"
+    redirect_uri: "https://gw.test/dashboard/oauth2/callback"
+    jwt_provider: "idp"
+    cookie_secure: false
+"#
+    ))
+    .unwrap()
+}
+
+async fn backends() -> AuthBackends {
+    let identity = identity(
+        r#"
+jwt_providers:
+  - name: "idp"
+    algorithms: ["HS256"]
+    issuers: ["https://idp.test"]
+    roles_claims: ["/groups"]
+"#,
+    );
+    common::backends(&identity, Some("test-secret")).await
+}
+
+fn auth(route: &RouteConfig) -> &AuthConfig {
+    route.auth.as_ref().unwrap()
+}
+
+fn cookies(pairs: &[(&str, &str)]) -> HeaderMap {
+    let cookie = pairs
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("; ");
+    let mut headers = HeaderMap::new();
+    headers.insert(header::COOKIE, HeaderValue::from_str(&cookie).unwrap());
+    headers
+}
+
+fn set_cookie_value(response: &axum::response::Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|value| value.split(';').next()?.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+// Runs the redirect and callback and returns the response to the callback.
+// `code` turns the login nonce into the authorization code the stub expects.
+async fn login(
+    backends: &AuthBackends,
+    route: &RouteConfig,
+    code: impl Fn(&str) -> String,
+) -> Result<axum::response::Response, AppError> {
+    let uri: Uri = "/dashboard/reports?range=7d".parse().unwrap();
+    let redirect = backends
+        .oidc
+        .login(&Method::GET, &uri, route, auth(route))
+        .await
+        .unwrap();
+    assert_eq!(redirect.status(), StatusCode::SEE_OTHER);
+
+    let location = Url::parse(redirect.headers()[header::LOCATION].to_str().unwrap()).unwrap();
+    let params: HashMap<_, _> = location.query_pairs().into_owned().collect();
+    assert_eq!(params["code_challenge_method"], "S256");
+    assert_eq!(
+        params["redirect_uri"],
+        "https://gw.test/dashboard/oauth2/callback"
+    );
+    let state = set_cookie_value(&redirect, "gw_session_state").unwrap();
+    assert_eq!(params["state"], state);
+
+    let callback: Uri = format!(
+        "/dashboard/oauth2/callback?code={}&state={}",
+        code(&params["nonce"]),
+        state
+    )
+    .parse()
+    .unwrap();
+    backends
+        .oidc
+        .handle_endpoint(
+            &callback,
+            &cookies(&[("gw_session_state", &state)]),
+            route,
+            auth(route),
+            &backends.jwt_providers,
+        )
+        .await
+        .map(Option::unwrap)
+}
+
+#[tokio::test]
+async fn test_login_creates_session_with_id_token_claims() {
+    let (route, backends) = (route(&start_idp().await), backends().await);
+
+    let callback = login(&backends, &route, str::to_string).await.unwrap();
+    assert_eq!(
+        callback.headers()[header::LOCATION],
+        "/dashboard/reports?range=7d"
+    );
+    let session = set_cookie_value(&callback, "gw_session").unwrap();
+
+    let claims = verify_token(
+        &cookies(&[("gw_session", &session)]),
+        &Uri::from_static("/"),
+        auth(&route),
+        &backends,
+        &empty_key_store(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(claims.sub, "alice");
+    assert_eq!(claims.roles, vec!["admin"]);
+    assert_eq!(claims.provider.as_deref(), Some("idp"));
+}
+
+#[tokio::test]
+async fn test_callback_checks_state_and_nonce() {
+    let (route, backends) = (route(&start_idp().await), backends().await);
+
+    let result = login(&backends, &route, |_| "not-the-nonce".to_string()).await;
+    assert!(matches!(result, Err(AppError::AuthFailed(_))));
+
+    // A callback without the browser's state cookie is refused.
+    let callback: Uri = "/dashboard/oauth2/callback?code=x&state=forged"
+        .parse()
+        .unwrap();
+    let result = backends
+        .oidc
+        .handle_endpoint(
+            &callback,
+            &HeaderMap::new(),
+            &route,
+            auth(&route),
+            &backends.jwt_providers,
+        )
+        .await;
+    assert!(matches!(result, Err(AppError::AuthFailed(_))));
+}
+
+#[tokio::test]
+async fn test_expired_session_is_refreshed() {
+    let (route, backends) = (route(&start_idp().await), backends().await);
+
+    let callback = login(&backends, &route, |nonce| format!("stale-{}", nonce))
+        .await
+        .unwrap();
+    let session = set_cookie_value(&callback, "gw_session").unwrap();
+
+    let claims = verify_token(
+        &cookies(&[("gw_session", &session)]),
+        &Uri::from_static("/"),
+        auth(&route),
+        &backends,
+        &empty_key_store(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(claims.roles, vec!["admin", "ops"]);
+    assert!(claims.exp as u64 > now());
+}
+
+#[tokio::test]
+async fn test_concurrent_requests_share_one_refresh() {
+    let (route, backends) = (route(&start_idp().await), backends().await);
+
+    let callback = login(&backends, &route, |nonce| format!("stale-{}", nonce))
+        .await
+        .unwrap();
+    let session = set_cookie_value(&callback, "gw_session").unwrap();
+    let headers = cookies(&[("gw_session", &session)]);
+    let key_store = empty_key_store();
+
+    // The stub refuses a second redemption of the same refresh token
+    let uri = Uri::from_static("/");
+    let verify = || verify_token(&headers, &uri, auth(&route), &backends, &key_store);
+    let results = futures::future::join_all((0..8).map(|_| verify())).await;
+    for result in results {
+        assert_eq!(result.unwrap().roles, vec!["admin", "ops"]);
+    }
+}
+
+#[test]
+fn test_session_cookies_are_not_sent_upstream() {
+    let route = route("http://127.0.0.1:1");
+    let mut headers = cookies(&[
+        ("theme", "dark"),
+        ("gw_session", "secret"),
+        ("gw_session_state", "state"),
+        ("lang", "en"),
+    ]);
+
+    strip_session_cookies(&mut headers, auth(&route));
+    assert_eq!(headers[header::COOKIE], "theme=dark; lang=en");
+
+    let mut headers = cookies(&[("gw_session", "secret")]);
+    strip_session_cookies(&mut headers, auth(&route));
+    assert!(!headers.contains_key(header::COOKIE));
+}
+
+#[tokio::test]
+async fn test_logout_ends_session() {
+    let (route, backends) = (route(&start_idp().await), backends().await);
+
+    let callback = login(&backends, &route, str::to_string).await.unwrap();
+    let session = set_cookie_value(&callback, "gw_session").unwrap();
+    let headers = cookies(&[("gw_session", &session)]);
+
+    let logout = backends
+        .oidc
+        .handle_endpoint(
+            &"/dashboard/oauth2/logout".parse().unwrap(),
+            &headers,
+            &route,
+            auth(&route),
+            &backends.jwt_providers,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(logout.headers()[header::LOCATION], "/");
+    assert_eq!(set_cookie_value(&logout, "gw_session").as_deref(), Some(""));
+
+    let result = verify_token(
+        &headers,
+        &Uri::from_static("/"),
+        auth(&route),
+        &backends,
+        &empty_key_store(),
+    )
+    .await;
+    assert!(matches!(result, Err(AppError::AuthFailed(_))));
+}
+
+#[tokio::test]
+async fn test_api_requests_are_not_redirected() {
+    let (route, backends) = (route(&start_idp().await), backends().await);
+
+    let result = backends
+        .oidc
+        .login(
+            &Method::POST,
+            &"/dashboard/api/save".parse().unwrap(),
+            &route,
+            auth(&route),
+        )
+        .await;
+    assert!(matches!(result, Err(AppError::AuthFailed(_))));
+}
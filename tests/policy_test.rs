@@ -0,0 +1,132 @@
+use axum::response::IntoResponse;
+use http::{HeaderMap, HeaderValue, Method, StatusCode, Uri};
+use rustway::{
+    config::AuthConfig,
+    errors::AppError,
+    features::auth::{
+        auth::Claims,
+        policy::{PolicyContext, evaluate},
+    },
+};
+use serde_json::json;
+
+fn auth(policies: &str) -> AuthConfig {
+    serde_yaml::from_str(&format!("type: Jwt\npolicies:\n{}", policies)).unwrap()
+}
+
+fn claims() -> Claims {
+    serde_json::from_value(json!({
+        "sub": "alice",
+        "roles": ["user"],
+        "exp": 0,
+        "tenant": "acme",
+        "org": { "regions": ["eu", "us"] }
+    }))
+    .unwrap()
+}
+
+fn check(
+    auth: &AuthConfig,
+    method: Method,
+    uri: &str,
+    headers: &HeaderMap,
+) -> Result<(), AppError> {
+    let uri: Uri = uri.parse().unwrap();
+    evaluate(
+        &auth.policies,
+        &PolicyContext {
+            method: &method,
+            uri: &uri,
+            headers,
+            client_ip: Some("10.1.2.3".parse().unwrap()),
+            claims: &claims(),
+        },
+    )
+}
+
+#[test]
+fn test_path_capture_must_match_subject() {
+    let auth = auth(
+        "  - name: \"own-orders\"\n    methods: [\"GET\"]\n    path: \"/api/orders/user/{user_id}\"\n    require: [\"path.user_id == claims.sub\"]\n",
+    );
+    let headers = HeaderMap::new();
+
+    assert!(check(&auth, Method::GET, "/api/orders/user/alice", &headers).is_ok());
+    let error = check(&auth, Method::GET, "/api/orders/user/bob", &headers).unwrap_err();
+    assert!(
+        matches!(&error, AppError::InsufficientPermissions(reason) if reason.contains("own-orders"))
+    );
+    assert_eq!(error.into_response().status(), StatusCode::FORBIDDEN);
+
+    // Rules only apply to requests they match
+    assert!(check(&auth, Method::DELETE, "/api/orders/user/bob", &headers).is_ok());
+    assert!(check(&auth, Method::GET, "/api/orders/42", &headers).is_ok());
+}
+
+#[test]
+fn test_rules_see_through_path_spellings_and_head() {
+    let auth = auth(
+        "  - name: \"own-orders\"\n    methods: [\"GET\"]\n    path: \"/api/orders/user/{user_id}\"\n    require: [\"path.user_id == claims.sub\"]\n",
+    );
+    let headers = HeaderMap::new();
+
+    for path in [
+        "/api//orders/user/bob",
+        "/api/./orders/user/bob",
+        "/api/%6Frders/user/bob",
+        "/api/orders/x/../user/bob/",
+        "/api/orders/user/%62ob",
+    ] {
+        assert!(
+            check(&auth, Method::GET, path, &headers).is_err(),
+            "{} slipped past the rule",
+            path
+        );
+    }
+    assert!(check(&auth, Method::GET, "/api/orders/user/%61lice", &headers).is_ok());
+
+    // An encoded slash can't be matched reliably, so it is refused
+    assert!(check(&auth, Method::GET, "/api/orders/user%2Fbob", &headers).is_err());
+
+    assert!(check(&auth, Method::HEAD, "/api/orders/user/bob", &headers).is_err());
+    assert!(check(&auth, Method::HEAD, "/api/orders/user/alice", &headers).is_ok());
+}
+
+#[test]
+fn test_header_query_and_ip_conditions() {
+    let auth = auth(
+        "  - name: \"tenant\"\n    require:\n      - \"header.X-Tenant == claims.tenant\"\n      - \"query.region in claims.org.regions\"\n      - \"client_ip in '10.0.0.0/8'\"\n      - \"method != 'DELETE'\"\n",
+    );
+    let mut headers = HeaderMap::new();
+    headers.insert("x-tenant", HeaderValue::from_static("acme"));
+
+    assert!(check(&auth, Method::GET, "/reports?region=eu", &headers).is_ok());
+    assert!(check(&auth, Method::GET, "/reports?region=apac", &headers).is_err());
+    assert!(check(&auth, Method::DELETE, "/reports?region=eu", &headers).is_err());
+    // Missing attributes never satisfy a condition
+    assert!(check(&auth, Method::GET, "/reports", &headers).is_err());
+    assert!(check(&auth, Method::GET, "/reports?region=eu", &HeaderMap::new()).is_err());
+}
+
+#[test]
+fn test_literals_and_lists() {
+    let auth = auth(
+        "  - name: \"literals\"\n    require: [\"claims.sub in ['alice', 'carol']\", \"'a == b' != claims.tenant\"]\n",
+    );
+    assert!(check(&auth, Method::GET, "/", &HeaderMap::new()).is_ok());
+}
+
+#[test]
+fn test_invalid_conditions_are_rejected() {
+    for condition in [
+        "claims.sub",
+        "body.id == claims.sub",
+        "claims.sub in [admin]",
+    ] {
+        let result: Result<AuthConfig, _> = serde_yaml::from_str(&format!(
+            "type: Jwt\npolicies:\n  - name: \"bad\"\n    require: [\"{}\"]\n",
+            condition
+        ));
+        assert!(result.is_err(), "{} should not parse", condition);
+    }
+}
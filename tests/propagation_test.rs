@@ -0,0 +1,99 @@
+use http::{HeaderMap, HeaderValue};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode};
+use rustway::{
+    config::IdentityPropagationConfig,
+    features::auth::{
+        auth::Claims,
+        propagation::{IdentityPropagator, strip_identity_headers},
+    },
+};
+use serde_json::{Value, json};
+
+const SECRET_ENV: &str = "PROPAGATION_TEST_IDENTITY_SECRET";
+
+fn config(extra: &str) -> IdentityPropagationConfig {
+    serde_yaml::from_str(&format!(
+        "subject_header: \"X-User-Id\"\nclaims:\n  X-Tenant: \"/tenant\"\n  X-Org-Level: \"/org/level\"\n{}",
+        extra
+    ))
+    .unwrap()
+}
+
+fn claims() -> Claims {
+    serde_json::from_value(json!({
+        "sub": "alice",
+        "roles": ["reader", "billing"],
+        "exp": 0,
+        "tenant": "acme",
+        "org": { "level": 3 }
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_identity_headers_replace_client_values() {
+    let config = config("");
+    let mut headers = HeaderMap::new();
+    headers.insert("x-user-id", HeaderValue::from_static("admin"));
+    headers.insert("x-user-roles", HeaderValue::from_static("admin"));
+    headers.insert("x-tenant", HeaderValue::from_static("other"));
+    headers.insert("accept", HeaderValue::from_static("*/*"));
+
+    strip_identity_headers(&mut headers, &config);
+    assert!(!headers.contains_key("x-user-id"));
+    assert!(!headers.contains_key("x-tenant"));
+    assert!(headers.contains_key("accept"));
+
+    IdentityPropagator::new()
+        .apply(&mut headers, &claims(), &config)
+        .await
+        .unwrap();
+    assert_eq!(headers["x-user-id"], "alice");
+    assert_eq!(headers["x-user-roles"], "reader,billing");
+    assert_eq!(headers["x-tenant"], "acme");
+    assert_eq!(headers["x-org-level"], "3");
+}
+
+#[tokio::test]
+async fn test_internal_jwt_is_minted() {
+    unsafe { std::env::set_var(SECRET_ENV, "gateway-secret") };
+    let config = config(&format!(
+        "internal_jwt:\n  secret_env: \"{}\"\n  audience: \"orders\"\n  ttl: \"30s\"\n",
+        SECRET_ENV
+    ));
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-gateway-identity", HeaderValue::from_static("forged"));
+    strip_identity_headers(&mut headers, &config);
+    assert!(!headers.contains_key("x-gateway-identity"));
+
+    IdentityPropagator::new()
+        .apply(&mut headers, &claims(), &config)
+        .await
+        .unwrap();
+    let token = headers["x-gateway-identity"].to_str().unwrap();
+
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_audience(&["orders"]);
+    validation.set_issuer(&["rustygw"]);
+    let decoded = decode::<Value>(
+        token,
+        &DecodingKey::from_secret(b"gateway-secret"),
+        &validation,
+    )
+    .unwrap()
+    .claims;
+    assert_eq!(decoded["sub"], "alice");
+    assert_eq!(decoded["tenant"], "acme");
+    let lifetime = decoded["exp"].as_u64().unwrap() - decoded["iat"].as_u64().unwrap();
+    assert_eq!(lifetime, 30);
+}
+
+#[tokio::test]
+async fn test_missing_signing_key_fails_closed() {
+    let config = config("internal_jwt:\n  secret_env: \"PROPAGATION_TEST_UNSET_SECRET\"\n");
+    let result = IdentityPropagator::new()
+        .apply(&mut HeaderMap::new(), &claims(), &config)
+        .await;
+    assert!(result.is_err());
+}
@@ -0,0 +1,78 @@
+use http::{HeaderMap, HeaderValue};
+use rustway::features::proxy::headers::{apply_host_policy, strip_hop_by_hop};
+
+#[test]
+fn test_hop_by_hop_headers_are_stripped() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "connection",
+        HeaderValue::from_static("keep-alive, X-Trace"),
+    );
+    headers.insert("keep-alive", HeaderValue::from_static("timeout=5"));
+    headers.insert("transfer-encoding", HeaderValue::from_static("chunked"));
+    headers.insert("te", HeaderValue::from_static("trailers"));
+    headers.insert("upgrade", HeaderValue::from_static("websocket"));
+    headers.insert("x-trace", HeaderValue::from_static("abc"));
+    headers.insert("content-type", HeaderValue::from_static("application/json"));
+
+    strip_hop_by_hop(&mut headers);
+
+    assert_eq!(headers.len(), 1);
+    assert_eq!(headers["content-type"], "application/json");
+}
+
+#[test]
+fn test_host_policy() {
+    let mut headers = HeaderMap::new();
+    headers.insert("host", HeaderValue::from_static("gateway.example.com"));
+
+    apply_host_policy(&mut headers, true);
+    assert_eq!(headers["host"], "gateway.example.com");
+
+    apply_host_policy(&mut headers, false);
+    assert!(!headers.contains_key("host"));
+}
+
+#[tokio::test]
+async fn test_connection_header_cannot_remove_gateway_headers() {
+    use axum::{
+        Router,
+        body::Body,
+        extract::Request,
+        middleware::{Next, from_fn},
+        routing::get,
+    };
+    use rustway::middleware::hop_by_hop::hop_by_hop::layer;
+    use tower::ServiceExt;
+
+    // Stands in for auth adding identity headers after the strip
+    async fn identify(mut req: Request, next: Next) -> axum::response::Response {
+        req.headers_mut()
+            .insert("x-user-id", HeaderValue::from_static("alice"));
+        next.run(req).await
+    }
+    async fn echo(headers: HeaderMap) -> String {
+        format!(
+            "{:?} {:?} {:?}",
+            headers.get("x-user-id"),
+            headers.get("x-forwarded-for"),
+            headers.get("connection")
+        )
+    }
+
+    let app = Router::new()
+        .route("/", get(echo))
+        .layer(from_fn(identify))
+        .layer(from_fn(layer));
+    let request = http::Request::get("/")
+        .header("connection", "x-user-id, x-forwarded-for")
+        .header("x-forwarded-for", "203.0.113.7")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(body, r#"Some("alice") None None"#);
+}
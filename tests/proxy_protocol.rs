@@ -0,0 +1,67 @@
+use std::net::SocketAddr;
+
+use rustway::features::proxy_protocol::proxy_protocol::{ProxyProtocolError, read_header};
+use tokio::io::AsyncReadExt;
+
+#[tokio::test]
+async fn test_v1_tcp4_header() {
+    let mut stream: &[u8] = b"PROXY TCP4 203.0.113.7 10.0.0.1 56324 8094\r\nGET / HTTP/1.1\r\n";
+
+    let client = read_header(&mut stream).await.unwrap();
+    assert_eq!(client, Some("203.0.113.7:56324".parse().unwrap()));
+
+    // Only the header is consumed, the HTTP request is left intact.
+    let mut rest = String::new();
+    stream.read_to_string(&mut rest).await.unwrap();
+    assert_eq!(rest, "GET / HTTP/1.1\r\n");
+}
+
+#[tokio::test]
+async fn test_v1_tcp6_and_unknown_headers() {
+    let mut stream: &[u8] = b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 443\r\n";
+    let client = read_header(&mut stream).await.unwrap();
+    assert_eq!(client, Some("[2001:db8::1]:4000".parse().unwrap()));
+
+    let mut stream: &[u8] = b"PROXY UNKNOWN\r\n";
+    assert_eq!(read_header(&mut stream).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_v2_ipv4_header() {
+    let mut header = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+    header.extend_from_slice(&[0x21, 0x11, 0x00, 0x0C]);
+    header.extend_from_slice(&[198, 51, 100, 9, 10, 0, 0, 1]);
+    header.extend_from_slice(&4321u16.to_be_bytes());
+    header.extend_from_slice(&8094u16.to_be_bytes());
+    header.extend_from_slice(b"payload");
+
+    let mut stream: &[u8] = &header;
+    let client = read_header(&mut stream).await.unwrap();
+    let expected: SocketAddr = "198.51.100.9:4321".parse().unwrap();
+    assert_eq!(client, Some(expected));
+    assert_eq!(stream, b"payload");
+}
+
+#[tokio::test]
+async fn test_v2_local_command_keeps_peer_address() {
+    let mut header = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+    header.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]);
+
+    let mut stream: &[u8] = &header;
+    assert_eq!(read_header(&mut stream).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_missing_or_malformed_header_is_rejected() {
+    let mut stream: &[u8] = b"GET / HTTP/1.1\r\nHost: example\r\n";
+    assert!(matches!(
+        read_header(&mut stream).await,
+        Err(ProxyProtocolError::MissingHeader)
+    ));
+
+    let mut stream: &[u8] = b"PROXY TCP4 not-an-ip 10.0.0.1 1 2\r\n";
+    assert!(matches!(
+        read_header(&mut stream).await,
+        Err(ProxyProtocolError::Malformed(_))
+    ));
+}
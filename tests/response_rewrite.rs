@@ -0,0 +1,94 @@
+use http::{HeaderMap, HeaderValue};
+use rustway::{config::RouteConfig, features::proxy::rewrite::rewrite_response_headers};
+
+fn route(rewrite: &str) -> RouteConfig {
+    serde_yaml::from_str(&format!(
+        r#"
+name: "users"
+path: "/api/users"
+destination: "http://127.0.0.1:8091/users"
+response_rewrite:
+{}
+"#,
+        rewrite
+    ))
+    .unwrap()
+}
+
+#[test]
+fn test_relative_and_absolute_redirects_are_rewritten() {
+    let route = route("  redirects: true");
+
+    let mut headers = HeaderMap::new();
+    headers.insert("location", HeaderValue::from_static("/users/login?next=1"));
+    rewrite_response_headers(&mut headers, &route);
+    assert_eq!(headers["location"], "/api/users/login?next=1");
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "location",
+        HeaderValue::from_static("http://127.0.0.1:8091/users"),
+    );
+    headers.insert(
+        "content-location",
+        HeaderValue::from_static("http://127.0.0.1:8091/users/42"),
+    );
+    rewrite_response_headers(&mut headers, &route);
+    assert_eq!(headers["location"], "/api/users");
+    assert_eq!(headers["content-location"], "/api/users/42");
+}
+
+#[test]
+fn test_foreign_redirects_are_left_alone() {
+    let route = route("  redirects: true");
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "location",
+        HeaderValue::from_static("https://idp.example.com/users/login"),
+    );
+    rewrite_response_headers(&mut headers, &route);
+    assert_eq!(headers["location"], "https://idp.example.com/users/login");
+
+    let mut headers = HeaderMap::new();
+    headers.insert("location", HeaderValue::from_static("/usersettings"));
+    rewrite_response_headers(&mut headers, &route);
+    assert_eq!(headers["location"], "/usersettings");
+}
+
+#[test]
+fn test_set_cookie_path_and_domain_are_rewritten() {
+    let route = route("  cookie_domain: \"api.example.com\"");
+
+    let mut headers = HeaderMap::new();
+    headers.append(
+        "set-cookie",
+        HeaderValue::from_static("sid=abc; Path=/users; Domain=127.0.0.1; HttpOnly"),
+    );
+    headers.append("set-cookie", HeaderValue::from_static("theme=dark; Path=/"));
+    rewrite_response_headers(&mut headers, &route);
+
+    let cookies: Vec<_> = headers.get_all("set-cookie").iter().collect();
+    assert_eq!(
+        cookies[0],
+        "sid=abc; Path=/api/users; Domain=api.example.com; HttpOnly"
+    );
+    assert_eq!(cookies[1], "theme=dark; Path=/");
+}
+
+#[test]
+fn test_routes_without_rewrite_config_are_untouched() {
+    let route: RouteConfig = serde_yaml::from_str(
+        r#"
+name: "users"
+path: "/api/users"
+destination: "http://127.0.0.1:8091/users"
+"#,
+    )
+    .unwrap();
+
+    let mut headers = HeaderMap::new();
+    headers.insert("location", HeaderValue::from_static("/users/login"));
+    rewrite_response_headers(&mut headers, &route);
+    assert_eq!(headers["location"], "/users/login");
+}
@@ -0,0 +1,224 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{TimeZone, Utc};
+use rustway::features::auth::{
+    auth::Claims,
+    revocation::{RevocationList, Revocations},
+};
+use serde_json::json;
+use tokio::sync::RwLock;
+
+fn claims(sub: &str, jti: &str, iat: Option<i64>) -> Claims {
+    let mut claims = json!({ "sub": sub, "roles": [], "exp": 0, "jti": jti });
+    if let Some(iat) = iat {
+        claims["iat"] = iat.into();
+    }
+    serde_json::from_value(claims).unwrap()
+}
+
+const RUNTIME_TTL: Duration = Duration::from_secs(86400);
+
+fn cutoff() -> i64 {
+    Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 0)
+        .unwrap()
+        .timestamp()
+}
+
+#[tokio::test]
+async fn test_file_entries_revoke_tokens_and_subjects() {
+    let path = std::env::temp_dir().join(format!("rustygw-{}-revoked.yaml", std::process::id()));
+    std::fs::write(
+        &path,
+        "jti: [\"stolen\"]\nsubjects:\n  bob: \"2025-06-01T00:00:00Z\"\n",
+    )
+    .unwrap();
+    let list = Arc::new(RwLock::new(RevocationList::load(&path).unwrap()));
+    let revocations = Revocations::new(list.clone(), RUNTIME_TTL);
+
+    assert!(
+        revocations
+            .check(&claims("alice", "stolen", None))
+            .await
+            .is_err()
+    );
+    assert!(
+        revocations
+            .check(&claims("alice", "fine", None))
+            .await
+            .is_ok()
+    );
+
+    // Only bob's tokens issued by the cutoff are revoked
+    assert!(
+        revocations
+            .check(&claims("bob", "a", Some(cutoff() - 1)))
+            .await
+            .is_err()
+    );
+    assert!(
+        revocations
+            .check(&claims("bob", "d", Some(cutoff())))
+            .await
+            .is_err()
+    );
+    assert!(revocations.check(&claims("bob", "b", None)).await.is_err());
+    assert!(
+        revocations
+            .check(&claims("bob", "c", Some(cutoff() + 1)))
+            .await
+            .is_ok()
+    );
+
+    // A reloaded list replaces the file entries
+    *list.write().await = RevocationList::default();
+    assert!(
+        revocations
+            .check(&claims("alice", "stolen", None))
+            .await
+            .is_ok()
+    );
+}
+
+#[tokio::test]
+async fn test_runtime_entries_survive_reloads() {
+    let list = Arc::new(RwLock::new(RevocationList::default()));
+    let revocations = Revocations::new(list.clone(), RUNTIME_TTL);
+    // Recent enough for tokens issued by it to be still valid
+    let issued_before = Utc::now();
+    let cutoff = issued_before.timestamp();
+
+    revocations
+        .revoke_token("leaked".to_string())
+        .await
+        .unwrap();
+    revocations
+        .revoke_subject("carol".to_string(), issued_before)
+        .await
+        .unwrap();
+    *list.write().await = RevocationList::default();
+
+    assert!(
+        revocations
+            .check(&claims("alice", "leaked", None))
+            .await
+            .is_err()
+    );
+    assert!(
+        revocations
+            .check(&claims("carol", "x", Some(cutoff - 60)))
+            .await
+            .is_err()
+    );
+    assert!(
+        revocations
+            .check(&claims("carol", "y", Some(cutoff)))
+            .await
+            .is_err()
+    );
+    assert!(
+        revocations
+            .check(&claims("carol", "z", Some(cutoff + 1)))
+            .await
+            .is_ok()
+    );
+}
+
+#[tokio::test]
+async fn test_runtime_entries_expire_with_the_tokens_they_cover() {
+    let revocations = Revocations::new(Default::default(), Duration::from_secs(1));
+    let issued_at = Utc::now().timestamp();
+
+    revocations
+        .revoke_token("leaked".to_string())
+        .await
+        .unwrap();
+    revocations
+        .revoke_subject("carol".to_string(), Utc::now())
+        .await
+        .unwrap();
+    // Every token issued by this cutoff expired an hour ago
+    revocations
+        .revoke_subject("dave".to_string(), Utc::now() - chrono::Duration::hours(1))
+        .await
+        .unwrap();
+
+    assert!(
+        revocations
+            .check(&claims("alice", "leaked", Some(issued_at)))
+            .await
+            .is_err()
+    );
+    assert!(
+        revocations
+            .check(&claims("carol", "x", Some(issued_at)))
+            .await
+            .is_err()
+    );
+    assert!(
+        revocations
+            .check(&claims("dave", "x", Some(issued_at - 7200)))
+            .await
+            .is_ok()
+    );
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert!(
+        revocations
+            .check(&claims("alice", "leaked", Some(issued_at)))
+            .await
+            .is_ok()
+    );
+    assert!(
+        revocations
+            .check(&claims("carol", "x", Some(issued_at)))
+            .await
+            .is_ok()
+    );
+}
+
+#[tokio::test]
+async fn test_runtime_entries_are_handed_over_to_a_successor() {
+    let revocations = Revocations::new(Default::default(), RUNTIME_TTL);
+    let issued_before = Utc::now();
+    let cutoff = issued_before.timestamp();
+    revocations
+        .revoke_token("leaked".to_string())
+        .await
+        .unwrap();
+    revocations
+        .revoke_subject("carol".to_string(), issued_before)
+        .await
+        .unwrap();
+
+    let path = revocations.hand_over().await.save_for_successor().unwrap();
+    // Taken after the snapshot, so it would be lost
+    assert!(revocations.revoke_token("late".to_string()).await.is_err());
+
+    let successor = Revocations::new(Default::default(), RUNTIME_TTL);
+    successor
+        .restore(RevocationList::load(&path).unwrap())
+        .await;
+    std::fs::remove_file(&path).unwrap();
+    assert!(
+        successor
+            .check(&claims("alice", "leaked", None))
+            .await
+            .is_err()
+    );
+    assert!(
+        successor
+            .check(&claims("carol", "x", Some(cutoff)))
+            .await
+            .is_err()
+    );
+    assert!(
+        successor
+            .check(&claims("carol", "y", Some(cutoff + 1)))
+            .await
+            .is_ok()
+    );
+
+    // A failed hand over takes revocations again
+    revocations.resume().await;
+    assert!(revocations.revoke_token("late".to_string()).await.is_ok());
+}
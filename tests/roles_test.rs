@@ -0,0 +1,61 @@
+use http::Method;
+use rustway::{
+    config::AuthConfig,
+    errors::AppError,
+    features::auth::auth::{check_roles, check_route_roles},
+};
+
+fn auth(yaml: &str) -> AuthConfig {
+    serde_yaml::from_str(&format!("type: Jwt\n{}", yaml)).unwrap()
+}
+
+fn roles(roles: &[&str]) -> Vec<String> {
+    roles.iter().map(|role| role.to_string()).collect()
+}
+
+#[test]
+fn test_plain_list_requires_every_role() {
+    let auth = auth("roles: [\"admin\", \"user\"]");
+    let required = auth.roles.as_ref().unwrap();
+
+    assert!(check_roles(&roles(&["user", "admin"]), required).is_ok());
+    assert!(matches!(
+        check_roles(&roles(&["admin"]), required),
+        Err(AppError::InsufficientPermissions(_))
+    ));
+}
+
+#[test]
+fn test_nested_blocks() {
+    let auth = auth(
+        "roles:\n  any_of: [\"admin\", { all_of: [\"support\", \"billing\"] }]\n  none_of: [\"suspended\"]\n",
+    );
+    let required = auth.roles.as_ref().unwrap();
+
+    assert!(check_roles(&roles(&["admin"]), required).is_ok());
+    assert!(check_roles(&roles(&["support", "billing"]), required).is_ok());
+    assert!(check_roles(&roles(&["support"]), required).is_err());
+    assert!(check_roles(&roles(&["admin", "suspended"]), required).is_err());
+    assert!(check_roles(&[], required).is_err());
+}
+
+#[test]
+fn test_method_specific_roles() {
+    let auth = auth(
+        "roles: { none_of: [\"banned\"] }\nmethod_roles:\n  GET: \"reader\"\n  delete: [\"admin\"]\n",
+    );
+
+    assert!(check_route_roles(&roles(&["reader"]), &auth, &Method::GET).is_ok());
+    assert!(check_route_roles(&roles(&["reader"]), &auth, &Method::DELETE).is_err());
+    assert!(check_route_roles(&roles(&["admin"]), &auth, &Method::DELETE).is_ok());
+    // Methods without an entry only need the route-wide requirement
+    assert!(check_route_roles(&[], &auth, &Method::POST).is_ok());
+    assert!(check_route_roles(&roles(&["reader", "banned"]), &auth, &Method::GET).is_err());
+}
+
+#[test]
+fn test_unknown_block_is_rejected() {
+    let result: Result<AuthConfig, _> =
+        serde_yaml::from_str("type: Jwt\nroles: { one_of: [\"a\"] }");
+    assert!(result.is_err());
+}
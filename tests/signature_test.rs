@@ -0,0 +1,194 @@
+mod common;
+
+use std::collections::HashMap;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use common::now;
+use http::{HeaderMap, HeaderValue, Method, Uri};
+use rustway::{
+    config::{ApiKeyDetails, ApiKeyStore, KeyStatus, SignatureAlgorithm, SignatureConfig},
+    errors::AppError,
+    features::auth::{
+        key_store::YamlKeyStore,
+        signature::{SignatureVerifier, sign},
+    },
+};
+
+const BODY: &[u8] = br#"{"action":"opened"}"#;
+
+fn key_store(status: KeyStatus) -> YamlKeyStore {
+    let entry = ApiKeyDetails {
+        user_id: "github".to_string(),
+        roles: vec!["webhook".to_string()],
+        status,
+        signing_secret: Some("whsec".to_string()),
+        ..Default::default()
+    };
+    YamlKeyStore::from(ApiKeyStore {
+        keys: HashMap::from([("github".to_string(), entry)]),
+        hmac_secret: None,
+    })
+}
+
+fn config(yaml: &str) -> SignatureConfig {
+    serde_yaml::from_str(yaml).unwrap()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn headers(pairs: &[(&'static str, String)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+        headers.insert(*name, HeaderValue::from_str(value).unwrap());
+    }
+    headers
+}
+
+async fn verify(
+    verifier: &SignatureVerifier,
+    headers: &HeaderMap,
+    config: &SignatureConfig,
+    store: &YamlKeyStore,
+) -> Result<(), AppError> {
+    let uri = Uri::from_static("/hooks/github?delivery=1");
+    verifier
+        .verify(&Method::POST, &uri, headers, BODY, config, store)
+        .await
+        .map(|claims| assert_eq!(claims.sub, "github"))
+}
+
+#[tokio::test]
+async fn test_github_style_signature() {
+    let config =
+        config("header: \"X-Hub-Signature-256\"\nprefix: \"sha256=\"\nkey_id: \"github\"\n");
+    let verifier = SignatureVerifier::new();
+    let store = key_store(KeyStatus::Active);
+    let signature = hex(&sign(SignatureAlgorithm::HmacSha256, b"whsec", BODY));
+
+    let valid = headers(&[("x-hub-signature-256", format!("sha256={}", signature))]);
+    assert!(verify(&verifier, &valid, &config, &store).await.is_ok());
+    // Without a timestamp or nonce, redeliveries are accepted
+    assert!(verify(&verifier, &valid, &config, &store).await.is_ok());
+
+    let tampered = hex(&sign(SignatureAlgorithm::HmacSha256, b"whsec", b"{}"));
+    let invalid = headers(&[("x-hub-signature-256", format!("sha256={}", tampered))]);
+    assert!(matches!(
+        verify(&verifier, &invalid, &config, &store).await,
+        Err(AppError::AuthFailed(_))
+    ));
+    assert!(
+        verify(&verifier, &HeaderMap::new(), &config, &store)
+            .await
+            .is_err()
+    );
+
+    let revoked = key_store(KeyStatus::Revoked);
+    assert!(matches!(
+        verify(&verifier, &valid, &config, &revoked).await,
+        Err(AppError::ApiKeyRevoked)
+    ));
+}
+
+#[tokio::test]
+async fn test_stripe_style_timestamp_and_replay() {
+    let config = config("format: Stripe\nheader: \"Stripe-Signature\"\nkey_id: \"github\"\n");
+    let verifier = SignatureVerifier::new();
+    let store = key_store(KeyStatus::Active);
+    let stripe = |timestamp: u64| {
+        let message = [timestamp.to_string().as_bytes(), b".", BODY].concat();
+        let signature = hex(&sign(SignatureAlgorithm::HmacSha256, b"whsec", &message));
+        headers(&[(
+            "stripe-signature",
+            format!("t={},v1=deadbeef,v1={}", timestamp, signature),
+        )])
+    };
+
+    let fresh = stripe(now());
+    assert!(verify(&verifier, &fresh, &config, &store).await.is_ok());
+    assert!(matches!(
+        verify(&verifier, &fresh, &config, &store).await,
+        Err(AppError::AuthFailed(reason)) if reason.contains("Replayed")
+    ));
+
+    let stale = stripe(now() - 3600);
+    assert!(verify(&verifier, &stale, &config, &store).await.is_err());
+}
+
+#[tokio::test]
+async fn test_custom_canonical_string_with_nonce() {
+    let config = config(
+        "header: \"X-Signature\"\nalgorithm: HmacSha512\nencoding: Base64\nkey_id_header: \"X-Key-Id\"\ntimestamp_header: \"X-Timestamp\"\nnonce_header: \"X-Nonce\"\ncanonical: [Timestamp, Nonce, Method, Path, Body]\nseparator: \"\\n\"\n",
+    );
+    let verifier = SignatureVerifier::new();
+    let store = key_store(KeyStatus::Active);
+    let signed = |nonce: &str| {
+        let timestamp = now().to_string();
+        let message = [
+            timestamp.as_bytes(),
+            b"\n",
+            nonce.as_bytes(),
+            b"\nPOST\n/hooks/github?delivery=1\n",
+            BODY,
+        ]
+        .concat();
+        let signature = STANDARD.encode(sign(SignatureAlgorithm::HmacSha512, b"whsec", &message));
+        headers(&[
+            ("x-signature", signature),
+            ("x-key-id", "github".to_string()),
+            ("x-timestamp", timestamp),
+            ("x-nonce", nonce.to_string()),
+        ])
+    };
+
+    assert!(
+        verify(&verifier, &signed("n-1"), &config, &store)
+            .await
+            .is_ok()
+    );
+    assert!(
+        verify(&verifier, &signed("n-2"), &config, &store)
+            .await
+            .is_ok()
+    );
+    assert!(
+        verify(&verifier, &signed("n-1"), &config, &store)
+            .await
+            .is_err()
+    );
+
+    let mut unknown_key = signed("n-3");
+    unknown_key.insert("x-key-id", HeaderValue::from_static("gitlab"));
+    assert!(
+        verify(&verifier, &unknown_key, &config, &store)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_checked_headers_must_be_signed() {
+    // The timestamp would be checked but not signed, so a captured body
+    // could be replayed under any fresh timestamp
+    let unsigned =
+        config("header: \"X-Signature\"\nkey_id: \"github\"\ntimestamp_header: \"X-Timestamp\"\n");
+    assert_eq!(unsigned.unsigned_header(), Some("X-Timestamp"));
+
+    let verifier = SignatureVerifier::new();
+    let store = key_store(KeyStatus::Active);
+    let signature = hex(&sign(SignatureAlgorithm::HmacSha256, b"whsec", BODY));
+    let request = headers(&[
+        ("x-signature", signature),
+        ("x-timestamp", now().to_string()),
+    ]);
+    assert!(matches!(
+        verify(&verifier, &request, &unsigned, &store).await,
+        Err(AppError::InternalServerError)
+    ));
+
+    let signed = config(
+        "header: \"X-Signature\"\nkey_id: \"github\"\ntimestamp_header: \"X-Timestamp\"\ncanonical: [Timestamp, Body]\n",
+    );
+    assert_eq!(signed.unsigned_header(), None);
+}
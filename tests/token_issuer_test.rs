@@ -0,0 +1,257 @@
+mod common;
+
+use std::collections::HashMap;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use common::secrets;
+use http::{HeaderMap, HeaderValue};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode};
+use rustway::{
+    config::{ApiKeyDetails, ApiKeyStore, TokenIssuerConfig},
+    errors::AppError,
+    features::auth::{
+        api_key::generate_key,
+        key_store::{KeyStore, YamlKeyStore},
+        token_issuer::{TokenIssuer, TokenRequest, TokenResponse},
+    },
+};
+use serde_json::{Value, json};
+
+const SECRET: &str = "issuer-test-secret";
+
+fn config(yaml: &str) -> TokenIssuerConfig {
+    serde_yaml::from_str(yaml).unwrap()
+}
+
+// Returns the store and the full key handed to the client
+fn key_store(details: ApiKeyDetails) -> (YamlKeyStore, String) {
+    let generated = generate_key(None);
+    let entry = ApiKeyDetails {
+        hash: Some(generated.hash),
+        ..details
+    };
+    let store = ApiKeyStore {
+        keys: HashMap::from([(generated.id, entry)]),
+        hmac_secret: None,
+    };
+    (YamlKeyStore::from(store), generated.key)
+}
+
+fn mobile_key() -> ApiKeyDetails {
+    ApiKeyDetails {
+        user_id: "mobile@example.com".to_string(),
+        roles: vec!["user".to_string()],
+        ..Default::default()
+    }
+}
+
+fn request(body: Value) -> TokenRequest {
+    serde_json::from_value(body).unwrap()
+}
+
+async fn issue(
+    issuer: &TokenIssuer,
+    body: Value,
+    headers: &HeaderMap,
+    store: &YamlKeyStore,
+    config: &TokenIssuerConfig,
+) -> Result<TokenResponse, AppError> {
+    issuer
+        .issue(
+            request(body),
+            headers,
+            store,
+            config,
+            &secrets(Some(SECRET)),
+        )
+        .await
+}
+
+fn decode_claims(token: &str) -> Value {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_audience(&["mobile-api"]);
+    decode::<Value>(
+        token,
+        &DecodingKey::from_secret(SECRET.as_bytes()),
+        &validation,
+    )
+    .unwrap()
+    .claims
+}
+
+#[tokio::test]
+async fn test_api_key_is_exchanged_for_jwt() {
+    let (store, key) = key_store(mobile_key());
+    let config = config("audience: \"mobile-api\"\naccess_token_ttl: \"5m\"\n");
+    let response = issue(
+        &TokenIssuer::new(),
+        json!({ "grant_type": "api_key", "api_key": key }),
+        &HeaderMap::new(),
+        &store,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.token_type, "Bearer");
+    assert_eq!(response.expires_in, 300);
+    assert!(response.refresh_token.is_some());
+
+    let claims = decode_claims(&response.access_token);
+    assert_eq!(claims["sub"], "mobile@example.com");
+    assert_eq!(claims["roles"], json!(["user"]));
+    assert_eq!(claims["iss"], "rustygw");
+    assert_eq!(claims["client_id"], key.split_once('.').unwrap().0);
+    assert!(claims["jti"].is_string());
+}
+
+#[tokio::test]
+async fn test_client_credentials_and_refresh_rotation() {
+    let (store, key) = key_store(mobile_key());
+    let config = config("audience: \"mobile-api\"\n");
+    let issuer = TokenIssuer::new();
+    let (client_id, client_secret) = key.split_once('.').unwrap();
+
+    let mut headers = HeaderMap::new();
+    let basic = STANDARD.encode(format!("{}:{}", client_id, client_secret));
+    headers.insert(
+        "authorization",
+        HeaderValue::from_str(&format!("Basic {}", basic)).unwrap(),
+    );
+    let first = issue(
+        &issuer,
+        json!({ "grant_type": "client_credentials" }),
+        &headers,
+        &store,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    let refresh = json!({ "grant_type": "refresh_token", "refresh_token": first.refresh_token });
+    let second = issue(&issuer, refresh.clone(), &HeaderMap::new(), &store, &config)
+        .await
+        .unwrap();
+    assert_eq!(
+        decode_claims(&second.access_token)["sub"],
+        "mobile@example.com"
+    );
+    assert_ne!(second.refresh_token, first.refresh_token);
+
+    // Refresh tokens are single use
+    assert!(matches!(
+        issue(&issuer, refresh, &HeaderMap::new(), &store, &config).await,
+        Err(AppError::AuthFailed(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_revoked_key_cannot_refresh() {
+    let (store, key) = key_store(mobile_key());
+    let config = config("");
+    let issuer = TokenIssuer::new();
+    let response = issue(
+        &issuer,
+        json!({ "grant_type": "api_key", "api_key": key }),
+        &HeaderMap::new(),
+        &store,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    let (key_id, _) = key.split_once('.').unwrap();
+    assert!(store.revoke(key_id).await.unwrap());
+    let refresh = json!({ "grant_type": "refresh_token", "refresh_token": response.refresh_token });
+    assert!(matches!(
+        issue(&issuer, refresh, &HeaderMap::new(), &store, &config).await,
+        Err(AppError::ApiKeyRevoked)
+    ));
+}
+
+#[tokio::test]
+async fn test_restricted_keys_and_bad_requests_are_rejected() {
+    let (store, key) = key_store(ApiKeyDetails {
+        allowed_methods: vec!["GET".to_string()],
+        ..mobile_key()
+    });
+    let config = config("refresh_tokens: false\n");
+    let issuer = TokenIssuer::new();
+
+    assert!(matches!(
+        issue(
+            &issuer,
+            json!({ "grant_type": "api_key", "api_key": key }),
+            &HeaderMap::new(),
+            &store,
+            &config,
+        )
+        .await,
+        Err(AppError::ApiKeyNotAllowed(_))
+    ));
+    assert!(matches!(
+        issue(
+            &issuer,
+            json!({ "grant_type": "refresh_token", "refresh_token": "x" }),
+            &HeaderMap::new(),
+            &store,
+            &config,
+        )
+        .await,
+        Err(AppError::InvalidRequest(_))
+    ));
+    assert!(matches!(
+        issue(
+            &issuer,
+            json!({ "grant_type": "api_key" }),
+            &HeaderMap::new(),
+            &store,
+            &config,
+        )
+        .await,
+        Err(AppError::InvalidRequest(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_rate_limited_keys_are_not_exchanged() {
+    let (store, key) = key_store(ApiKeyDetails {
+        rate_limit: Some(serde_yaml::from_str("{ requests: 10, period: \"1m\" }").unwrap()),
+        ..mobile_key()
+    });
+
+    assert!(matches!(
+        issue(
+            &TokenIssuer::new(),
+            json!({ "grant_type": "api_key", "api_key": key }),
+            &HeaderMap::new(),
+            &store,
+            &config(""),
+        )
+        .await,
+        Err(AppError::ApiKeyNotAllowed(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_tokens_expire_with_the_key() {
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(120);
+    let (store, key) = key_store(ApiKeyDetails {
+        expires_at: Some(expires_at),
+        ..mobile_key()
+    });
+    let config = config("audience: \"mobile-api\"\naccess_token_ttl: \"15m\"\n");
+
+    let response = issue(
+        &TokenIssuer::new(),
+        json!({ "grant_type": "api_key", "api_key": key }),
+        &HeaderMap::new(),
+        &store,
+        &config,
+    )
+    .await
+    .unwrap();
+    assert!(response.expires_in <= 120);
+    let claims = decode_claims(&response.access_token);
+    assert!(claims["exp"].as_i64().unwrap() <= expires_at.timestamp());
+}